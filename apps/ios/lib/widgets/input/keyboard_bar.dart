@@ -1,6 +1,4 @@
 import 'dart:ui';
-import 'dart:io';
-import 'dart:convert';
 import 'package:flutter/cupertino.dart';
 import 'package:flutter/material.dart';
 import 'package:flutter/services.dart';
@@ -8,23 +6,6 @@ import '../../theme/remote_theme.dart';
 import '../../services/input_service.dart';
 import '../../utils/haptics.dart';
 
-// #region agent log
-void _debugLogKeyboard(String location, String message, Map<String, dynamic> data, String hypothesisId) {
-  try {
-    final logEntry = jsonEncode({
-      'location': location,
-      'message': message,
-      'data': data,
-      'hypothesisId': hypothesisId,
-      'timestamp': DateTime.now().millisecondsSinceEpoch,
-      'sessionId': 'debug-session',
-    });
-    File('/Users/davell/Documents/github/blink/.cursor/debug.log')
-        .writeAsStringSync('$logEntry\n', mode: FileMode.append, flush: true);
-  } catch (_) {}
-}
-// #endregion
-
 /// Floating keyboard trigger and input bar
 class KeyboardBar extends StatefulWidget {
   final InputService inputService;
@@ -66,14 +47,6 @@ class _KeyboardBarState extends State<KeyboardBar> {
   }
 
   void _toggleKeyboard() {
-    // #region agent log
-    _debugLogKeyboard('keyboard_bar.dart:_toggleKeyboard', 'Toggle keyboard called', {
-      'hasFocus': _focusNode.hasFocus,
-      'isExpanded': _isExpanded,
-      'windowId': widget.windowId,
-    }, 'E');
-    // #endregion
-    
     Haptics.tap();
     if (_isExpanded) {
       // Already expanded, close it
@@ -86,12 +59,6 @@ class _KeyboardBarState extends State<KeyboardBar> {
       // Request focus in next frame after TextField is built
       WidgetsBinding.instance.addPostFrameCallback((_) {
         _focusNode.requestFocus();
-        // #region agent log
-        _debugLogKeyboard('keyboard_bar.dart:_toggleKeyboard:postFrame', 'Focus requested after rebuild', {
-          'hasFocus': _focusNode.hasFocus,
-          'isExpanded': _isExpanded,
-        }, 'F');
-        // #endregion
       });
     }
   }
@@ -99,29 +66,8 @@ class _KeyboardBarState extends State<KeyboardBar> {
   String _previousText = '';
   
   void _onTextChanged(String text) {
-    // #region agent log
-    _debugLogKeyboard('keyboard_bar.dart:_onTextChanged', 'Text changed callback', {
-      'text': text,
-      'textLength': text.length,
-      'isEmpty': text.isEmpty,
-      'previousText': _previousText,
-      'previousTextLength': _previousText.length,
-      'windowId': widget.windowId,
-      'inputServiceConnected': widget.inputService.isConnected,
-    }, 'A');
-    // #endregion
-    
     // FIX for Hypothesis A: Detect backspace when text gets shorter
     if (text.length < _previousText.length) {
-      // #region agent log
-      _debugLogKeyboard('keyboard_bar.dart:_onTextChanged:backspaceDetected', 'Backspace detected - sending key', {
-        'text': text,
-        'previousText': _previousText,
-        'deletedCount': _previousText.length - text.length,
-        'windowId': widget.windowId,
-      }, 'A');
-      // #endregion
-      
       // Calculate how many characters were deleted (usually 1 for backspace)
       final deletedCount = _previousText.length - text.length;
       for (var i = 0; i < deletedCount; i++) {
@@ -141,15 +87,6 @@ class _KeyboardBarState extends State<KeyboardBar> {
       // Get the newly added characters
       final newChars = text.substring(_previousText.length);
       
-      // #region agent log
-      _debugLogKeyboard('keyboard_bar.dart:_onTextChanged:newChars', 'New characters detected', {
-        'newChars': newChars,
-        'text': text,
-        'previousText': _previousText,
-        'windowId': widget.windowId,
-      }, 'D');
-      // #endregion
-      
       // Send each new character
       for (final char in newChars.characters) {
         widget.inputService.sendTextInput(
@@ -170,13 +107,6 @@ class _KeyboardBarState extends State<KeyboardBar> {
   }
   
   void _onSubmitted(String value) {
-    // #region agent log
-    _debugLogKeyboard('keyboard_bar.dart:_onSubmitted', 'Done/Submit pressed - sending Enter', {
-      'value': value,
-      'windowId': widget.windowId,
-    }, 'B');
-    // #endregion
-    
     // FIX for Hypothesis B: Send Enter key when Done is pressed
     Haptics.tap();
     widget.inputService.sendKeyPress(
@@ -189,15 +119,6 @@ class _KeyboardBarState extends State<KeyboardBar> {
   }
 
   void _sendSpecialKey(int keyCode, {List<KeyModifier> modifiers = const []}) {
-    // #region agent log
-    _debugLogKeyboard('keyboard_bar.dart:_sendSpecialKey', 'Special key pressed', {
-      'keyCode': keyCode,
-      'modifiers': modifiers.map((m) => m.name).toList(),
-      'windowId': widget.windowId,
-      'inputServiceConnected': widget.inputService.isConnected,
-    }, 'D');
-    // #endregion
-    
     Haptics.tap();
     widget.inputService.sendKeyPress(
       windowId: widget.windowId,
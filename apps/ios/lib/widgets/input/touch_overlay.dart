@@ -1,6 +1,4 @@
 import 'dart:async';
-import 'dart:io';
-import 'dart:convert';
 import 'package:flutter/material.dart';
 import 'package:flutter/services.dart';
 import '../../theme/remote_theme.dart';
@@ -8,23 +6,6 @@ import '../../services/input_service.dart';
 import '../../services/stream_service.dart';
 import '../../utils/haptics.dart';
 
-// #region agent log
-void _debugLog(String location, String message, Map<String, dynamic> data, String hypothesisId) {
-  try {
-    final logEntry = jsonEncode({
-      'location': location,
-      'message': message,
-      'data': data,
-      'hypothesisId': hypothesisId,
-      'timestamp': DateTime.now().millisecondsSinceEpoch,
-      'sessionId': 'debug-session',
-    });
-    File('/Users/davell/Documents/github/blink/.cursor/debug.log')
-        .writeAsStringSync('$logEntry\n', mode: FileMode.append, flush: true);
-  } catch (_) {}
-}
-// #endregion
-
 /// Transparent overlay that captures touch gestures and translates them to input events
 class TouchOverlay extends StatefulWidget {
   final Widget child;
@@ -169,7 +150,6 @@ class _TouchOverlayState extends State<TouchOverlay> {
       (adjustedY / effectiveHeight).clamp(0.0, 1.0),
     );
     
-    // #region agent log
     debugPrint('[CLICK] tap=(${localPosition.dx.toStringAsFixed(1)}, ${localPosition.dy.toStringAsFixed(1)}) '
         'widget=(${size.width.toStringAsFixed(0)}x${size.height.toStringAsFixed(0)}) '
         'scale=$_scale offset=(${_offset.dx.toStringAsFixed(1)}, ${_offset.dy.toStringAsFixed(1)}) '
@@ -177,7 +157,6 @@ class _TouchOverlayState extends State<TouchOverlay> {
         'letterbox=(${letterboxOffsetX.toStringAsFixed(1)}, ${letterboxOffsetY.toStringAsFixed(1)}) '
         'effective=(${effectiveWidth.toStringAsFixed(0)}x${effectiveHeight.toStringAsFixed(0)}) '
         'normalized=(${normalized.dx.toStringAsFixed(4)}, ${normalized.dy.toStringAsFixed(4)})');
-    // #endregion
     
     return normalized;
   }
@@ -252,14 +231,6 @@ class _TouchOverlayState extends State<TouchOverlay> {
   }
 
   void _handleScaleStart(ScaleStartDetails details) {
-    // #region agent log
-    _debugLog('touch_overlay.dart:_handleScaleStart', 'Scale gesture started', {
-      'pointerCount': details.pointerCount,
-      'localFocalPoint': {'dx': details.localFocalPoint.dx, 'dy': details.localFocalPoint.dy},
-      'currentScale': _scale,
-    }, 'A');
-    // #endregion
-    
     _dragStartPosition = details.localFocalPoint;
     _focalPoint = details.localFocalPoint;
     _previousScale = _scale;
@@ -281,16 +252,6 @@ class _TouchOverlayState extends State<TouchOverlay> {
   }
 
   void _handleScaleUpdate(ScaleUpdateDetails details) {
-    // #region agent log
-    _debugLog('touch_overlay.dart:_handleScaleUpdate', 'Scale gesture update', {
-      'pointerCount': details.pointerCount,
-      'scale': details.scale,
-      'previousScale': _previousScale,
-      'currentScale': _scale,
-      'isDragging': _isDragging,
-    }, 'B');
-    // #endregion
-    
     final size = context.size ?? const Size(1, 1);
     final normalized = _normalizePosition(details.localFocalPoint, size);
     
@@ -319,16 +280,6 @@ class _TouchOverlayState extends State<TouchOverlay> {
       // Pinch zoom - actual zoom with viewport update
       final newScale = (_previousScale * details.scale).clamp(1.0, 4.0);
       
-      // #region agent log
-      _debugLog('touch_overlay.dart:_handleScaleUpdate:pinch', 'Pinch zoom detected', {
-        'detailsScale': details.scale,
-        'previousScale': _previousScale,
-        'newScale': newScale,
-        'currentScale': _scale,
-        'focalPoint': {'dx': details.localFocalPoint.dx, 'dy': details.localFocalPoint.dy},
-      }, 'B');
-      // #endregion
-      
       if ((newScale - _scale).abs() > 0.01 || details.localFocalPoint != _dragStartPosition) {
         setState(() {
           final oldScale = _scale;
@@ -355,13 +306,6 @@ class _TouchOverlayState extends State<TouchOverlay> {
           }
         });
         
-        // #region agent log
-        _debugLog('touch_overlay.dart:_handleScaleUpdate:afterSetState', 'Scale updated', {
-          'newScale': _scale,
-          'offset': {'dx': _offset.dx, 'dy': _offset.dy},
-        }, 'C');
-        // #endregion
-        
         _sendViewportUpdate();
       }
     }
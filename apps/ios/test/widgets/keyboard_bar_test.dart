@@ -1,5 +1,3 @@
-import 'dart:convert';
-import 'dart:io';
 import 'package:flutter/cupertino.dart';
 import 'package:flutter/material.dart';
 import 'package:flutter/services.dart';
@@ -7,23 +5,6 @@ import 'package:flutter_test/flutter_test.dart';
 import 'package:blink/widgets/input/keyboard_bar.dart';
 import 'package:blink/services/input_service.dart';
 
-// #region agent log
-void _debugLog(String location, String message, Map<String, dynamic> data, String hypothesisId) {
-  try {
-    final logEntry = jsonEncode({
-      'location': location,
-      'message': message,
-      'data': data,
-      'hypothesisId': hypothesisId,
-      'timestamp': DateTime.now().millisecondsSinceEpoch,
-      'sessionId': 'test-session',
-    });
-    File('/Users/davell/Documents/github/blink/.cursor/debug.log')
-        .writeAsStringSync('$logEntry\n', mode: FileMode.append, flush: true);
-  } catch (_) {}
-}
-// #endregion
-
 /// Mock InputService that captures all sent events for verification
 class MockInputService extends ChangeNotifier implements InputService {
   final List<Map<String, dynamic>> sentEvents = [];
@@ -58,9 +39,6 @@ class MockInputService extends ChangeNotifier implements InputService {
       'modifiers': modifiers.map((m) => m.name).toList(),
     };
     sentEvents.add(event);
-    // #region agent log
-    _debugLog('MockInputService:sendKeyPress', 'Key press sent', event, 'test');
-    // #endregion
   }
 
   @override
@@ -91,9 +69,6 @@ class MockInputService extends ChangeNotifier implements InputService {
       'text': text,
     };
     sentEvents.add(event);
-    // #region agent log
-    _debugLog('MockInputService:sendTextInput', 'Text input sent', event, 'test');
-    // #endregion
   }
 
   @override
@@ -121,10 +96,6 @@ class MockInputService extends ChangeNotifier implements InputService {
 }
 
 void main() {
-  // #region agent log
-  _debugLog('keyboard_bar_test.dart:main', 'Starting keyboard bar tests', {}, 'test');
-  // #endregion
-
   group('KeyboardBar Input Handling', () {
     late MockInputService mockInputService;
 
@@ -133,10 +104,6 @@ void main() {
     });
 
     testWidgets('Hypothesis A: Backspace detection when typing then deleting', (tester) async {
-      // #region agent log
-      _debugLog('test:hypothesis_a', 'Starting backspace detection test', {}, 'A');
-      // #endregion
-
       await tester.pumpWidget(
         CupertinoApp(
           home: CupertinoPageScaffold(
@@ -164,13 +131,6 @@ void main() {
       await tester.enterText(textField, 'a');
       await tester.pump();
 
-      // #region agent log
-      _debugLog('test:hypothesis_a', 'After typing "a"', {
-        'sentEvents': mockInputService.sentEvents.length,
-        'textInputEvents': mockInputService.textInputEvents.length,
-      }, 'A');
-      // #endregion
-
       // The character should have been sent
       final textEventsAfterTyping = mockInputService.textInputEvents.length;
       
@@ -179,26 +139,10 @@ void main() {
       await tester.enterText(textField, '');
       await tester.pump();
 
-      // #region agent log
-      _debugLog('test:hypothesis_a', 'After backspace (empty text)', {
-        'sentEvents': mockInputService.sentEvents.length,
-        'textInputEvents': mockInputService.textInputEvents.length,
-        'keyPressEvents': mockInputService.keyPressEvents.length,
-      }, 'A');
-      // #endregion
-
       // Check if backspace was sent (either as key press with code 51 or text input)
       final backspaceKeySent = mockInputService.keyPressEvents.any((e) => e['keyCode'] == 51);
       final anyEventSent = mockInputService.sentEvents.isNotEmpty;
 
-      // #region agent log
-      _debugLog('test:hypothesis_a', 'Backspace test result', {
-        'backspaceKeySent': backspaceKeySent,
-        'anyEventSent': anyEventSent,
-        'allEvents': mockInputService.sentEvents,
-      }, 'A');
-      // #endregion
-
       // POST-FIX: Backspace should now be detected and sent
       print('Backspace test: anyEventSent=$anyEventSent, backspaceKeySent=$backspaceKeySent');
       print('Events after backspace: ${mockInputService.sentEvents}');
@@ -208,10 +152,6 @@ void main() {
     });
 
     testWidgets('Hypothesis B: Enter/Done button submission', (tester) async {
-      // #region agent log
-      _debugLog('test:hypothesis_b', 'Starting enter/done test', {}, 'B');
-      // #endregion
-
       await tester.pumpWidget(
         CupertinoApp(
           home: CupertinoPageScaffold(
@@ -238,26 +178,12 @@ void main() {
       await tester.enterText(textField, 'test');
       await tester.pump();
 
-      // #region agent log
-      _debugLog('test:hypothesis_b', 'After typing "test"', {
-        'eventsBeforeSubmit': mockInputService.sentEvents.length,
-      }, 'B');
-      // #endregion
-
       mockInputService.clear();
 
       // Simulate pressing Done/Enter on the keyboard
       await tester.testTextInput.receiveAction(TextInputAction.done);
       await tester.pump();
 
-      // #region agent log
-      _debugLog('test:hypothesis_b', 'After Done action', {
-        'sentEvents': mockInputService.sentEvents.length,
-        'keyPressEvents': mockInputService.keyPressEvents.length,
-        'allEvents': mockInputService.sentEvents,
-      }, 'B');
-      // #endregion
-
       // Check if enter key (code 36) was sent
       final enterKeySent = mockInputService.keyPressEvents.any((e) => e['keyCode'] == 36);
       final anyEventSent = mockInputService.sentEvents.isNotEmpty;
@@ -270,10 +196,6 @@ void main() {
     });
 
     testWidgets('Control: Normal text input works', (tester) async {
-      // #region agent log
-      _debugLog('test:control', 'Starting normal text input test', {}, 'control');
-      // #endregion
-
       await tester.pumpWidget(
         CupertinoApp(
           home: CupertinoPageScaffold(
@@ -300,13 +222,6 @@ void main() {
       await tester.enterText(textField, 'x');
       await tester.pump();
 
-      // #region agent log
-      _debugLog('test:control', 'After typing "x"', {
-        'textInputEvents': mockInputService.textInputEvents.length,
-        'events': mockInputService.textInputEvents,
-      }, 'control');
-      // #endregion
-
       // Normal text should work
       expect(mockInputService.textInputEvents.length, greaterThan(0));
       expect(mockInputService.textInputEvents.first['text'], 'x');
@@ -314,10 +229,6 @@ void main() {
     });
 
     testWidgets('Hypothesis D: TextEditingController clear behavior', (tester) async {
-      // #region agent log
-      _debugLog('test:hypothesis_d', 'Starting controller clear test', {}, 'D');
-      // #endregion
-
       await tester.pumpWidget(
         CupertinoApp(
           home: CupertinoPageScaffold(
@@ -353,14 +264,6 @@ void main() {
       
       final eventsAfterB = mockInputService.sentEvents.length;
 
-      // #region agent log
-      _debugLog('test:hypothesis_d', 'Controller clear test result', {
-        'eventsAfterA': eventsAfterA,
-        'eventsAfterB': eventsAfterB,
-        'allEvents': mockInputService.sentEvents,
-      }, 'D');
-      // #endregion
-
       print('Controller clear test: eventsAfterA=$eventsAfterA, eventsAfterB=$eventsAfterB');
       print('All events: ${mockInputService.sentEvents}');
     });
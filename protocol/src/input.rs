@@ -0,0 +1,157 @@
+//! Input events sent by clients
+
+use serde::{Deserialize, Serialize};
+
+/// Mouse button types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// Mouse action types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseAction {
+    Click,
+    DoubleClick,
+    Down,
+    Up,
+    Move,
+    Drag,
+    Scroll,
+}
+
+/// Mouse input event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MouseEvent {
+    pub window_id: u32,
+    pub action: MouseAction,
+    #[serde(default)]
+    pub button: Option<MouseButton>,
+    /// Normalized X coordinate (0.0 - 1.0)
+    pub x: f64,
+    /// Normalized Y coordinate (0.0 - 1.0)
+    pub y: f64,
+    /// Scroll delta for scroll events
+    #[serde(default)]
+    pub scroll_delta: Option<i32>,
+    /// `x` and `y` move the pointer by that many window points instead of
+    /// placing it (trackpad-style input; see `PointerSettings`)
+    ///
+    /// Non-move actions happen at the pointer position after the movement.
+    #[serde(default)]
+    pub relative: bool,
+}
+
+/// Scaling of relative pointer movement
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PointerSettings {
+    /// Multiplier for every movement
+    #[serde(default = "default_pointer_speed")]
+    pub speed: f64,
+    /// Extra gain per point of movement in one event (0 = linear)
+    #[serde(default)]
+    pub acceleration: f64,
+}
+
+fn default_pointer_speed() -> f64 {
+    1.0
+}
+
+impl Default for PointerSettings {
+    fn default() -> Self {
+        Self {
+            speed: default_pointer_speed(),
+            acceleration: 0.0,
+        }
+    }
+}
+
+/// Key action types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyAction {
+    Down,
+    Up,
+}
+
+/// Keyboard modifier keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyModifier {
+    Cmd,
+    Shift,
+    Alt,
+    Ctrl,
+    Fn,
+}
+
+/// Keyboard layout of the streamed host, used to place characters on keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardLayout {
+    #[default]
+    Us,
+    Uk,
+    De,
+    Fr,
+    Jp,
+}
+
+/// Keyboard input event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyEvent {
+    pub window_id: u32,
+    pub action: KeyAction,
+    /// macOS virtual key code, needed unless `key` is set
+    #[serde(default)]
+    pub key_code: Option<u16>,
+    /// Active modifier keys
+    #[serde(default)]
+    pub modifiers: Vec<KeyModifier>,
+    /// Character the key should produce, instead of `key_code`
+    ///
+    /// The server picks the key (and Shift/Option) that produces it in the
+    /// connection's keyboard layout, so shortcuts like Cmd+Z hit the right key
+    /// on non-US layouts.
+    #[serde(default)]
+    pub key: Option<char>,
+}
+
+/// Text input event - for typing text characters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextEvent {
+    pub window_id: u32,
+    /// The text to type
+    pub text: String,
+}
+
+/// File dropped onto a window
+///
+/// The content is either sent inline (`data`, base64) or fetched by the
+/// server from a plain `http://` URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DroppedFile {
+    /// File name, without directories
+    pub name: String,
+    /// Base64 file content
+    #[serde(default)]
+    pub data: Option<String>,
+    /// URL to fetch the content from, instead of `data`
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// Drop files onto a window, as if dragged from the Finder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileDrop {
+    pub window_id: u32,
+    /// Normalized X coordinate of the drop point (0.0 - 1.0)
+    pub x: f64,
+    /// Normalized Y coordinate of the drop point (0.0 - 1.0)
+    pub y: f64,
+    pub files: Vec<DroppedFile>,
+}
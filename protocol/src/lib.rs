@@ -0,0 +1,17 @@
+//! Blink Protocol - wire message types shared by Blink services
+//!
+//! Serde models for the stream-server WebSocket protocol, used by the server
+//! and by Rust clients (including the integration tests) so both sides agree
+//! on the JSON format.
+
+pub mod input;
+pub mod messages;
+pub mod quality;
+pub mod stats;
+pub mod window;
+
+pub use input::*;
+pub use messages::{ClientMessage, IceCandidate, ServerMessage};
+pub use quality::QualityPreset;
+pub use stats::{StageLatency, TrackQuality};
+pub use window::{AppInfo, WindowBounds, WindowInfo};
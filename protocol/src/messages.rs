@@ -0,0 +1,429 @@
+//! WebSocket messages exchanged with the stream server
+//!
+//! All messages are JSON objects tagged by a snake_case `type` field.
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::{FileDrop, KeyEvent, KeyboardLayout, MouseEvent, PointerSettings, TextEvent};
+use crate::quality::QualityPreset;
+use crate::stats::{StageLatency, TrackQuality};
+use crate::window::{AppInfo, WindowBounds, WindowInfo};
+
+/// ICE candidate with full WebRTC fields
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IceCandidate {
+    /// The SDP candidate string
+    pub candidate: String,
+    /// The SDP media stream identification tag
+    #[serde(default)]
+    pub sdp_mid: Option<String>,
+    /// The index of the media description in the SDP
+    #[serde(default, alias = "sdpMLineIndex")]
+    pub sdp_m_line_index: Option<u16>,
+}
+
+/// Messages sent from client to server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// WebRTC offer from client (initial connection)
+    Offer { sdp: String },
+    /// WebRTC answer from client (response to server's renegotiation offer)
+    Answer { sdp: String },
+    /// ICE candidate from client
+    Ice { candidate: IceCandidate },
+    /// Subscribe to window streams
+    Subscribe { window_ids: Vec<u32> },
+    /// Stop window streams subscribed to with `subscribe`
+    ///
+    /// A window keeps streaming while another client still wants it.
+    Unsubscribe { window_ids: Vec<u32> },
+    /// Stream whichever window of an application is frontmost
+    ///
+    /// The server subscribes to the app's frontmost window and switches to
+    /// another window of the app when that one changes (see `app_window_changed`).
+    SubscribeApp { bundle_id: String },
+    /// Stop following an application
+    ///
+    /// Its window keeps streaming while another client still wants it.
+    UnsubscribeApp { bundle_id: String },
+    /// Update viewport for a window (crop region for zoom)
+    Viewport {
+        window_id: u32,
+        /// Left edge (0.0 = left, 1.0 = right)
+        x: f32,
+        /// Top edge (0.0 = top, 1.0 = bottom)
+        y: f32,
+        /// Width as fraction of source (1.0 = full width)
+        width: f32,
+        /// Height as fraction of source (1.0 = full height)
+        height: f32,
+    },
+    /// Mouse input event
+    Mouse(MouseEvent),
+    /// Keyboard input event
+    Key(KeyEvent),
+    /// Text input event (for typing text)
+    Text(TextEvent),
+    /// Drop files onto a window (see `files_dropped`)
+    FileDrop(FileDrop),
+    /// Switch the stream quality preset (applied to active captures in place)
+    ///
+    /// Changes the preset for every client, so it requires the server's admin
+    /// token when one is set.
+    SetQuality {
+        preset: QualityPreset,
+        #[serde(default)]
+        token: Option<String>,
+    },
+    /// Set the keyboard layout used to resolve `key` in key events (this connection only)
+    SetKeyboardLayout { layout: KeyboardLayout },
+    /// Set speed and acceleration of relative mouse events (this connection only)
+    SetPointerSettings(PointerSettings),
+    /// Capture a PNG screenshot of a window (0 or omitted = native size)
+    Screenshot {
+        window_id: u32,
+        #[serde(default)]
+        max_width: u32,
+        #[serde(default)]
+        max_height: u32,
+    },
+    /// Stop sending video for a window while keeping its track
+    PauseStream {
+        window_id: u32,
+        /// Also stop capturing/encoding the window until resumed
+        #[serde(default)]
+        stop_capture: bool,
+    },
+    /// Resume sending video for a paused window
+    ResumeStream { window_id: u32 },
+    /// Ask for a keyframe on a window's stream (e.g. after a decoder error)
+    RequestKeyframe { window_id: u32 },
+    /// Request window list
+    GetWindows,
+    /// Request frame pipeline statistics
+    GetStats,
+    /// Change the video output of the running server for every client
+    ///
+    /// Requires the server's admin token. Omitted fields keep their current
+    /// value; a `preset` is applied before `width`/`height` and `scaling`.
+    Reconfigure {
+        token: String,
+        #[serde(default)]
+        preset: Option<QualityPreset>,
+        /// Output size (both or neither)
+        #[serde(default)]
+        width: Option<u32>,
+        #[serde(default)]
+        height: Option<u32>,
+        #[serde(default)]
+        scaling: Option<bool>,
+    },
+}
+
+/// Messages sent from server to client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// WebRTC answer to client (response to client's offer)
+    Answer { sdp: String },
+    /// WebRTC offer to client (renegotiation - server initiated)
+    Offer { sdp: String },
+    /// ICE candidate to client
+    Ice { candidate: IceCandidate },
+    /// List of available windows
+    WindowList {
+        windows: Vec<WindowInfo>,
+        /// Windows grouped by owning application, frontmost app first
+        #[serde(default)]
+        apps: Vec<AppInfo>,
+    },
+    /// Window closed notification
+    WindowClosed { id: u32 },
+    /// A streamed window was recreated by its app under a new ID
+    ///
+    /// The stream continues on `new_window_id`; the old window is gone.
+    WindowReplaced { old_window_id: u32, new_window_id: u32 },
+    /// The window streamed for a `subscribe_app` changed
+    ///
+    /// `window_id` is None while the app has no window.
+    AppWindowChanged { bundle_id: String, window_id: Option<u32> },
+    /// A captured window moved or resized
+    WindowBoundsChanged { window_id: u32, bounds: WindowBounds },
+    /// Capture for a window stopped with an error
+    ///
+    /// `retrying` is true while the server restarts the capture with backoff
+    /// (`attempt` counts from 1); false once it has given up.
+    CaptureError {
+        window_id: u32,
+        message: String,
+        retrying: bool,
+        attempt: u32,
+    },
+    /// Quality preset changed (acknowledges `set_quality`)
+    QualityChanged {
+        preset: QualityPreset,
+        /// Maximum output size, 0x0 if windows are streamed at native size
+        width: u32,
+        height: u32,
+        bitrate: u32,
+        fps: u32,
+    },
+    /// Keyboard layout changed (acknowledges `set_keyboard_layout`)
+    KeyboardLayoutChanged { layout: KeyboardLayout },
+    /// Pointer settings changed (acknowledges `set_pointer_settings`)
+    PointerSettingsChanged(PointerSettings),
+    /// PNG screenshot of a window (base64 encoded)
+    Screenshot { window_id: u32, data: String },
+    /// Files of a `file_drop` were delivered to the window
+    FilesDropped {
+        window_id: u32,
+        /// Where the files were written on the host
+        paths: Vec<String>,
+    },
+    /// Stream paused (acknowledges `pause_stream`)
+    StreamPaused { window_id: u32 },
+    /// Stream resumed (acknowledges `resume_stream`)
+    StreamResumed { window_id: u32 },
+    /// Frame pipeline statistics (response to `get_stats`)
+    Stats {
+        /// Frames received from the encoder since startup
+        frames: u64,
+        stages: Vec<StageLatency>,
+        /// Encoder output statistics per track
+        #[serde(default)]
+        quality: Vec<TrackQuality>,
+    },
+    /// Video output changed by a `reconfigure` (sent to every client)
+    Reconfigured {
+        /// Maximum output size, 0x0 if windows are streamed at native size
+        width: u32,
+        height: u32,
+        scaling: bool,
+        bitrate: u32,
+        fps: u32,
+    },
+    /// Error response
+    ///
+    /// `client_id` identifies the connection in the server's logs.
+    Error {
+        message: String,
+        #[serde(default)]
+        client_id: Option<u64>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{DroppedFile, KeyAction, KeyModifier, MouseAction, MouseButton};
+
+    fn round_trip<T>(message: &T) -> T
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        let json = serde_json::to_string(message).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_client_message_round_trip() {
+        let messages = vec![
+            ClientMessage::Offer { sdp: "v=0".to_string() },
+            ClientMessage::Ice {
+                candidate: IceCandidate {
+                    candidate: "candidate:1 1 udp 1 10.0.0.1 5000 typ host".to_string(),
+                    sdp_mid: Some("0".to_string()),
+                    sdp_m_line_index: Some(0),
+                },
+            },
+            ClientMessage::Subscribe { window_ids: vec![1, 2] },
+            ClientMessage::Unsubscribe { window_ids: vec![2] },
+            ClientMessage::SubscribeApp { bundle_id: "com.apple.Terminal".to_string() },
+            ClientMessage::UnsubscribeApp { bundle_id: "com.apple.Terminal".to_string() },
+            ClientMessage::Viewport { window_id: 1, x: 0.25, y: 0.5, width: 0.5, height: 0.5 },
+            ClientMessage::Mouse(MouseEvent {
+                window_id: 1,
+                action: MouseAction::Click,
+                button: Some(MouseButton::Left),
+                x: 0.5,
+                y: 0.3,
+                scroll_delta: None,
+                relative: false,
+            }),
+            ClientMessage::Mouse(MouseEvent {
+                window_id: 1,
+                action: MouseAction::Drag,
+                button: None,
+                x: 2.5,
+                y: -0.75,
+                scroll_delta: None,
+                relative: true,
+            }),
+            ClientMessage::Key(KeyEvent {
+                window_id: 1,
+                action: KeyAction::Down,
+                key_code: Some(36),
+                modifiers: vec![KeyModifier::Cmd],
+                key: None,
+            }),
+            ClientMessage::Key(KeyEvent {
+                window_id: 1,
+                action: KeyAction::Down,
+                key_code: None,
+                modifiers: vec![KeyModifier::Cmd],
+                key: Some('z'),
+            }),
+            ClientMessage::Text(TextEvent { window_id: 1, text: "hello".to_string() }),
+            ClientMessage::FileDrop(FileDrop {
+                window_id: 1,
+                x: 0.5,
+                y: 0.5,
+                files: vec![
+                    DroppedFile { name: "notes.txt".to_string(), data: Some("aGk=".to_string()), url: None },
+                    DroppedFile { name: "photo.jpg".to_string(), data: None, url: Some("http://nas/photo.jpg".to_string()) },
+                ],
+            }),
+            ClientMessage::SetQuality { preset: QualityPreset::LosslessText, token: Some("secret".to_string()) },
+            ClientMessage::SetKeyboardLayout { layout: KeyboardLayout::De },
+            ClientMessage::SetPointerSettings(PointerSettings { speed: 1.5, acceleration: 0.1 }),
+            ClientMessage::Screenshot { window_id: 1, max_width: 1280, max_height: 800 },
+            ClientMessage::PauseStream { window_id: 1, stop_capture: true },
+            ClientMessage::ResumeStream { window_id: 1 },
+            ClientMessage::RequestKeyframe { window_id: 1 },
+            ClientMessage::GetWindows,
+            ClientMessage::GetStats,
+            ClientMessage::Reconfigure {
+                token: "secret".to_string(),
+                preset: Some(QualityPreset::High),
+                width: Some(2560),
+                height: Some(1440),
+                scaling: None,
+            },
+        ];
+
+        for message in messages {
+            assert_eq!(round_trip(&message), message);
+        }
+    }
+
+    #[test]
+    fn test_server_message_round_trip() {
+        let bounds = WindowBounds { x: 0.0, y: 25.0, width: 1280.0, height: 800.0 };
+        let messages = vec![
+            ServerMessage::Answer { sdp: "v=0".to_string() },
+            ServerMessage::WindowList {
+                windows: vec![WindowInfo {
+                    id: 1,
+                    title: "Cursor - project".to_string(),
+                    app: "Cursor".to_string(),
+                    bounds: bounds.clone(),
+                    bundle_id: Some("com.todesktop.230313mzl4w4u92".to_string()),
+                    pid: Some(4242),
+                }],
+                apps: vec![AppInfo {
+                    bundle_id: "com.todesktop.230313mzl4w4u92".to_string(),
+                    name: "Cursor".to_string(),
+                    pid: Some(4242),
+                    icon: None,
+                    window_ids: vec![1],
+                }],
+            },
+            ServerMessage::WindowClosed { id: 1 },
+            ServerMessage::WindowReplaced { old_window_id: 1, new_window_id: 3 },
+            ServerMessage::AppWindowChanged { bundle_id: "com.apple.Terminal".to_string(), window_id: Some(2) },
+            ServerMessage::WindowBoundsChanged { window_id: 1, bounds },
+            ServerMessage::CaptureError {
+                window_id: 1,
+                message: "stream stopped".to_string(),
+                retrying: true,
+                attempt: 1,
+            },
+            ServerMessage::QualityChanged {
+                preset: QualityPreset::High,
+                width: 1920,
+                height: 1080,
+                bitrate: 6_000_000,
+                fps: 30,
+            },
+            ServerMessage::KeyboardLayoutChanged { layout: KeyboardLayout::Fr },
+            ServerMessage::PointerSettingsChanged(PointerSettings::default()),
+            ServerMessage::Screenshot { window_id: 1, data: "iVBORw0KGgo=".to_string() },
+            ServerMessage::FilesDropped { window_id: 1, paths: vec!["/tmp/blink-drops/1/notes.txt".to_string()] },
+            ServerMessage::StreamPaused { window_id: 1 },
+            ServerMessage::StreamResumed { window_id: 1 },
+            ServerMessage::Stats {
+                frames: 120,
+                stages: vec![StageLatency {
+                    stage: "send".to_string(),
+                    count: 120,
+                    mean_us: 180,
+                    p50_us: 250,
+                    p95_us: 500,
+                    p99_us: 1000,
+                    max_us: 2300,
+                }],
+                quality: vec![TrackQuality {
+                    window_id: 1,
+                    frames: 120,
+                    keyframes: 2,
+                    bytes: 1_500_000,
+                    mean_frame_bytes: 12_500,
+                    max_frame_bytes: 180_000,
+                    mean_qp: Some(27.5),
+                    min_qp: Some(22),
+                    max_qp: Some(34),
+                }],
+            },
+            ServerMessage::Reconfigured { width: 2560, height: 1440, scaling: true, bitrate: 6_000_000, fps: 30 },
+            ServerMessage::Error { message: "bad request".to_string(), client_id: Some(3) },
+        ];
+
+        for message in messages {
+            assert_eq!(round_trip(&message), message);
+        }
+    }
+
+    #[test]
+    fn test_wire_format() {
+        let json = serde_json::to_value(ClientMessage::Subscribe { window_ids: vec![12345] }).unwrap();
+        assert_eq!(json, serde_json::json!({"type": "subscribe", "window_ids": [12345]}));
+
+        // ICE fields use the browser/Flutter camelCase names
+        let candidate: IceCandidate =
+            serde_json::from_str(r#"{"candidate": "c", "sdpMid": "0", "sdpMLineIndex": 1}"#).unwrap();
+        assert_eq!(candidate.sdp_m_line_index, Some(1));
+
+        // Optional fields may be omitted
+        let message: ClientMessage =
+            serde_json::from_str(r#"{"type": "screenshot", "window_id": 7}"#).unwrap();
+        assert_eq!(message, ClientMessage::Screenshot { window_id: 7, max_width: 0, max_height: 0 });
+
+        let message: ClientMessage =
+            serde_json::from_str(r#"{"type": "set_pointer_settings", "acceleration": 0.2}"#).unwrap();
+        assert_eq!(message, ClientMessage::SetPointerSettings(PointerSettings { speed: 1.0, acceleration: 0.2 }));
+
+        let message: ClientMessage = serde_json::from_str(r#"{"type": "set_quality", "preset": "high"}"#).unwrap();
+        assert_eq!(message, ClientMessage::SetQuality { preset: QualityPreset::High, token: None });
+
+        let message: ClientMessage =
+            serde_json::from_str(r#"{"type": "key", "window_id": 1, "action": "down", "key": "z"}"#).unwrap();
+        let ClientMessage::Key(event) = message else {
+            panic!("expected key");
+        };
+        assert_eq!((event.key_code, event.key), (None, Some('z')));
+
+        // Window lists from servers without app grouping
+        let message: ServerMessage = serde_json::from_str(
+            r#"{"type": "window_list", "windows": [{"id": 1, "title": "t", "app": "a",
+                "bounds": {"x": 0, "y": 0, "width": 1, "height": 1}}]}"#,
+        )
+        .unwrap();
+        let ServerMessage::WindowList { windows, apps } = message else {
+            panic!("expected window_list");
+        };
+        assert_eq!(windows[0].bundle_id, None);
+        assert!(apps.is_empty());
+    }
+}
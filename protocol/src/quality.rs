@@ -0,0 +1,94 @@
+//! Named quality presets for the video stream
+//!
+//! A preset bundles output resolution, encoder bitrate and frame rate so the
+//! server (or a client via `set_quality`) can switch them together at runtime.
+
+use serde::{Deserialize, Serialize};
+
+/// Quality preset combining resolution, bitrate and FPS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityPreset {
+    /// 480p, 1 Mbps, 15 fps - for slow or metered links
+    Low,
+    /// 720p, 2.5 Mbps, 30 fps
+    #[default]
+    Medium,
+    /// 1080p, 6 Mbps, 30 fps
+    High,
+    /// Native resolution, 12 Mbps, 15 fps - keeps small text crisp in editors/terminals
+    #[serde(alias = "lossless-text")]
+    LosslessText,
+}
+
+impl QualityPreset {
+    /// Parse from a preset name (e.g., "low", "high", "lossless-text")
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "low" => Some(QualityPreset::Low),
+            "medium" => Some(QualityPreset::Medium),
+            "high" => Some(QualityPreset::High),
+            "lossless-text" | "lossless_text" => Some(QualityPreset::LosslessText),
+            _ => None,
+        }
+    }
+
+    /// Output width and height for this preset
+    ///
+    /// For `LosslessText` this is only an upper bound used when scaling is
+    /// forced on; by default it streams at native size.
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            QualityPreset::Low => (854, 480),
+            QualityPreset::Medium => (1280, 720),
+            QualityPreset::High | QualityPreset::LosslessText => (1920, 1080),
+        }
+    }
+
+    /// Target encoder bitrate in bits per second
+    pub fn bitrate(&self) -> u32 {
+        match self {
+            QualityPreset::Low => 1_000_000,
+            QualityPreset::Medium => 2_500_000,
+            QualityPreset::High => 6_000_000,
+            QualityPreset::LosslessText => 12_000_000,
+        }
+    }
+
+    /// Target capture/encode frame rate
+    pub fn fps(&self) -> u32 {
+        match self {
+            QualityPreset::Low | QualityPreset::LosslessText => 15,
+            QualityPreset::Medium | QualityPreset::High => 30,
+        }
+    }
+
+    /// Whether frames are scaled down to the preset resolution
+    ///
+    /// `LosslessText` streams at the window's native size, since any
+    /// resampling blurs glyphs.
+    pub fn scaling_enabled(&self) -> bool {
+        !matches!(self, QualityPreset::LosslessText)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quality_preset_parse() {
+        assert_eq!(QualityPreset::parse("low"), Some(QualityPreset::Low));
+        assert_eq!(QualityPreset::parse("HIGH"), Some(QualityPreset::High));
+        assert_eq!(QualityPreset::parse("lossless-text"), Some(QualityPreset::LosslessText));
+        assert_eq!(QualityPreset::parse("lossless_text"), Some(QualityPreset::LosslessText));
+        assert_eq!(QualityPreset::parse("ultra"), None);
+    }
+
+    #[test]
+    fn test_quality_preset_serde() {
+        let preset: QualityPreset = serde_json::from_str("\"lossless-text\"").unwrap();
+        assert_eq!(preset, QualityPreset::LosslessText);
+        assert_eq!(serde_json::to_string(&QualityPreset::High).unwrap(), "\"high\"");
+    }
+}
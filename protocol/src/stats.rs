@@ -0,0 +1,36 @@
+//! Server statistics
+
+use serde::{Deserialize, Serialize};
+
+/// Latency summary for one stage of the frame pipeline
+///
+/// Percentiles are bucket upper bounds, so they are approximate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageLatency {
+    /// Stage name (`channel`, `packetize`, `pacing`, `send`, `total`)
+    pub stage: String,
+    /// Number of frames measured
+    pub count: u64,
+    pub mean_us: u64,
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+/// Encoder output statistics for one window's track since its capture started
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackQuality {
+    pub window_id: u32,
+    /// Frames received from the encoder
+    pub frames: u64,
+    pub keyframes: u64,
+    /// Encoded bytes
+    pub bytes: u64,
+    pub mean_frame_bytes: u64,
+    pub max_frame_bytes: u64,
+    /// Mean slice QP (None until the stream's parameter sets were seen)
+    pub mean_qp: Option<f32>,
+    pub min_qp: Option<i32>,
+    pub max_qp: Option<i32>,
+}
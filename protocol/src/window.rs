@@ -0,0 +1,41 @@
+//! Window metadata
+
+use serde::{Deserialize, Serialize};
+
+/// Window bounds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowBounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Information about a capturable window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub app: String,
+    pub bounds: WindowBounds,
+    /// Bundle identifier (macOS) or WM_CLASS (X11) of the owning application
+    #[serde(default)]
+    pub bundle_id: Option<String>,
+    /// Process ID of the owning application
+    #[serde(default)]
+    pub pid: Option<u32>,
+}
+
+/// Windows of one application
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppInfo {
+    pub bundle_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    /// App icon as a base64 PNG, if the platform provides one
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Windows of the app, frontmost first
+    pub window_ids: Vec<u32>,
+}
@@ -0,0 +1,85 @@
+//! Platform capture backends
+//!
+//! Window enumeration, capture and H.264 encoding are provided by a backend
+//! per platform: ScreenCaptureKit via the Swift bridge on macOS, and
+//! X11/PipeWire with GStreamer on Linux. Encoded frames and capture errors
+//! are delivered through the callbacks registered in the bridge module
+//! regardless of backend.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+
+use super::WindowInfo;
+
+/// Capture and encode operations implemented per platform
+pub trait CaptureBackend: Send + Sync {
+    /// Human-readable backend name for logs
+    fn name(&self) -> &'static str;
+
+    /// Prepare the backend (called once at startup)
+    fn initialize(&self) -> Result<()>;
+
+    /// Whether the process may capture windows
+    fn has_permission(&self) -> bool;
+
+    /// Windows that can be captured
+    fn get_windows(&self) -> Result<Vec<WindowInfo>>;
+
+    /// Start capturing and encoding a window
+    ///
+    /// Output is scaled to fit within `max_width`x`max_height` (0 = native size)
+    /// and encoded at the given bitrate (bits/s) and frame rate.
+    fn start_capture(&self, window_id: u32, max_width: u32, max_height: u32, bitrate: u32, fps: u32) -> Result<()>;
+
+    /// Stop capturing a window
+    fn stop_capture(&self, window_id: u32) -> Result<()>;
+
+    /// Request a keyframe from the encoder for a window
+    fn request_keyframe(&self, window_id: u32) -> Result<()>;
+
+    /// Reconfigure output size, bitrate and frame rate of a running capture
+    fn set_quality(&self, window_id: u32, max_width: u32, max_height: u32, bitrate: u32, fps: u32) -> Result<()>;
+
+    /// Update the native size of a captured window after it was resized
+    fn set_source_size(&self, window_id: u32, width: u32, height: u32) -> Result<()>;
+
+    /// Set the encode rate for windows whose content is not changing (0 = disabled)
+    fn set_idle_fps(&self, fps: u32);
+
+    /// Capture a single PNG screenshot of a window
+    fn capture_screenshot_png(&self, window_id: u32, max_width: u32, max_height: u32) -> Result<Vec<u8>>;
+
+    /// Icon of a running application as a `size`x`size` PNG
+    ///
+    /// Backends without application icons return None.
+    fn app_icon_png(&self, _pid: u32, _size: u32) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Hand files to a window as if dropped at the normalized point (`x`, `y`)
+    ///
+    /// May block until the application accepted the files. Backends that
+    /// cannot deliver files return an error.
+    fn drop_files(&self, window: &WindowInfo, _x: f64, _y: f64, _paths: &[PathBuf]) -> Result<()> {
+        Err(anyhow!("The {} backend cannot drop files onto window {}", self.name(), window.id))
+    }
+}
+
+static BACKEND: OnceLock<Box<dyn CaptureBackend>> = OnceLock::new();
+
+/// The capture backend of this platform
+pub fn backend() -> &'static dyn CaptureBackend {
+    BACKEND.get_or_init(platform_backend).as_ref()
+}
+
+#[cfg(target_os = "macos")]
+fn platform_backend() -> Box<dyn CaptureBackend> {
+    Box::new(super::bridge::ScreenCaptureKitBackend)
+}
+
+#[cfg(target_os = "linux")]
+fn platform_backend() -> Box<dyn CaptureBackend> {
+    Box::new(super::linux::LinuxCaptureBackend::new())
+}
@@ -1,15 +1,22 @@
 //! FFI bridge to Swift ScreenCaptureKit wrapper
 //!
 //! This module provides Rust bindings to the Swift SCKBridge library
-//! which handles the actual ScreenCaptureKit operations.
+//! which handles the actual ScreenCaptureKit operations. The frame and
+//! capture error callbacks defined here are shared by all capture backends.
 
-use anyhow::{anyhow, Result};
-use serde::Deserialize;
-use std::ffi::{c_char, c_void, CStr};
+use std::ffi::{c_char, c_void};
 use std::sync::atomic::{AtomicPtr, Ordering};
 use tracing::{debug, trace};
 
-use super::{WindowBounds, WindowInfo};
+#[cfg(target_os = "macos")]
+use anyhow::{anyhow, Result};
+#[cfg(target_os = "macos")]
+use serde::Deserialize;
+#[cfg(target_os = "macos")]
+use std::ffi::CStr;
+
+#[cfg(target_os = "macos")]
+use super::{CaptureBackend, WindowBounds, WindowInfo};
 
 /// Encoded video frame from Swift
 #[repr(C)]
@@ -44,6 +51,33 @@ pub fn set_frame_callback(callback: FrameCallbackFn) {
     debug!("Frame callback registered");
 }
 
+/// Callback function type for capture errors reported by Swift
+/// The callback receives the window ID and a NUL-terminated error description
+pub type CaptureErrorCallbackFn = extern "C" fn(u32, *const c_char);
+
+/// Global capture error callback - set by Rust, called by Swift
+static CAPTURE_ERROR_CALLBACK: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Set the global callback that Swift will call when a capture stream stops with an error
+pub fn set_capture_error_callback(callback: CaptureErrorCallbackFn) {
+    CAPTURE_ERROR_CALLBACK.store(callback as *mut c_void, Ordering::SeqCst);
+    debug!("Capture error callback registered");
+}
+
+/// Called by Swift when a capture stream stops with an error
+/// (window closed, display reconfigured, stream invalidated)
+#[no_mangle]
+pub extern "C" fn rust_on_capture_error(window_id: u32, message: *const c_char) {
+    let callback_ptr = CAPTURE_ERROR_CALLBACK.load(Ordering::SeqCst);
+    if callback_ptr.is_null() {
+        trace!("Capture error for window {} but no callback registered", window_id);
+        return;
+    }
+
+    let callback: CaptureErrorCallbackFn = unsafe { std::mem::transmute(callback_ptr) };
+    callback(window_id, message);
+}
+
 /// Called by Swift when an encoded frame is ready
 /// This is exported as a C function for Swift to call
 #[no_mangle]
@@ -63,6 +97,19 @@ pub extern "C" fn rust_on_encoded_frame(frame: *const EncodedFrame) {
     callback(frame);
 }
 
+/// Deliver a frame encoded by a Rust-side backend to the registered callback
+#[cfg(target_os = "linux")]
+pub(crate) fn deliver_frame(frame: &EncodedFrame) {
+    rust_on_encoded_frame(frame);
+}
+
+/// Report a capture error from a Rust-side backend to the registered callback
+#[cfg(target_os = "linux")]
+pub(crate) fn report_capture_error(window_id: u32, message: &str) {
+    let message = std::ffi::CString::new(message.replace('\0', " ")).unwrap_or_default();
+    rust_on_capture_error(window_id, message.as_ptr());
+}
+
 // External Swift bridge functions
 // These are implemented in the Swift package and linked at build time
 #[cfg(target_os = "macos")]
@@ -70,41 +117,33 @@ extern "C" {
     fn sck_initialize() -> i32;
     fn sck_get_windows_json() -> *mut c_char;
     fn sck_free_string(ptr: *mut c_char);
-    fn sck_get_window_count() -> i32;
-    fn sck_start_capture(window_id: u32) -> i32;
+    fn sck_start_capture(window_id: u32, max_width: u32, max_height: u32, bitrate: u32, fps: u32) -> i32;
     fn sck_stop_capture(window_id: u32) -> i32;
     fn sck_has_permission() -> i32;
     fn sck_request_keyframe(window_id: u32) -> i32;
-}
-
-/// Initialize the app context for Window Server access
-/// This MUST be called before any ScreenCaptureKit operations
-#[cfg(target_os = "macos")]
-pub fn initialize() -> Result<()> {
-    unsafe {
-        let result = sck_initialize();
-        if result != 0 {
-            return Err(anyhow!("Failed to initialize ScreenCaptureKit bridge"));
-        }
-    }
-    debug!("ScreenCaptureKit bridge initialized");
-    Ok(())
-}
-
-#[cfg(not(target_os = "macos"))]
-pub fn initialize() -> Result<()> {
-    Ok(())
+    fn sck_set_quality(window_id: u32, max_width: u32, max_height: u32, bitrate: u32, fps: u32) -> i32;
+    fn sck_set_source_size(window_id: u32, width: u32, height: u32) -> i32;
+    fn sck_set_idle_fps(fps: u32);
+    fn sck_capture_screenshot_png(window_id: u32, max_width: u32, max_height: u32, out_len: *mut usize) -> *mut u8;
+    fn sck_get_app_icon_png(pid: i32, size: u32, out_len: *mut usize) -> *mut u8;
+    fn sck_free_buffer(ptr: *mut u8);
 }
 
 /// JSON structure for deserializing window info from Swift
+#[cfg(target_os = "macos")]
 #[derive(Debug, Deserialize)]
 struct JsonWindowInfo {
     id: u32,
     title: String,
     app: String,
     bounds: JsonBounds,
+    #[serde(default)]
+    bundle_id: String,
+    #[serde(default)]
+    pid: u32,
 }
 
+#[cfg(target_os = "macos")]
 #[derive(Debug, Deserialize)]
 struct JsonBounds {
     x: f64,
@@ -113,120 +152,163 @@ struct JsonBounds {
     height: f64,
 }
 
-/// Get list of available windows from ScreenCaptureKit
+/// Capture backend using ScreenCaptureKit and VideoToolbox through the Swift bridge
+#[cfg(target_os = "macos")]
+pub struct ScreenCaptureKitBackend;
+
 #[cfg(target_os = "macos")]
-pub fn get_windows() -> Result<Vec<WindowInfo>> {
-    unsafe {
-        let json_ptr = sck_get_windows_json();
+impl CaptureBackend for ScreenCaptureKitBackend {
+    fn name(&self) -> &'static str {
+        "ScreenCaptureKit"
+    }
 
-        if json_ptr.is_null() {
-            return Ok(Vec::new());
+    /// Initialize the app context for Window Server access
+    /// This MUST be called before any ScreenCaptureKit operations
+    fn initialize(&self) -> Result<()> {
+        unsafe {
+            let result = sck_initialize();
+            if result != 0 {
+                return Err(anyhow!("Failed to initialize ScreenCaptureKit bridge"));
+            }
         }
+        debug!("ScreenCaptureKit bridge initialized");
+        Ok(())
+    }
 
-        let json_str = CStr::from_ptr(json_ptr).to_string_lossy().into_owned();
-        sck_free_string(json_ptr);
-
-        // Parse JSON
-        let json_windows: Vec<JsonWindowInfo> = serde_json::from_str(&json_str)
-            .map_err(|e| anyhow!("Failed to parse windows JSON: {}", e))?;
-
-        let windows: Vec<WindowInfo> = json_windows
-            .into_iter()
-            .map(|w| WindowInfo {
-                id: w.id,
-                title: w.title,
-                app: w.app,
-                bounds: WindowBounds {
-                    x: w.bounds.x,
-                    y: w.bounds.y,
-                    width: w.bounds.width,
-                    height: w.bounds.height,
-                },
-            })
-            .collect();
-
-        debug!("Got {} windows from ScreenCaptureKit", windows.len());
-        Ok(windows)
+    fn has_permission(&self) -> bool {
+        unsafe { sck_has_permission() == 1 }
     }
-}
 
-/// Check if screen recording permission is granted
-#[cfg(target_os = "macos")]
-pub fn has_permission() -> bool {
-    unsafe { sck_has_permission() == 1 }
-}
+    fn get_windows(&self) -> Result<Vec<WindowInfo>> {
+        unsafe {
+            let json_ptr = sck_get_windows_json();
 
-/// Get count of available windows
-#[cfg(target_os = "macos")]
-pub fn get_window_count() -> i32 {
-    unsafe { sck_get_window_count() }
-}
+            if json_ptr.is_null() {
+                return Ok(Vec::new());
+            }
 
-/// Start capturing a window
-#[cfg(target_os = "macos")]
-pub fn start_capture(window_id: u32) -> Result<()> {
-    unsafe {
-        let result = sck_start_capture(window_id);
-        if result != 0 {
-            return Err(anyhow!("Failed to start capture for window {}", window_id));
+            let json_str = CStr::from_ptr(json_ptr).to_string_lossy().into_owned();
+            sck_free_string(json_ptr);
+
+            // Parse JSON
+            let json_windows: Vec<JsonWindowInfo> = serde_json::from_str(&json_str)
+                .map_err(|e| anyhow!("Failed to parse windows JSON: {}", e))?;
+
+            let windows: Vec<WindowInfo> = json_windows
+                .into_iter()
+                .map(|w| WindowInfo {
+                    id: w.id,
+                    title: w.title,
+                    app: w.app,
+                    bounds: WindowBounds {
+                        x: w.bounds.x,
+                        y: w.bounds.y,
+                        width: w.bounds.width,
+                        height: w.bounds.height,
+                    },
+                    bundle_id: Some(w.bundle_id).filter(|id| !id.is_empty()),
+                    pid: Some(w.pid).filter(|&pid| pid != 0),
+                })
+                .collect();
+
+            debug!("Got {} windows from ScreenCaptureKit", windows.len());
+            Ok(windows)
         }
     }
-    Ok(())
-}
 
-/// Stop capturing a window
-#[cfg(target_os = "macos")]
-pub fn stop_capture(window_id: u32) -> Result<()> {
-    unsafe {
-        let result = sck_stop_capture(window_id);
-        if result != 0 {
-            return Err(anyhow!("Failed to stop capture for window {}", window_id));
+    fn start_capture(&self, window_id: u32, max_width: u32, max_height: u32, bitrate: u32, fps: u32) -> Result<()> {
+        unsafe {
+            let result = sck_start_capture(window_id, max_width, max_height, bitrate, fps);
+            if result != 0 {
+                return Err(anyhow!("Failed to start capture for window {}", window_id));
+            }
         }
+        Ok(())
     }
-    Ok(())
-}
 
-// Stub implementations for non-macOS platforms
-#[cfg(not(target_os = "macos"))]
-pub fn get_windows() -> Result<Vec<WindowInfo>> {
-    tracing::warn!("ScreenCaptureKit is only available on macOS");
-    Ok(Vec::new())
-}
+    fn stop_capture(&self, window_id: u32) -> Result<()> {
+        unsafe {
+            let result = sck_stop_capture(window_id);
+            if result != 0 {
+                return Err(anyhow!("Failed to stop capture for window {}", window_id));
+            }
+        }
+        Ok(())
+    }
 
-#[cfg(not(target_os = "macos"))]
-pub fn has_permission() -> bool {
-    false
-}
+    fn request_keyframe(&self, window_id: u32) -> Result<()> {
+        unsafe {
+            let result = sck_request_keyframe(window_id);
+            if result != 0 {
+                return Err(anyhow!("Failed to request keyframe for window {}", window_id));
+            }
+        }
+        debug!("Requested keyframe for window {}", window_id);
+        Ok(())
+    }
 
-#[cfg(not(target_os = "macos"))]
-pub fn get_window_count() -> i32 {
-    0
-}
+    /// The stream is updated in place; the encoder is only recreated when the
+    /// output size changes.
+    fn set_quality(&self, window_id: u32, max_width: u32, max_height: u32, bitrate: u32, fps: u32) -> Result<()> {
+        unsafe {
+            let result = sck_set_quality(window_id, max_width, max_height, bitrate, fps);
+            if result != 0 {
+                return Err(anyhow!("Failed to set quality for window {}", window_id));
+            }
+        }
+        debug!("Set quality for window {}: max {}x{}, {} bps, {} fps", window_id, max_width, max_height, bitrate, fps);
+        Ok(())
+    }
 
-#[cfg(not(target_os = "macos"))]
-pub fn start_capture(_window_id: u32) -> Result<()> {
-    Err(anyhow!("ScreenCaptureKit is only available on macOS"))
-}
+    /// The output is re-fitted to the capture's current max bounds.
+    fn set_source_size(&self, window_id: u32, width: u32, height: u32) -> Result<()> {
+        unsafe {
+            let result = sck_set_source_size(window_id, width, height);
+            if result != 0 {
+                return Err(anyhow!("Failed to update source size for window {}", window_id));
+            }
+        }
+        debug!("Set source size for window {}: {}x{}", window_id, width, height);
+        Ok(())
+    }
 
-#[cfg(not(target_os = "macos"))]
-pub fn stop_capture(_window_id: u32) -> Result<()> {
-    Err(anyhow!("ScreenCaptureKit is only available on macOS"))
-}
+    /// Windows switch back to their full frame rate as soon as content changes.
+    /// Applies to running captures too.
+    fn set_idle_fps(&self, fps: u32) {
+        unsafe { sck_set_idle_fps(fps) }
+        debug!("Set idle frame rate to {} fps", fps);
+    }
 
-/// Request a keyframe from the encoder for a window
-#[cfg(target_os = "macos")]
-pub fn request_keyframe(window_id: u32) -> Result<()> {
-    unsafe {
-        let result = sck_request_keyframe(window_id);
-        if result != 0 {
-            return Err(anyhow!("Failed to request keyframe for window {}", window_id));
+    /// Uses the latest frame of an active capture when there is one; otherwise the
+    /// window is captured fresh and scaled to fit within `max_width`x`max_height`
+    /// (0 = native size).
+    fn capture_screenshot_png(&self, window_id: u32, max_width: u32, max_height: u32) -> Result<Vec<u8>> {
+        unsafe {
+            let mut len: usize = 0;
+            let ptr = sck_capture_screenshot_png(window_id, max_width, max_height, &mut len);
+            if ptr.is_null() || len == 0 {
+                return Err(anyhow!("Failed to capture screenshot of window {}", window_id));
+            }
+
+            let png = std::slice::from_raw_parts(ptr, len).to_vec();
+            sck_free_buffer(ptr);
+
+            debug!("Captured {} byte screenshot of window {}", png.len(), window_id);
+            Ok(png)
         }
     }
-    debug!("Requested keyframe for window {}", window_id);
-    Ok(())
-}
 
-#[cfg(not(target_os = "macos"))]
-pub fn request_keyframe(_window_id: u32) -> Result<()> {
-    Ok(())
+    fn app_icon_png(&self, pid: u32, size: u32) -> Result<Option<Vec<u8>>> {
+        unsafe {
+            let mut len: usize = 0;
+            let ptr = sck_get_app_icon_png(pid as i32, size, &mut len);
+            if ptr.is_null() || len == 0 {
+                return Ok(None);
+            }
+
+            let png = std::slice::from_raw_parts(ptr, len).to_vec();
+            sck_free_buffer(ptr);
+            Ok(Some(png))
+        }
+    }
 }
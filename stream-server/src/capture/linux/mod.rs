@@ -0,0 +1,201 @@
+//! Linux capture backend
+//!
+//! On X11 top-level windows are listed through EWMH and captured with
+//! `ximagesrc`. Wayland does not let clients list or capture other windows,
+//! so the user picks windows/monitors once through the xdg-desktop-portal
+//! ScreenCast dialog and the shared streams are read from PipeWire. Frames are
+//! encoded with VAAPI when available (x264 otherwise) and delivered through the
+//! same callbacks as the macOS bridge. Files are dropped onto X11 windows
+//! through XDND; the portal offers no way to drop onto shared windows.
+
+mod pipeline;
+mod portal;
+mod x11;
+mod xdnd;
+
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use parking_lot::Mutex;
+use tracing::{debug, info, warn};
+
+use super::{CaptureBackend, WindowInfo};
+use pipeline::{CapturePipeline, EncodeSettings, Source};
+use portal::PortalSession;
+use x11::X11Windows;
+
+/// Display server the backend captures from
+enum DisplaySession {
+    X11(X11Windows),
+    Wayland(PortalSession),
+}
+
+/// Capture backend for X11 and Wayland (PipeWire) sessions
+pub struct LinuxCaptureBackend {
+    session: OnceLock<DisplaySession>,
+    captures: Mutex<HashMap<u32, CapturePipeline>>,
+}
+
+impl LinuxCaptureBackend {
+    pub fn new() -> Self {
+        Self {
+            session: OnceLock::new(),
+            captures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn session(&self) -> Result<&DisplaySession> {
+        self.session
+            .get()
+            .ok_or_else(|| anyhow!("Capture backend is not initialized"))
+    }
+
+    /// Source and native size of a window
+    fn source(&self, window_id: u32) -> Result<(Source, (u32, u32))> {
+        match self.session()? {
+            DisplaySession::X11(x11) => Ok((Source::X11Window(window_id), x11.window_size(window_id)?)),
+            DisplaySession::Wayland(portal) => {
+                let stream = portal
+                    .stream(window_id)
+                    .ok_or_else(|| anyhow!("Window {} is not shared through the portal", window_id))?;
+                Ok((
+                    Source::PipeWire { fd: portal.fd(), node_id: stream.node_id },
+                    stream.size,
+                ))
+            }
+        }
+    }
+
+    /// Replace the pipeline of a running capture (caps are fixed per pipeline)
+    fn restart(&self, window_id: u32, source_size: Option<(u32, u32)>, settings: Option<EncodeSettings>) -> Result<()> {
+        let mut captures = self.captures.lock();
+        let current = captures
+            .get(&window_id)
+            .ok_or_else(|| anyhow!("No capture for window {}", window_id))?;
+
+        let source = current.source();
+        let source_size = source_size.unwrap_or(current.source_size());
+        let settings = settings.unwrap_or(current.settings());
+        if source_size == current.source_size() && settings == current.settings() {
+            return Ok(());
+        }
+
+        // Stop the old pipeline before starting the new one on the same source
+        captures.remove(&window_id);
+        let pipeline = CapturePipeline::start(window_id, source, source_size, settings)?;
+        captures.insert(window_id, pipeline);
+        Ok(())
+    }
+}
+
+impl Default for LinuxCaptureBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CaptureBackend for LinuxCaptureBackend {
+    fn name(&self) -> &'static str {
+        match self.session.get() {
+            Some(DisplaySession::Wayland(_)) => "PipeWire (ScreenCast portal)",
+            _ => "X11",
+        }
+    }
+
+    fn initialize(&self) -> Result<()> {
+        gst::init().map_err(|e| anyhow!("Failed to initialize GStreamer: {}", e))?;
+
+        let session = if env::var_os("WAYLAND_DISPLAY").is_some() {
+            match PortalSession::open() {
+                Ok(portal) => DisplaySession::Wayland(portal),
+                // XWayland still allows capturing X11 clients
+                Err(e) if env::var_os("DISPLAY").is_some() => {
+                    warn!("ScreenCast portal unavailable ({}), capturing XWayland windows only", e);
+                    DisplaySession::X11(X11Windows::connect()?)
+                }
+                Err(e) => return Err(e),
+            }
+        } else if env::var_os("DISPLAY").is_some() {
+            DisplaySession::X11(X11Windows::connect()?)
+        } else {
+            return Err(anyhow!("No X11 or Wayland display found (DISPLAY/WAYLAND_DISPLAY unset)"));
+        };
+
+        let _ = self.session.set(session);
+        info!("{} capture backend initialized", self.name());
+        Ok(())
+    }
+
+    fn has_permission(&self) -> bool {
+        self.session.get().is_some()
+    }
+
+    fn get_windows(&self) -> Result<Vec<WindowInfo>> {
+        match self.session()? {
+            DisplaySession::X11(x11) => x11.windows(),
+            DisplaySession::Wayland(portal) => Ok(portal.windows()),
+        }
+    }
+
+    fn start_capture(&self, window_id: u32, max_width: u32, max_height: u32, bitrate: u32, fps: u32) -> Result<()> {
+        if self.captures.lock().contains_key(&window_id) {
+            return Ok(());
+        }
+
+        let (source, source_size) = self.source(window_id)?;
+        let settings = EncodeSettings { max_width, max_height, bitrate, fps };
+        let pipeline = CapturePipeline::start(window_id, source, source_size, settings)?;
+        self.captures.lock().insert(window_id, pipeline);
+        Ok(())
+    }
+
+    fn stop_capture(&self, window_id: u32) -> Result<()> {
+        // Dropping the pipeline stops it
+        self.captures.lock().remove(&window_id);
+        Ok(())
+    }
+
+    fn request_keyframe(&self, window_id: u32) -> Result<()> {
+        let captures = self.captures.lock();
+        let capture = captures
+            .get(&window_id)
+            .ok_or_else(|| anyhow!("No capture for window {}", window_id))?;
+        capture.request_keyframe()?;
+        debug!("Requested keyframe for window {}", window_id);
+        Ok(())
+    }
+
+    fn set_quality(&self, window_id: u32, max_width: u32, max_height: u32, bitrate: u32, fps: u32) -> Result<()> {
+        let settings = EncodeSettings { max_width, max_height, bitrate, fps };
+        self.restart(window_id, None, Some(settings))
+    }
+
+    fn set_source_size(&self, window_id: u32, width: u32, height: u32) -> Result<()> {
+        self.restart(window_id, Some((width, height)), None)
+    }
+
+    fn set_idle_fps(&self, fps: u32) {
+        if fps > 0 {
+            debug!("Idle frame rate throttling is not supported on Linux; encoding at full rate");
+        }
+    }
+
+    fn capture_screenshot_png(&self, window_id: u32, max_width: u32, max_height: u32) -> Result<Vec<u8>> {
+        let (source, source_size) = self.source(window_id)?;
+        pipeline::screenshot_png(source, source_size, max_width, max_height)
+    }
+
+    fn drop_files(&self, window: &WindowInfo, x: f64, y: f64, paths: &[PathBuf]) -> Result<()> {
+        match self.session()? {
+            DisplaySession::X11(_) => xdnd::drop_files(window.id, x, y, paths),
+            DisplaySession::Wayland(_) => Err(anyhow!(
+                "Files cannot be dropped onto window {} shared through the ScreenCast portal",
+                window.id
+            )),
+        }
+    }
+}
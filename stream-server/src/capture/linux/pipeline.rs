@@ -0,0 +1,268 @@
+//! GStreamer capture and H.264 encode pipelines
+
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app::AppSink;
+use tracing::{debug, info, warn};
+
+use crate::capture::bridge::{deliver_frame, report_capture_error};
+use crate::capture::EncodedFrame;
+
+/// How long a screenshot may take before giving up
+const SCREENSHOT_TIMEOUT: gst::ClockTime = gst::ClockTime::from_seconds(5);
+
+/// Where frames of a window are read from
+#[derive(Debug, Clone, Copy)]
+pub enum Source {
+    /// X11 window, by XID
+    X11Window(u32),
+    /// PipeWire stream shared through the ScreenCast portal
+    PipeWire { fd: RawFd, node_id: u32 },
+}
+
+impl Source {
+    fn element(&self) -> String {
+        match self {
+            Source::X11Window(xid) => format!("ximagesrc xid={} use-damage=false show-pointer=true", xid),
+            Source::PipeWire { fd, node_id } => format!("pipewiresrc fd={} path={} do-timestamp=true", fd, node_id),
+        }
+    }
+}
+
+/// Output settings of a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeSettings {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Bits per second
+    pub bitrate: u32,
+    pub fps: u32,
+}
+
+/// Fit a source size within max bounds, preserving aspect ratio
+///
+/// A zero bound means native size; output is never upscaled and is rounded to
+/// even dimensions for H.264 (same rules as the macOS bridge).
+pub fn fit_dimensions(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    if max_width == 0 || max_height == 0 || width == 0 || height == 0 {
+        return (width & !1, height & !1);
+    }
+
+    let scale = (max_width as f64 / width as f64)
+        .min(max_height as f64 / height as f64)
+        .min(1.0);
+    let fitted_width = (width as f64 * scale) as u32 & !1;
+    let fitted_height = (height as f64 * scale) as u32 & !1;
+    (fitted_width.max(2), fitted_height.max(2))
+}
+
+fn element_available(name: &str) -> bool {
+    gst::ElementFactory::find(name).is_some()
+}
+
+/// H.264 encoder, preferring VAAPI hardware encoding over x264
+fn encoder_element(settings: &EncodeSettings) -> String {
+    let kbps = (settings.bitrate / 1000).max(1);
+    let keyframe_interval = settings.fps.max(1) * 2;
+
+    if element_available("vaapih264enc") {
+        format!("vaapih264enc rate-control=cbr bitrate={} keyframe-period={}", kbps, keyframe_interval)
+    } else if element_available("vah264enc") {
+        format!("vah264enc rate-control=cbr bitrate={} key-int-max={}", kbps, keyframe_interval)
+    } else {
+        format!(
+            "x264enc tune=zerolatency speed-preset=ultrafast bitrate={} key-int-max={}",
+            kbps, keyframe_interval
+        )
+    }
+}
+
+fn parse_pipeline(description: &str) -> Result<(gst::Pipeline, AppSink)> {
+    let pipeline = gst::parse::launch(description)
+        .map_err(|e| anyhow!("Failed to create capture pipeline: {}", e))?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| anyhow!("Capture pipeline is not a pipeline"))?;
+
+    let sink = pipeline
+        .by_name("sink")
+        .and_then(|sink| sink.downcast::<AppSink>().ok())
+        .ok_or_else(|| anyhow!("Capture pipeline has no appsink"))?;
+
+    Ok((pipeline, sink))
+}
+
+/// A running capture: source → scale → H.264 encoder → appsink
+pub struct CapturePipeline {
+    pipeline: gst::Pipeline,
+    sink: AppSink,
+    source: Source,
+    source_size: (u32, u32),
+    settings: EncodeSettings,
+    /// Set when the pipeline is stopped deliberately, so the bus watcher
+    /// does not report it as an error
+    stopped: Arc<AtomicBool>,
+}
+
+impl CapturePipeline {
+    /// Build and start a capture pipeline for a window
+    pub fn start(window_id: u32, source: Source, source_size: (u32, u32), settings: EncodeSettings) -> Result<Self> {
+        let (width, height) = fit_dimensions(source_size.0, source_size.1, settings.max_width, settings.max_height);
+        let description = format!(
+            "{source} ! videoconvert ! videoscale ! videorate ! \
+             video/x-raw,format=NV12,width={width},height={height},framerate={fps}/1 ! \
+             {encoder} ! h264parse config-interval=-1 ! \
+             video/x-h264,stream-format=byte-stream,alignment=au ! \
+             appsink name=sink sync=false max-buffers=2 drop=true",
+            source = source.element(),
+            fps = settings.fps.max(1),
+            encoder = encoder_element(&settings),
+        );
+        debug!("Capture pipeline for window {}: {}", window_id, description);
+
+        let (pipeline, sink) = parse_pipeline(&description)?;
+
+        sink.set_callbacks(
+            gstreamer_app::AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
+                    let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+
+                    deliver_frame(&EncodedFrame {
+                        window_id,
+                        timestamp_ms: buffer.pts().map(|pts| pts.mseconds()).unwrap_or(0),
+                        is_keyframe: !buffer.flags().contains(gst::BufferFlags::DELTA_UNIT),
+                        data: map.as_ptr(),
+                        data_len: map.len(),
+                        width,
+                        height,
+                    });
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .build(),
+        );
+
+        let bus = pipeline.bus().ok_or_else(|| anyhow!("Capture pipeline has no bus"))?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_for_bus = Arc::clone(&stopped);
+        thread::Builder::new()
+            .name(format!("capture-bus-{}", window_id))
+            .spawn(move || watch_bus(window_id, bus, stopped_for_bus))?;
+
+        pipeline
+            .set_state(gst::State::Playing)
+            .map_err(|e| anyhow!("Failed to start capture pipeline: {}", e))?;
+
+        info!("Capture pipeline for window {} started at {}x{}", window_id, width, height);
+
+        Ok(Self {
+            pipeline,
+            sink,
+            source,
+            source_size,
+            settings,
+            stopped,
+        })
+    }
+
+    pub fn source(&self) -> Source {
+        self.source
+    }
+
+    pub fn source_size(&self) -> (u32, u32) {
+        self.source_size
+    }
+
+    pub fn settings(&self) -> EncodeSettings {
+        self.settings
+    }
+
+    /// Ask the encoder for a keyframe with SPS/PPS
+    pub fn request_keyframe(&self) -> Result<()> {
+        let event = gstreamer_video::UpstreamForceKeyUnitEvent::builder()
+            .all_headers(true)
+            .build();
+        if !self.sink.send_event(event) {
+            return Err(anyhow!("Encoder did not accept the keyframe request"));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CapturePipeline {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Err(e) = self.pipeline.set_state(gst::State::Null) {
+            warn!("Failed to stop capture pipeline: {}", e);
+        }
+    }
+}
+
+/// Report errors and unexpected end of stream as capture errors
+fn watch_bus(window_id: u32, bus: gst::Bus, stopped: Arc<AtomicBool>) {
+    while !stopped.load(Ordering::SeqCst) {
+        let Some(message) = bus.timed_pop_filtered(
+            gst::ClockTime::from_mseconds(200),
+            &[gst::MessageType::Error, gst::MessageType::Eos],
+        ) else {
+            continue;
+        };
+
+        let reason = match message.view() {
+            gst::MessageView::Error(err) => err.error().to_string(),
+            _ => "Capture stream ended".to_string(),
+        };
+
+        if !stopped.load(Ordering::SeqCst) {
+            report_capture_error(window_id, &reason);
+        }
+        break;
+    }
+}
+
+/// Capture a single PNG screenshot from a source
+pub fn screenshot_png(source: Source, source_size: (u32, u32), max_width: u32, max_height: u32) -> Result<Vec<u8>> {
+    let (width, height) = fit_dimensions(source_size.0, source_size.1, max_width, max_height);
+    let description = format!(
+        "{source} num-buffers=1 ! videoconvert ! videoscale ! \
+         video/x-raw,width={width},height={height} ! pngenc ! appsink name=sink sync=false",
+        source = source.element(),
+    );
+
+    let (pipeline, sink) = parse_pipeline(&description)?;
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(|e| anyhow!("Failed to start screenshot pipeline: {}", e))?;
+
+    let png = sink
+        .try_pull_sample(SCREENSHOT_TIMEOUT)
+        .and_then(|sample| {
+            let buffer = sample.buffer()?;
+            let map = buffer.map_readable().ok()?;
+            Some(map.to_vec())
+        });
+
+    let _ = pipeline.set_state(gst::State::Null);
+    png.ok_or_else(|| anyhow!("Failed to capture screenshot"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_dimensions() {
+        // Native size when unbounded, rounded down to even
+        assert_eq!(fit_dimensions(1281, 721, 0, 0), (1280, 720));
+        // Scaled down preserving aspect ratio
+        assert_eq!(fit_dimensions(2560, 1600, 1920, 1080), (1728, 1080));
+        // Never upscaled
+        assert_eq!(fit_dimensions(800, 600, 1920, 1080), (800, 600));
+    }
+}
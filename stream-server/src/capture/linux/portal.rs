@@ -0,0 +1,122 @@
+//! xdg-desktop-portal ScreenCast session for Wayland
+//!
+//! Wayland clients cannot list or capture other windows. Instead the portal
+//! asks the user to pick windows and monitors once, and hands out a PipeWire
+//! remote with one stream per selection.
+
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+
+use anyhow::{anyhow, Result};
+use ashpd::desktop::screencast::{CursorMode, Screencast, SourceType};
+use ashpd::desktop::{PersistMode, Session};
+use ashpd::WindowIdentifier;
+use tracing::info;
+
+use crate::capture::{WindowBounds, WindowInfo};
+
+/// A stream shared through the portal
+#[derive(Debug, Clone)]
+pub struct PortalStream {
+    pub node_id: u32,
+    pub is_monitor: bool,
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+}
+
+/// An open ScreenCast session with the streams the user selected
+pub struct PortalSession {
+    fd: OwnedFd,
+    streams: Vec<PortalStream>,
+    // Kept alive for the lifetime of the streams
+    _session: Session<'static, Screencast<'static>>,
+    _proxy: Screencast<'static>,
+}
+
+impl PortalSession {
+    /// Open a session, showing the portal's source picker
+    ///
+    /// Must be called from within the tokio runtime.
+    pub fn open() -> Result<Self> {
+        tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(Self::open_async()))
+    }
+
+    async fn open_async() -> Result<Self> {
+        let proxy = Screencast::new().await?;
+        let session = proxy.create_session().await?;
+
+        proxy
+            .select_sources(
+                &session,
+                CursorMode::Embedded,
+                SourceType::Window | SourceType::Monitor,
+                true,
+                None,
+                PersistMode::DoNot,
+            )
+            .await?;
+
+        let response = proxy
+            .start(&session, &WindowIdentifier::default())
+            .await?
+            .response()
+            .map_err(|e| anyhow!("Screen sharing was not granted: {}", e))?;
+
+        let streams: Vec<PortalStream> = response
+            .streams()
+            .iter()
+            .map(|stream| {
+                let (width, height) = stream.size().unwrap_or((0, 0));
+                PortalStream {
+                    node_id: stream.pipe_wire_node_id(),
+                    is_monitor: stream.source_type() == Some(SourceType::Monitor),
+                    position: stream.position().unwrap_or((0, 0)),
+                    size: (width.max(0) as u32, height.max(0) as u32),
+                }
+            })
+            .collect();
+
+        let fd = proxy.open_pipe_wire_remote(&session).await?;
+        info!("ScreenCast portal shared {} streams", streams.len());
+
+        Ok(Self {
+            fd,
+            streams,
+            _session: session,
+            _proxy: proxy,
+        })
+    }
+
+    /// PipeWire remote for the session's streams
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    pub fn stream(&self, node_id: u32) -> Option<&PortalStream> {
+        self.streams.iter().find(|stream| stream.node_id == node_id)
+    }
+
+    /// Shared streams as windows (IDs are PipeWire node IDs)
+    pub fn windows(&self) -> Vec<WindowInfo> {
+        self.streams
+            .iter()
+            .enumerate()
+            .map(|(i, stream)| WindowInfo {
+                id: stream.node_id,
+                title: if stream.is_monitor {
+                    format!("Screen {}", i + 1)
+                } else {
+                    format!("Shared window {}", i + 1)
+                },
+                app: "Screen sharing".to_string(),
+                bundle_id: None,
+                pid: None,
+                bounds: WindowBounds {
+                    x: stream.position.0 as f64,
+                    y: stream.position.1 as f64,
+                    width: stream.size.0 as f64,
+                    height: stream.size.1 as f64,
+                },
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,154 @@
+//! X11 window enumeration via EWMH
+
+use anyhow::{anyhow, Result};
+use tracing::debug;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+
+use crate::capture::{WindowBounds, WindowInfo};
+
+/// Windows smaller than this are skipped (same as the macOS enumerator)
+const MIN_WINDOW_SIZE: u16 = 100;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_CLIENT_LIST,
+        _NET_CLIENT_LIST_STACKING,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        UTF8_STRING,
+    }
+}
+
+/// Connection to the X server for listing top-level windows
+pub struct X11Windows {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+}
+
+impl X11Windows {
+    pub fn connect() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(|e| anyhow!("Failed to connect to X server: {}", e))?;
+        let root = conn.setup().roots[screen_num].root;
+        let atoms = Atoms::new(&conn)?.reply()?;
+        Ok(Self { conn, root, atoms })
+    }
+
+    /// Top-level windows managed by the window manager, frontmost first
+    pub fn windows(&self) -> Result<Vec<WindowInfo>> {
+        // The stacking list is bottom to top; not every window manager provides it
+        let mut ids = self.client_list(self.atoms._NET_CLIENT_LIST_STACKING)?;
+        if ids.is_empty() {
+            ids = self.client_list(self.atoms._NET_CLIENT_LIST)?;
+        }
+        ids.reverse();
+
+        let mut windows = Vec::new();
+        for id in ids {
+            match self.window_info(id) {
+                Ok(Some(window)) => windows.push(window),
+                Ok(None) => {}
+                Err(e) => debug!("Skipping X11 window {}: {}", id, e),
+            }
+        }
+
+        debug!("Got {} windows from X11", windows.len());
+        Ok(windows)
+    }
+
+    fn client_list(&self, property: u32) -> Result<Vec<Window>> {
+        let clients = self
+            .conn
+            .get_property(false, self.root, property, AtomEnum::WINDOW, 0, u32::MAX)?
+            .reply()?;
+        Ok(clients.value32().map(|ids| ids.collect()).unwrap_or_default())
+    }
+
+    /// Current size of a window
+    pub fn window_size(&self, window: Window) -> Result<(u32, u32)> {
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        Ok((geometry.width as u32, geometry.height as u32))
+    }
+
+    fn window_info(&self, window: Window) -> Result<Option<WindowInfo>> {
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        if geometry.width < MIN_WINDOW_SIZE || geometry.height < MIN_WINDOW_SIZE {
+            return Ok(None);
+        }
+
+        // Geometry is relative to the parent (often a WM frame); bounds are in root coordinates
+        let origin = self.conn.translate_coordinates(window, self.root, 0, 0)?.reply()?;
+
+        let title = match self.text_property(window, self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING)? {
+            Some(title) => title,
+            None => self
+                .text_property(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?
+                .unwrap_or_default(),
+        };
+
+        let wm_class = self
+            .conn
+            .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 1024)?
+            .reply()?;
+        let app = parse_wm_class(&wm_class.value);
+
+        let pid = self
+            .conn
+            .get_property(false, window, self.atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next());
+
+        Ok(Some(WindowInfo {
+            id: window,
+            title,
+            // WM_CLASS is the closest X11 has to a bundle identifier
+            bundle_id: Some(app.clone()).filter(|app| !app.is_empty()),
+            pid,
+            app,
+            bounds: WindowBounds {
+                x: origin.dst_x as f64,
+                y: origin.dst_y as f64,
+                width: geometry.width as f64,
+                height: geometry.height as f64,
+            },
+        }))
+    }
+
+    fn text_property(&self, window: Window, property: u32, type_: u32) -> Result<Option<String>> {
+        let reply = self
+            .conn
+            .get_property(false, window, property, type_, 0, 1024)?
+            .reply()?;
+        if reply.value.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(&reply.value).into_owned()))
+    }
+}
+
+/// Application name from a WM_CLASS value ("instance\0Class\0")
+///
+/// Prefers the class name, falling back to the instance name.
+fn parse_wm_class(value: &[u8]) -> String {
+    let mut parts = value
+        .split(|&b| b == 0)
+        .filter(|part| !part.is_empty())
+        .map(|part| String::from_utf8_lossy(part).into_owned());
+    let instance = parts.next();
+    parts.next().or(instance).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wm_class() {
+        assert_eq!(parse_wm_class(b"gnome-terminal-server\0Gnome-terminal\0"), "Gnome-terminal");
+        assert_eq!(parse_wm_class(b"xterm\0"), "xterm");
+        assert_eq!(parse_wm_class(b""), "");
+    }
+}
@@ -0,0 +1,264 @@
+//! File drops through the XDND protocol
+//!
+//! The server acts as a drag source like a file manager would: a hidden
+//! window owns `XdndSelection` with the files as a `text/uri-list` and walks
+//! the target window through enter, position and drop at the requested point.
+//! Each drop uses its own X connection, so the events of the exchange do not
+//! mix with window enumeration.
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use tracing::debug;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt, CreateWindowAux, EventMask, PropMode, SelectionNotifyEvent,
+    SelectionRequestEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+/// Highest XDND protocol version spoken
+const XDND_VERSION: u32 = 5;
+
+/// Time allowed for the target to answer each step of the drop
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay between polls for the target's answer
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        XdndAware,
+        XdndEnter,
+        XdndPosition,
+        XdndStatus,
+        XdndLeave,
+        XdndDrop,
+        XdndFinished,
+        XdndSelection,
+        XdndActionCopy,
+        TARGETS,
+        TEXT_URI_LIST: b"text/uri-list",
+    }
+}
+
+/// Drop files onto a window at the normalized point (`x`, `y`)
+///
+/// Blocks until the target accepted the files or refused them.
+pub fn drop_files(window: Window, x: f64, y: f64, paths: &[PathBuf]) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| anyhow!("Failed to connect to X server: {}", e))?;
+    let root = conn.setup().roots[screen_num].root;
+    let atoms = Atoms::new(&conn)?.reply()?;
+
+    // XDND positions are in root coordinates
+    let geometry = conn.get_geometry(window)?.reply()?;
+    let origin = conn.translate_coordinates(window, root, 0, 0)?.reply()?;
+    let root_x = origin.dst_x + (x.clamp(0.0, 1.0) * geometry.width.saturating_sub(1) as f64) as i16;
+    let root_y = origin.dst_y + (y.clamp(0.0, 1.0) * geometry.height.saturating_sub(1) as f64) as i16;
+
+    let (target, version) = find_target(&conn, &atoms, root, window, (root_x, root_y))?
+        .ok_or_else(|| anyhow!("Window {} does not accept dropped files", window))?;
+
+    let source = conn.generate_id()?;
+    conn.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        source,
+        root,
+        -1,
+        -1,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        COPY_FROM_PARENT,
+        &CreateWindowAux::new(),
+    )?;
+
+    let drag = Drag {
+        conn: &conn,
+        atoms: &atoms,
+        source,
+        target,
+        version: version.min(XDND_VERSION),
+        uri_list: uri_list(paths),
+    };
+    let result = drag.run((root_x, root_y));
+
+    let _ = conn.destroy_window(source);
+    let _ = conn.flush();
+    result
+}
+
+/// The XDND-aware window at a point in `window`, and its protocol version
+///
+/// Usually the top-level window itself; otherwise its children at the point
+/// are searched.
+fn find_target(
+    conn: &RustConnection,
+    atoms: &Atoms,
+    root: Window,
+    window: Window,
+    (root_x, root_y): (i16, i16),
+) -> Result<Option<(Window, u32)>> {
+    let mut current = window;
+    loop {
+        let version = conn
+            .get_property(false, current, atoms.XdndAware, AtomEnum::ATOM, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next());
+        if let Some(version) = version {
+            return Ok(Some((current, version)));
+        }
+
+        let child = conn.translate_coordinates(root, current, root_x, root_y)?.reply()?.child;
+        if child == NONE {
+            return Ok(None);
+        }
+        current = child;
+    }
+}
+
+/// One drop from `source` onto `target`
+struct Drag<'a> {
+    conn: &'a RustConnection,
+    atoms: &'a Atoms,
+    source: Window,
+    target: Window,
+    version: u32,
+    uri_list: String,
+}
+
+impl Drag<'_> {
+    fn run(&self, (root_x, root_y): (i16, i16)) -> Result<()> {
+        let atoms = self.atoms;
+        self.conn
+            .set_selection_owner(self.source, atoms.XdndSelection, CURRENT_TIME)?;
+
+        self.send(atoms.XdndEnter, [self.source, self.version << 24, atoms.TEXT_URI_LIST, 0, 0])?;
+        let position = ((root_x as u16 as u32) << 16) | root_y as u16 as u32;
+        self.send(atoms.XdndPosition, [self.source, 0, position, CURRENT_TIME, atoms.XdndActionCopy])?;
+
+        let status = self.wait_for(atoms.XdndStatus)?;
+        if status[1] & 1 == 0 {
+            self.send(atoms.XdndLeave, [self.source, 0, 0, 0, 0])?;
+            bail!("Window {} refused the dropped files", self.target);
+        }
+
+        self.send(atoms.XdndDrop, [self.source, 0, CURRENT_TIME, 0, 0])?;
+        let finished = self.wait_for(atoms.XdndFinished)?;
+        // Before version 5 XdndFinished does not report the outcome
+        if self.version >= 5 && finished[1] & 1 == 0 {
+            bail!("Window {} did not accept the dropped files", self.target);
+        }
+
+        debug!("Dropped files onto X11 window {} at ({}, {})", self.target, root_x, root_y);
+        Ok(())
+    }
+
+    /// Send an XDND client message to the target
+    fn send(&self, type_: Atom, data: [u32; 5]) -> Result<()> {
+        let event = ClientMessageEvent::new(32, self.target, type_, data);
+        self.conn.send_event(false, self.target, EventMask::NO_EVENT, event)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Wait for the target's client message of `type_`, serving the files meanwhile
+    fn wait_for(&self, type_: Atom) -> Result<[u32; 5]> {
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        loop {
+            match self.conn.poll_for_event()? {
+                Some(Event::SelectionRequest(request)) => self.answer(&request)?,
+                Some(Event::ClientMessage(message)) if message.window == self.source && message.type_ == type_ => {
+                    return Ok(message.data.as_data32());
+                }
+                Some(_) => {}
+                None if Instant::now() >= deadline => {
+                    let _ = self.send(self.atoms.XdndLeave, [self.source, 0, 0, 0, 0]);
+                    bail!("Window {} did not answer the drop", self.target);
+                }
+                None => std::thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Answer a request for the dragged data
+    fn answer(&self, request: &SelectionRequestEvent) -> Result<()> {
+        let atoms = self.atoms;
+        // Obsolete clients leave the property unset
+        let property = if request.property == NONE { request.target } else { request.property };
+        let served = if request.selection != atoms.XdndSelection {
+            false
+        } else if request.target == atoms.TEXT_URI_LIST {
+            self.conn.change_property8(
+                PropMode::REPLACE,
+                request.requestor,
+                property,
+                atoms.TEXT_URI_LIST,
+                self.uri_list.as_bytes(),
+            )?;
+            true
+        } else if request.target == atoms.TARGETS {
+            self.conn.change_property32(
+                PropMode::REPLACE,
+                request.requestor,
+                property,
+                AtomEnum::ATOM,
+                &[atoms.TARGETS, atoms.TEXT_URI_LIST],
+            )?;
+            true
+        } else {
+            false
+        };
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property: if served { property } else { NONE },
+        };
+        self.conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// `text/uri-list` of files
+fn uri_list(paths: &[PathBuf]) -> String {
+    paths.iter().map(|path| format!("{}\r\n", file_uri(path))).collect()
+}
+
+/// `file://` URI of an absolute path
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_list() {
+        let paths = [PathBuf::from("/tmp/blink-drops/1-1/notes.txt"), PathBuf::from("/tmp/a b/ü#.txt")];
+        assert_eq!(
+            uri_list(&paths),
+            "file:///tmp/blink-drops/1-1/notes.txt\r\nfile:///tmp/a%20b/%C3%BC%23.txt\r\n"
+        );
+    }
+}
@@ -1,33 +1,59 @@
-//! Screen capture module using ScreenCaptureKit bridge
+//! Window capture module (ScreenCaptureKit on macOS, X11/PipeWire on Linux)
 
+mod backend;
 mod bridge;
+#[cfg(target_os = "linux")]
+mod linux;
 
-pub use bridge::{initialize, set_frame_callback, request_keyframe, EncodedFrame, FrameCallbackFn};
+pub use backend::{backend, CaptureBackend};
+pub use bridge::{
+    set_frame_callback, set_capture_error_callback, CaptureErrorCallbackFn, EncodedFrame,
+    FrameCallbackFn,
+};
+pub use blink_protocol::{WindowBounds, WindowInfo};
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use parking_lot::RwLock;
-use serde::{Deserialize, Serialize};
-use tracing::info;
+use tracing::{debug, info, warn};
 
-/// Window bounds
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WindowBounds {
-    pub x: f64,
-    pub y: f64,
-    pub width: f64,
-    pub height: f64,
+use crate::video::VideoConfig;
+
+/// Initialize the platform capture backend (call once at startup)
+pub fn initialize() -> Result<()> {
+    backend().initialize()
+}
+
+/// Request a keyframe from the encoder for a window
+pub fn request_keyframe(window_id: u32) -> Result<()> {
+    backend().request_keyframe(window_id)
+}
+
+/// Set the encode rate for captured windows whose content is not changing
+///
+/// Windows switch back to their full frame rate as soon as content changes.
+/// 0 disables throttling. Applies to running captures too.
+pub fn set_idle_fps(fps: u32) {
+    backend().set_idle_fps(fps)
 }
 
-/// Information about a capturable window
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WindowInfo {
-    pub id: u32,
-    pub title: String,
-    pub app: String,
-    pub bounds: WindowBounds,
+/// Capture a PNG screenshot of a window, streamed or not
+///
+/// Blocks until the capture is done, so call it from a blocking thread.
+pub fn capture_screenshot(window_id: u32, max_width: u32, max_height: u32) -> Result<Vec<u8>> {
+    backend().capture_screenshot_png(window_id, max_width, max_height)
+}
+
+/// Hand files to a window as if dropped at the normalized point (`x`, `y`)
+///
+/// Blocks until the application accepted the files, so call it from a
+/// blocking thread.
+pub fn drop_files(window: &WindowInfo, x: f64, y: f64, paths: &[PathBuf]) -> Result<()> {
+    backend().drop_files(window, x, y, paths)
 }
 
 /// Captured frame data
@@ -43,30 +69,80 @@ pub struct CapturedFrame {
 /// Callback type for frame capture
 pub type FrameCallback = Arc<dyn Fn(CapturedFrame) + Send + Sync>;
 
+/// Automatic restart policy for captures that stop with an error
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Maximum restart attempts before the capture is dropped
+    pub max_attempts: u32,
+    /// Delay before the first restart attempt
+    pub initial_backoff: Duration,
+    /// Upper bound for the exponential backoff delay
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Backoff delay before the given (1-based) restart attempt
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// What to do after a capture stopped with an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartAction {
+    /// Restart after the backoff delay
+    Retry { attempt: u32, delay: Duration },
+    /// Restart attempts exhausted; the capture has been dropped
+    GiveUp,
+    /// The window is no longer being captured (e.g. stopped deliberately)
+    Ignore,
+}
+
 /// Manages window capture sessions
 pub struct CaptureManager {
     active_captures: RwLock<HashMap<u32, CaptureSession>>,
     frame_callbacks: RwLock<HashMap<u32, FrameCallback>>,
+    restart_policy: RestartPolicy,
 }
 
 struct CaptureSession {
     window_id: u32,
-    #[allow(dead_code)]
+    /// False while the stream is stopped and waiting to be restarted
     is_active: bool,
+    /// Settings the capture was started with, reused on restart
+    video_config: VideoConfig,
+    /// Consecutive failed restarts
+    restart_attempts: u32,
 }
 
 impl CaptureManager {
     pub fn new() -> Self {
+        Self::with_restart_policy(RestartPolicy::default())
+    }
+
+    /// Create a capture manager with a custom restart policy
+    pub fn with_restart_policy(restart_policy: RestartPolicy) -> Self {
         Self {
             active_captures: RwLock::new(HashMap::new()),
             frame_callbacks: RwLock::new(HashMap::new()),
+            restart_policy,
         }
     }
 
     /// Get list of all available windows
     pub fn get_windows(&self) -> Vec<WindowInfo> {
-        // Call into Swift bridge to enumerate windows
-        match bridge::get_windows() {
+        match backend().get_windows() {
             Ok(windows) => windows,
             Err(e) => {
                 tracing::error!("Failed to get windows: {}", e);
@@ -83,8 +159,8 @@ impl CaptureManager {
             .map(|w| w.bounds)
     }
 
-    /// Start capturing a window
-    pub fn start_capture(&self, window_id: u32) -> Result<()> {
+    /// Start capturing a window with the given output settings
+    pub fn start_capture(&self, window_id: u32, video_config: &VideoConfig) -> Result<()> {
         let mut captures = self.active_captures.write();
 
         if captures.contains_key(&window_id) {
@@ -92,14 +168,16 @@ impl CaptureManager {
             return Ok(());
         }
 
-        // Start capture via Swift bridge
-        bridge::start_capture(window_id)?;
+        let (max_width, max_height) = video_config.max_dimensions();
+        backend().start_capture(window_id, max_width, max_height, video_config.bitrate, video_config.fps)?;
 
         captures.insert(
             window_id,
             CaptureSession {
                 window_id,
                 is_active: true,
+                video_config: video_config.clone(),
+                restart_attempts: 0,
             },
         );
 
@@ -112,7 +190,7 @@ impl CaptureManager {
         let mut captures = self.active_captures.write();
 
         if let Some(_session) = captures.remove(&window_id) {
-            bridge::stop_capture(window_id)?;
+            backend().stop_capture(window_id)?;
             info!("Stopped capture for window {}", window_id);
         }
 
@@ -121,12 +199,141 @@ impl CaptureManager {
         Ok(())
     }
 
+    /// Apply new output settings to all active captures without restarting them
+    ///
+    /// Captures waiting to be restarted pick the settings up on restart.
+    pub fn apply_video_config(&self, video_config: &VideoConfig) -> Result<()> {
+        let mut captures = self.active_captures.write();
+        let (max_width, max_height) = video_config.max_dimensions();
+
+        for session in captures.values_mut() {
+            session.video_config = video_config.clone();
+            if session.is_active {
+                backend().set_quality(session.window_id, max_width, max_height, video_config.bitrate, video_config.fps)?;
+            }
+        }
+
+        info!("Applied video config to {} captures", captures.len());
+        Ok(())
+    }
+
+    /// Change the encoder bitrate (bits/s) of one capture, keeping its other settings
+    ///
+    /// Applied on restart if the capture is waiting to be restarted.
+    pub fn set_bitrate(&self, window_id: u32, bitrate: u32) -> Result<()> {
+        let mut captures = self.active_captures.write();
+        let Some(session) = captures.get_mut(&window_id) else {
+            return Ok(());
+        };
+        if session.video_config.bitrate == bitrate {
+            return Ok(());
+        }
+
+        if session.is_active {
+            let (max_width, max_height) = session.video_config.max_dimensions();
+            backend().set_quality(window_id, max_width, max_height, bitrate, session.video_config.fps)?;
+        }
+        session.video_config.bitrate = bitrate;
+        debug!("Set bitrate of window {} to {} bps", window_id, bitrate);
+        Ok(())
+    }
+
+    /// IDs of windows currently being captured (excluding captures waiting to restart)
+    pub fn active_window_ids(&self) -> Vec<u32> {
+        self.active_captures
+            .read()
+            .values()
+            .filter(|session| session.is_active)
+            .map(|session| session.window_id)
+            .collect()
+    }
+
+    /// Follow a window resize so the capture output keeps the window's aspect ratio
+    pub fn update_source_size(&self, window_id: u32, width: u32, height: u32) -> Result<()> {
+        let captures = self.active_captures.read();
+        match captures.get(&window_id) {
+            Some(session) if session.is_active => backend().set_source_size(window_id, width, height),
+            _ => Ok(()),
+        }
+    }
+
+    /// Mark a capture as stopped after the bridge reported an error
+    ///
+    /// Returns true if the capture was running, i.e. the caller should start
+    /// recovery. Returns false if it is already being recovered or was not
+    /// being captured.
+    pub fn mark_failed(&self, window_id: u32) -> bool {
+        let mut captures = self.active_captures.write();
+        match captures.get_mut(&window_id) {
+            Some(session) if session.is_active => {
+                session.is_active = false;
+                warn!("Capture for window {} stopped with an error", window_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Decide the next step for a failed capture according to the restart policy
+    ///
+    /// Once attempts are exhausted the capture is dropped.
+    pub fn next_restart(&self, window_id: u32) -> RestartAction {
+        let mut captures = self.active_captures.write();
+        let Some(session) = captures.get_mut(&window_id) else {
+            return RestartAction::Ignore;
+        };
+
+        session.restart_attempts += 1;
+        let attempt = session.restart_attempts;
+
+        if attempt > self.restart_policy.max_attempts {
+            captures.remove(&window_id);
+            self.frame_callbacks.write().remove(&window_id);
+            warn!("Giving up on capture for window {} after {} restart attempts", window_id, attempt - 1);
+            return RestartAction::GiveUp;
+        }
+
+        RestartAction::Retry {
+            attempt,
+            delay: self.restart_policy.backoff(attempt),
+        }
+    }
+
+    /// Restart a failed capture with the settings it was last configured with
+    pub fn restart_capture(&self, window_id: u32) -> Result<()> {
+        let mut captures = self.active_captures.write();
+        let session = captures
+            .get_mut(&window_id)
+            .ok_or_else(|| anyhow!("No capture session for window {}", window_id))?;
+
+        let video_config = &session.video_config;
+        let (max_width, max_height) = video_config.max_dimensions();
+        backend().start_capture(window_id, max_width, max_height, video_config.bitrate, video_config.fps)?;
+
+        session.is_active = true;
+        session.restart_attempts = 0;
+
+        info!("Restarted capture for window {}", window_id);
+        Ok(())
+    }
+
+    /// PNG icon of the application with the given process ID, if the platform provides one
+    pub fn app_icon(&self, pid: u32, size: u32) -> Option<Vec<u8>> {
+        match backend().app_icon_png(pid, size) {
+            Ok(icon) => icon,
+            Err(e) => {
+                warn!("Failed to get icon of process {}: {}", pid, e);
+                None
+            }
+        }
+    }
+
     /// Register a callback for captured frames
     pub fn set_frame_callback(&self, window_id: u32, callback: FrameCallback) {
         self.frame_callbacks.write().insert(window_id, callback);
     }
 
-    /// Called by the Swift bridge when a frame is captured
+    /// Called by the capture backend when a frame is captured
     pub fn on_frame(&self, frame: CapturedFrame) {
         let callbacks = self.frame_callbacks.read();
         if let Some(callback) = callbacks.get(&frame.window_id) {
@@ -140,3 +347,32 @@ impl Default for CaptureManager {
         Self::new()
     }
 }
+
+/// 800x600 window at the origin for unit tests
+#[cfg(test)]
+pub(crate) fn test_window(id: u32, app: &str, title: &str) -> WindowInfo {
+    WindowInfo {
+        id,
+        title: title.to_string(),
+        app: app.to_string(),
+        bounds: WindowBounds { x: 0.0, y: 0.0, width: 800.0, height: 600.0 },
+        bundle_id: None,
+        pid: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_policy_backoff() {
+        let policy = RestartPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.backoff(4), Duration::from_secs(4));
+        // Capped at max_backoff
+        assert_eq!(policy.backoff(5), Duration::from_secs(8));
+        assert_eq!(policy.backoff(40), Duration::from_secs(8));
+    }
+}
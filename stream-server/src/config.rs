@@ -2,6 +2,8 @@
 
 use std::env;
 
+use crate::video::{QualityPreset, VideoConfig};
+
 /// Video resolution presets
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VideoResolution {
@@ -66,6 +68,24 @@ pub struct Config {
     pub video_resolution: VideoResolution,
     /// Whether video scaling is enabled
     pub video_scaling_enabled: bool,
+    /// Initial quality preset (bitrate/FPS, and resolution unless overridden)
+    pub video_quality: QualityPreset,
+    /// Interval for re-querying bounds of captured windows in milliseconds (0 = disabled)
+    pub bounds_refresh_ms: u64,
+    /// Encode rate for windows whose content is not changing (0 = always full rate)
+    pub idle_fps: u32,
+    /// Other stream-servers whose windows are relayed (`host:port` or `ws://` URLs)
+    pub relay_upstreams: Vec<String>,
+    /// SQLite database for session history (None = kept in memory)
+    pub session_db: Option<String>,
+    /// Move streams to the new window when an app recreates a streamed window
+    pub reattach_windows: bool,
+    /// Outgoing bitrate shared by all local captures in bits/s (0 = no budget)
+    pub bandwidth_budget: u32,
+    /// Rate in bits/s at which the RTP packets of a frame are sent (0 = one burst)
+    pub pacing_rate: u32,
+    /// Token that authorizes admin messages such as `reconfigure` (None = disabled)
+    pub admin_token: Option<String>,
 }
 
 impl Config {
@@ -77,6 +97,11 @@ impl Config {
             .unwrap_or_else(|| "Blink Stream Server".to_string());
 
         // Check environment variables for video settings
+        let video_quality = env::var("BLINK_VIDEO_QUALITY")
+            .ok()
+            .and_then(|s| QualityPreset::parse(&s))
+            .unwrap_or_default();
+
         let video_resolution = env::var("BLINK_VIDEO_RESOLUTION")
             .ok()
             .and_then(|s| VideoResolution::from_str(&s))
@@ -86,18 +111,67 @@ impl Config {
                 let height = env::var("BLINK_VIDEO_HEIGHT").ok()?.parse().ok()?;
                 Some(VideoResolution::Custom { width, height })
             })
-            .unwrap_or_default();
+            .unwrap_or_else(|| VideoResolution::from(video_quality));
         
         let video_scaling_enabled = env::var("BLINK_VIDEO_SCALING")
+            .map(|s| s != "0" && s.to_lowercase() != "false")
+            .unwrap_or_else(|_| video_quality.scaling_enabled());
+
+        let bounds_refresh_ms = env::var("BLINK_BOUNDS_REFRESH_MS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1000);
+
+        let idle_fps = env::var("BLINK_IDLE_FPS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(2);
+
+        let relay_upstreams = env::var("BLINK_RELAY_UPSTREAMS")
+            .map(|s| {
+                s.split(',')
+                    .map(str::trim)
+                    .filter(|address| !address.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let session_db = env::var("BLINK_SESSION_DB").ok().filter(|path| !path.is_empty());
+
+        let reattach_windows = env::var("BLINK_REATTACH_WINDOWS")
             .map(|s| s != "0" && s.to_lowercase() != "false")
             .unwrap_or(true);
 
+        let bandwidth_budget = env::var("BLINK_BANDWIDTH_BUDGET_KBPS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .map(|kbps| kbps.saturating_mul(1000))
+            .unwrap_or(0);
+
+        let pacing_rate = env::var("BLINK_PACING_RATE_KBPS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(50_000)
+            .saturating_mul(1000);
+
+        let admin_token = env::var("BLINK_ADMIN_TOKEN").ok().filter(|token| !token.is_empty());
+
         Self {
             port,
             server_name,
             version: "1".to_string(),
             video_resolution,
             video_scaling_enabled,
+            video_quality,
+            bounds_refresh_ms,
+            idle_fps,
+            relay_upstreams,
+            session_db,
+            reattach_windows,
+            bandwidth_budget,
+            pacing_rate,
+            admin_token,
         }
     }
 
@@ -112,6 +186,18 @@ impl Config {
     pub fn video_dimensions(&self) -> (u32, u32) {
         self.video_resolution.dimensions()
     }
+
+    /// Build the initial video configuration from the quality preset,
+    /// with any explicit resolution/scaling settings applied on top
+    pub fn video_config(&self) -> VideoConfig {
+        let (target_width, target_height) = self.video_dimensions();
+        VideoConfig {
+            target_width,
+            target_height,
+            enable_scaling: self.video_scaling_enabled,
+            ..VideoConfig::from(self.video_quality)
+        }
+    }
 }
 
 impl Default for Config {
@@ -0,0 +1,306 @@
+//! Opt-in structured diagnostics
+//!
+//! Debug events emitted with the [`diag!`](crate::diag) macro are written as
+//! JSON lines to the file named by `BLINK_DIAGNOSTICS_FILE`, and/or POSTed
+//! one by one to the `http://` URL in `BLINK_DIAGNOSTICS_ENDPOINT`. Without
+//! either variable the layer is not installed and the events are ignored, so
+//! instrumentation can stay in place in any module.
+//!
+//! ```ignore
+//! diag!(window_id, hypothesis = "keyframe lost", "Decoder stalled");
+//! ```
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::http_client;
+
+/// Tracing target for diagnostic events
+pub const TARGET: &str = "blink::diag";
+
+/// Environment variable naming the diagnostics output file
+pub const FILE_ENV: &str = "BLINK_DIAGNOSTICS_FILE";
+
+/// Environment variable with the `http://` URL diagnostic events are POSTed to
+pub const ENDPOINT_ENV: &str = "BLINK_DIAGNOSTICS_ENDPOINT";
+
+/// Events waiting to be POSTed before new ones are dropped
+const ENDPOINT_QUEUE: usize = 1024;
+
+/// Time allowed for each POST
+const ENDPOINT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Emit a structured diagnostic event
+///
+/// Takes the same field/message syntax as `tracing::debug!`.
+#[macro_export]
+macro_rules! diag {
+    ($($arg:tt)+) => {
+        ::tracing::debug!(target: $crate::diagnostics::TARGET, $($arg)+)
+    };
+}
+
+/// Layer writing diagnostic events as JSON lines
+pub struct DiagnosticsLayer {
+    sinks: Vec<Sink>,
+}
+
+/// Destination of diagnostic events
+enum Sink {
+    File(Mutex<File>),
+    /// Queue of the thread POSTing events, so a slow or unreachable
+    /// collector never blocks the thread that emitted the event
+    Endpoint(SyncSender<String>),
+}
+
+impl DiagnosticsLayer {
+    /// Append diagnostic events to the given file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self { sinks: vec![file_sink(path.as_ref())?] })
+    }
+
+    /// POST each diagnostic event as a JSON body to an `http://` URL
+    ///
+    /// Events are dropped while the queue of unsent events is full.
+    pub fn post_to(url: &str) -> Result<Self> {
+        Ok(Self { sinks: vec![endpoint_sink(url)?] })
+    }
+}
+
+/// Build the diagnostics layer if `BLINK_DIAGNOSTICS_FILE` or
+/// `BLINK_DIAGNOSTICS_ENDPOINT` is set
+///
+/// The layer only sees events with the [`TARGET`] target.
+pub fn layer_from_env<S>() -> Result<Option<Box<dyn Layer<S> + Send + Sync>>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let mut sinks = Vec::new();
+    if let Some(path) = std::env::var_os(FILE_ENV) {
+        sinks.push(file_sink(Path::new(&path))?);
+    }
+    if let Ok(url) = std::env::var(ENDPOINT_ENV) {
+        sinks.push(endpoint_sink(&url)?);
+    }
+    if sinks.is_empty() {
+        return Ok(None);
+    }
+
+    let filter = filter_fn(|metadata| metadata.target() == TARGET);
+    Ok(Some(DiagnosticsLayer { sinks }.with_filter(filter).boxed()))
+}
+
+fn file_sink(path: &Path) -> Result<Sink> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open diagnostics file {}", path.display()))?;
+    Ok(Sink::File(Mutex::new(file)))
+}
+
+fn endpoint_sink(url: &str) -> Result<Sink> {
+    let (authority, path) = http_client::parse_http_url(url).context("Invalid diagnostics endpoint")?;
+    let (authority, path) = (authority.to_string(), path.to_string());
+    let (sender, receiver) = sync_channel::<String>(ENDPOINT_QUEUE);
+
+    std::thread::Builder::new()
+        .name("diagnostics".to_string())
+        .spawn(move || {
+            // Only the first of a run of failures is logged
+            let mut failing = false;
+            for body in receiver {
+                let posted = http_client::resolve(&authority).and_then(|addresses| {
+                    http_client::post_json(addresses[0], &authority, &path, &body, ENDPOINT_TIMEOUT)
+                });
+                match posted {
+                    Ok(()) => failing = false,
+                    Err(e) if !failing => {
+                        tracing::warn!("Failed to post diagnostics to {}: {}", authority, e);
+                        failing = true;
+                    }
+                    Err(_) => {}
+                }
+            }
+        })
+        .context("Failed to start diagnostics thread")?;
+
+    Ok(Sink::Endpoint(sender))
+}
+
+impl<S> Layer<S> for DiagnosticsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+        let metadata = event.metadata();
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        let mut record = Map::new();
+        record.insert("timestamp_ms".to_string(), timestamp_ms.into());
+        record.insert("level".to_string(), metadata.level().as_str().into());
+        if let Some(module) = metadata.module_path() {
+            record.insert("module".to_string(), module.into());
+        }
+        if let (Some(file), Some(line)) = (metadata.file(), metadata.line()) {
+            record.insert("location".to_string(), format!("{}:{}", file, line).into());
+        }
+        if let Some(message) = fields.message {
+            record.insert("message".to_string(), message.into());
+        }
+        record.insert("fields".to_string(), Value::Object(fields.fields));
+
+        let Ok(line) = serde_json::to_string(&record) else {
+            return;
+        };
+        for sink in &self.sinks {
+            // Diagnostics must never take the server down; drop the event on errors
+            match sink {
+                Sink::File(file) => {
+                    let mut file = file.lock();
+                    let _ = file.write_all(line.as_bytes());
+                    let _ = file.write_all(b"\n");
+                }
+                Sink::Endpoint(sender) => {
+                    let _ = sender.try_send(line.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Collects event fields into a JSON object
+#[derive(Default)]
+struct JsonVisitor {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.fields.insert(field.name().to_string(), value.into());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let value = format!("{:?}", value);
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else {
+            self.fields.insert(field.name().to_string(), value.into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_diagnostics_layer_writes_json_lines() {
+        let path = std::env::temp_dir().join(format!("blink-diag-test-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let layer = DiagnosticsLayer::open(&path).unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(layer.with_filter(filter_fn(|metadata| metadata.target() == TARGET)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            crate::diag!(window_id = 42u32, hypothesis = "keyframe lost", "Decoder stalled");
+            tracing::info!("Not a diagnostic event");
+        });
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 1);
+
+        let record: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(record["level"], "DEBUG");
+        assert_eq!(record["message"], "Decoder stalled");
+        assert_eq!(record["fields"]["window_id"], 42);
+        assert_eq!(record["fields"]["hypothesis"], "keyframe lost");
+    }
+
+    #[test]
+    fn test_diagnostics_endpoint_posts_events() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/ingest", listener.local_addr().unwrap());
+
+        let layer = DiagnosticsLayer::post_to(&url).unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(layer.with_filter(filter_fn(|metadata| metadata.target() == TARGET)));
+        tracing::subscriber::with_default(subscriber, || {
+            crate::diag!(window_id = 7u32, "Frame dropped");
+        });
+
+        // The client waits for the response, so it only closes after this reply
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        let body = loop {
+            let n = stream.read(&mut buf).unwrap();
+            assert!(n > 0);
+            request.extend_from_slice(&buf[..n]);
+
+            let text = String::from_utf8_lossy(&request).into_owned();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length: usize = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                if body.len() == length {
+                    assert!(head.starts_with("POST /ingest HTTP/1.0\r\n"));
+                    break body.to_string();
+                }
+            }
+        };
+        stream.write_all(b"HTTP/1.0 204 No Content\r\n\r\n").unwrap();
+
+        let record: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(record["fields"]["window_id"], 7);
+    }
+}
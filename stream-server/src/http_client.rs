@@ -0,0 +1,145 @@
+//! Minimal blocking HTTP client for plain `http://` URLs
+//!
+//! Used to POST diagnostic events and to fetch files dropped by URL. Requests
+//! are sent with HTTP/1.0, so responses are never chunked and end when the
+//! server closes the connection. There is no TLS, redirect or keep-alive
+//! support. Calls block, so use them from a blocking thread.
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Room for the response head on top of the body
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Largest response body read for a POST
+const MAX_POST_RESPONSE: usize = 64 * 1024;
+
+/// Authority and path of an `http://` URL
+pub fn parse_http_url(url: &str) -> Result<(&str, &str)> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("Only http:// URLs are supported: {}", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() || authority.contains('@') {
+        bail!("Invalid URL: {}", url);
+    }
+    Ok((authority, path))
+}
+
+/// Socket addresses of a URL authority, on port 80 unless it names one
+pub fn resolve(authority: &str) -> Result<Vec<SocketAddr>> {
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    let addresses: Vec<SocketAddr> = address
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {}", address))?
+        .collect();
+    if addresses.is_empty() {
+        bail!("No address for {}", address);
+    }
+    Ok(addresses)
+}
+
+/// GET `path` from the server at `address`, failing if the body exceeds `limit` bytes
+///
+/// `timeout` bounds the whole exchange.
+pub fn get(address: SocketAddr, authority: &str, path: &str, limit: usize, timeout: Duration) -> Result<Vec<u8>> {
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, authority);
+    exchange(address, request.as_bytes(), limit, timeout)
+}
+
+/// POST a JSON body to the server at `address`, waiting for a successful response
+pub fn post_json(address: SocketAddr, authority: &str, path: &str, body: &str, timeout: Duration) -> Result<()> {
+    let request = format!(
+        "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        body.len(),
+        body
+    );
+    exchange(address, request.as_bytes(), MAX_POST_RESPONSE, timeout)?;
+    Ok(())
+}
+
+/// Send a request and return the body of a successful response
+fn exchange(address: SocketAddr, request: &[u8], limit: usize, timeout: Duration) -> Result<Vec<u8>> {
+    let deadline = Instant::now() + timeout;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)
+        .with_context(|| format!("Failed to connect to {}", address))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(request)?;
+
+    let max_response = limit + MAX_HEAD_SIZE;
+    let mut response = Vec::new();
+    let mut buf = [0u8; 16 * 1024];
+    loop {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or_else(|| anyhow!("Timed out waiting for {}", address))?;
+        stream.set_read_timeout(Some(remaining))?;
+
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&buf[..n]);
+        if response.len() > max_response {
+            bail!("Response is larger than {} bytes", limit);
+        }
+    }
+
+    let body = response_body(&response)?;
+    if body.len() > limit {
+        bail!("Response is larger than {} bytes", limit);
+    }
+    Ok(body.to_vec())
+}
+
+/// Body of a successful HTTP response
+fn response_body(response: &[u8]) -> Result<&[u8]> {
+    let head_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("Incomplete HTTP response"))?;
+    let head = std::str::from_utf8(&response[..head_end]).context("Invalid HTTP response")?;
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .ok_or_else(|| anyhow!("Invalid HTTP response"))?;
+    if !status.starts_with('2') {
+        bail!("HTTP status {}", status);
+    }
+    Ok(&response[head_end + 4..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_http_url() {
+        assert_eq!(parse_http_url("http://nas:8080/files/a.jpg").unwrap(), ("nas:8080", "/files/a.jpg"));
+        assert_eq!(parse_http_url("http://nas").unwrap(), ("nas", "/"));
+        assert!(parse_http_url("https://nas/a.jpg").is_err());
+        assert!(parse_http_url("http://user@nas/a.jpg").is_err());
+        assert!(parse_http_url("http:///a.jpg").is_err());
+    }
+
+    #[test]
+    fn test_response_body() {
+        assert_eq!(response_body(b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nhi").unwrap(), b"hi");
+        assert!(response_body(b"HTTP/1.1 404 Not Found\r\n\r\n").is_err());
+        assert!(response_body(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+}
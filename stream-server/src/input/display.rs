@@ -0,0 +1,164 @@
+//! Display geometry for multi-monitor coordinate conversion
+
+#[cfg(target_os = "macos")]
+use anyhow::{anyhow, Result};
+#[cfg(target_os = "macos")]
+use core_graphics::display::CGDisplay;
+
+use crate::capture::WindowBounds;
+
+/// Geometry of an active display
+///
+/// Bounds are in the global display space used by CGEvent: origin at the
+/// top-left of the main display, Y increasing downwards. Secondary displays
+/// may have negative coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayGeometry {
+    pub id: u32,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl DisplayGeometry {
+    /// Query the geometry of all active displays
+    #[cfg(target_os = "macos")]
+    pub fn active() -> Result<Vec<DisplayGeometry>> {
+        let ids = CGDisplay::active_displays()
+            .map_err(|e| anyhow!("Failed to list active displays: {}", e))?;
+
+        Ok(ids
+            .into_iter()
+            .map(|id| {
+                let bounds = CGDisplay::new(id).bounds();
+                DisplayGeometry {
+                    id,
+                    x: bounds.origin.x,
+                    y: bounds.origin.y,
+                    width: bounds.size.width,
+                    height: bounds.size.height,
+                }
+            })
+            .collect())
+    }
+
+    /// Height of the main display (the one at the global origin)
+    pub fn main_height(displays: &[DisplayGeometry]) -> Option<f64> {
+        displays
+            .iter()
+            .find(|d| d.x == 0.0 && d.y == 0.0)
+            .map(|d| d.height)
+    }
+
+    /// Top edge of this display in Quartz coordinates (origin at the bottom-left
+    /// of the main display, Y increasing upwards)
+    fn quartz_top(&self, main_height: f64) -> f64 {
+        main_height - self.y
+    }
+
+    /// Area of the window (Quartz bounds) that lies on this display
+    fn overlap_area(&self, bounds: &WindowBounds, main_height: f64) -> f64 {
+        let top = self.quartz_top(main_height);
+        let bottom = top - self.height;
+
+        let overlap_w = (bounds.x + bounds.width).min(self.x + self.width) - bounds.x.max(self.x);
+        let overlap_h = (bounds.y + bounds.height).min(top) - bounds.y.max(bottom);
+
+        overlap_w.max(0.0) * overlap_h.max(0.0)
+    }
+
+    /// Find the display showing the largest part of a window
+    pub fn for_window(
+        displays: &[DisplayGeometry],
+        bounds: &WindowBounds,
+        main_height: f64,
+    ) -> Option<DisplayGeometry> {
+        displays
+            .iter()
+            .map(|d| (d, d.overlap_area(bounds, main_height)))
+            .filter(|(_, area)| *area > 0.0)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(d, _)| *d)
+    }
+
+    /// Convert normalized window coordinates to a global CGEvent point on this display
+    ///
+    /// The Quartz→CGEvent flip is anchored on this display's top edge, and the
+    /// result is clamped to the display so events near a window edge never
+    /// land on a neighbouring monitor.
+    pub fn window_point(
+        &self,
+        bounds: &WindowBounds,
+        main_height: f64,
+        norm_x: f64,
+        norm_y: f64,
+    ) -> (f64, f64) {
+        let x = bounds.x + norm_x * bounds.width;
+
+        // Distance from this display's top edge down to the window's top edge
+        let window_top = bounds.y + bounds.height;
+        let offset_from_top = self.quartz_top(main_height) - window_top;
+        let y = self.y + offset_from_top + norm_y * bounds.height;
+
+        (
+            x.clamp(self.x, self.x + self.width - 1.0),
+            y.clamp(self.y, self.y + self.height - 1.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1920x1080 main display with a 2560x1440 display to its right,
+    /// top edges offset so the secondary extends 360pt above the main one
+    fn displays() -> Vec<DisplayGeometry> {
+        vec![
+            DisplayGeometry { id: 1, x: 0.0, y: 0.0, width: 1920.0, height: 1080.0 },
+            DisplayGeometry { id: 2, x: 1920.0, y: -360.0, width: 2560.0, height: 1440.0 },
+        ]
+    }
+
+    #[test]
+    fn test_display_for_window() {
+        let displays = displays();
+        let main_height = DisplayGeometry::main_height(&displays).unwrap();
+
+        let on_main = WindowBounds { x: 100.0, y: 100.0, width: 800.0, height: 600.0 };
+        assert_eq!(DisplayGeometry::for_window(&displays, &on_main, main_height).unwrap().id, 1);
+
+        // Mostly on the secondary display
+        let straddling = WindowBounds { x: 1800.0, y: 200.0, width: 1000.0, height: 600.0 };
+        assert_eq!(DisplayGeometry::for_window(&displays, &straddling, main_height).unwrap().id, 2);
+
+        let off_screen = WindowBounds { x: -5000.0, y: 0.0, width: 100.0, height: 100.0 };
+        assert!(DisplayGeometry::for_window(&displays, &off_screen, main_height).is_none());
+    }
+
+    #[test]
+    fn test_window_point_secondary_display() {
+        let displays = displays();
+        let main_height = DisplayGeometry::main_height(&displays).unwrap();
+        let secondary = displays[1];
+
+        // Window in the upper part of the secondary display (above the main display's top)
+        let bounds = WindowBounds { x: 2000.0, y: 1200.0, width: 800.0, height: 200.0 };
+        let (x, y) = secondary.window_point(&bounds, main_height, 0.5, 0.0);
+        assert_eq!(x, 2400.0);
+        assert_eq!(y, -320.0);
+    }
+
+    #[test]
+    fn test_window_point_clamped_to_display() {
+        let displays = displays();
+        let main_height = DisplayGeometry::main_height(&displays).unwrap();
+        let main = displays[0];
+
+        // Window hanging off the right edge of the main display
+        let bounds = WindowBounds { x: 1500.0, y: 0.0, width: 800.0, height: 600.0 };
+        let (x, _) = main.window_point(&bounds, main_height, 1.0, 0.5);
+        assert_eq!(x, 1919.0);
+    }
+}
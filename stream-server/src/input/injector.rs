@@ -7,88 +7,13 @@ use core_graphics::event::{
 };
 use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
 use core_graphics::geometry::CGPoint;
-use serde::{Deserialize, Serialize};
 use tracing::debug;
 
+use super::{
+    DisplayGeometry, KeyAction, KeyEvent, KeyModifier, MouseAction, MouseButton, MouseEvent, TextEvent,
+};
 use crate::capture::WindowBounds;
 
-/// Mouse button types
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum MouseButton {
-    Left,
-    Right,
-    Middle,
-}
-
-/// Mouse action types
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum MouseAction {
-    Click,
-    DoubleClick,
-    Down,
-    Up,
-    Move,
-    Drag,
-    Scroll,
-}
-
-/// Mouse input event
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MouseEvent {
-    pub window_id: u32,
-    pub action: MouseAction,
-    #[serde(default)]
-    pub button: Option<MouseButton>,
-    /// Normalized X coordinate (0.0 - 1.0)
-    pub x: f64,
-    /// Normalized Y coordinate (0.0 - 1.0)
-    pub y: f64,
-    /// Scroll delta for scroll events
-    #[serde(default)]
-    pub scroll_delta: Option<i32>,
-}
-
-/// Key action types
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum KeyAction {
-    Down,
-    Up,
-}
-
-/// Keyboard modifier keys
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
-pub enum KeyModifier {
-    Cmd,
-    Shift,
-    Alt,
-    Ctrl,
-    Fn,
-}
-
-/// Keyboard input event
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct KeyEvent {
-    pub window_id: u32,
-    pub action: KeyAction,
-    /// macOS virtual key code
-    pub key_code: u16,
-    /// Active modifier keys
-    #[serde(default)]
-    pub modifiers: Vec<KeyModifier>,
-}
-
-/// Text input event - for typing text characters
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TextEvent {
-    pub window_id: u32,
-    /// The text to type
-    pub text: String,
-}
-
 /// Handles input injection via CGEvent
 pub struct InputInjector {
     /// Cache of window bounds for coordinate conversion
@@ -108,39 +33,34 @@ impl InputInjector {
     }
 
     /// Get window bounds from cache
-    fn get_bounds(&self, window_id: u32) -> Option<WindowBounds> {
+    pub fn window_bounds(&self, window_id: u32) -> Option<WindowBounds> {
         self.window_bounds_cache.read().get(&window_id).cloned()
     }
 
     /// Convert normalized coordinates to screen coordinates
     /// 
     /// Note: Window bounds from ScreenCaptureKit are in Quartz coordinates (origin at bottom-left),
-    /// but CGEvent uses coordinates with origin at top-left. We need to convert Y.
+    /// but CGEvent uses coordinates with origin at top-left. The conversion is resolved against
+    /// the display that shows the window, so clicks stay accurate on secondary monitors.
     fn to_screen_coords(&self, window_id: u32, norm_x: f64, norm_y: f64) -> Result<CGPoint> {
         let bounds = self
-            .get_bounds(window_id)
+            .window_bounds(window_id)
             .ok_or_else(|| anyhow!("Window bounds not found for {}", window_id))?;
 
-        // Get main display height for Y coordinate conversion
-        let main_display = CGDisplay::main();
-        let screen_height = main_display.bounds().size.height;
+        // Quartz Y is measured from the bottom of the main display
+        let displays = DisplayGeometry::active()?;
+        let main_height = DisplayGeometry::main_height(&displays)
+            .unwrap_or_else(|| CGDisplay::main().bounds().size.height);
 
-        // X coordinate is straightforward (left-to-right is same in both systems)
-        let screen_x = bounds.x + (norm_x * bounds.width);
-        
-        // Y coordinate conversion:
-        // - Quartz: bounds.y is distance from screen BOTTOM to window BOTTOM
-        // - CGEvent: needs distance from screen TOP
-        // 
-        // Window top in Quartz = bounds.y + bounds.height
-        // Window top in CGEvent = screen_height - (bounds.y + bounds.height)
-        // Click position = window_top_cgevent + (norm_y * bounds.height)
-        let window_top_cgevent = screen_height - (bounds.y + bounds.height);
-        let screen_y = window_top_cgevent + (norm_y * bounds.height);
+        let display = DisplayGeometry::for_window(&displays, &bounds, main_height)
+            .ok_or_else(|| anyhow!("Window {} is not on any active display", window_id))?;
+
+        let (screen_x, screen_y) = display.window_point(&bounds, main_height, norm_x, norm_y);
 
         debug!(
-            "Coord conversion: norm({:.3},{:.3}) -> screen({:.1},{:.1}), bounds=({:.1},{:.1},{:.1},{:.1}), screen_h={:.1}",
-            norm_x, norm_y, screen_x, screen_y, bounds.x, bounds.y, bounds.width, bounds.height, screen_height
+            "Coord conversion: norm({:.3},{:.3}) -> screen({:.1},{:.1}), bounds=({:.1},{:.1},{:.1},{:.1}), display={} ({:.1},{:.1},{:.1},{:.1})",
+            norm_x, norm_y, screen_x, screen_y, bounds.x, bounds.y, bounds.width, bounds.height,
+            display.id, display.x, display.y, display.width, display.height
         );
 
         Ok(CGPoint::new(screen_x, screen_y))
@@ -342,8 +262,9 @@ impl InputInjector {
             .map_err(|_| anyhow!("Failed to create event source"))?;
 
         let is_down = matches!(event.action, KeyAction::Down);
+        let key_code = event.key_code.ok_or_else(|| anyhow!("Key event without a key code"))?;
 
-        let cg_event = CGEvent::new_keyboard_event(source, event.key_code, is_down)
+        let cg_event = CGEvent::new_keyboard_event(source, key_code, is_down)
             .map_err(|_| anyhow!("Failed to create keyboard event"))?;
 
         // Apply modifiers
@@ -355,7 +276,7 @@ impl InputInjector {
         debug!(
             "Injected key {} (code: {}, modifiers: {:?})",
             if is_down { "down" } else { "up" },
-            event.key_code,
+            key_code,
             event.modifiers
         );
 
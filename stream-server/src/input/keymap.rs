@@ -0,0 +1,282 @@
+//! Keyboard layout tables
+//!
+//! Key events carry macOS virtual key codes, which name physical key
+//! positions. Which character a position produces depends on the host's
+//! keyboard layout: on a German layout the key in the US "Y" position types
+//! "z". These tables map a character to the key and modifiers that produce
+//! it in a given layout, so clients can send `key: "z"` instead of guessing
+//! the position.
+
+use anyhow::{anyhow, bail, Result};
+
+use super::{KeyEvent, KeyModifier, KeyboardLayout};
+
+/// No character on this layer
+const NONE: char = '\0';
+
+/// Characters a key produces: plain, with Shift, with Option, with Option+Shift
+type KeyChars = (u16, [char; 4]);
+
+/// US layout (ANSI), also the base for the other layouts
+const US: &[KeyChars] = &[
+    (0, ['a', 'A', NONE, NONE]),
+    (1, ['s', 'S', NONE, NONE]),
+    (2, ['d', 'D', NONE, NONE]),
+    (3, ['f', 'F', NONE, NONE]),
+    (4, ['h', 'H', NONE, NONE]),
+    (5, ['g', 'G', NONE, NONE]),
+    (6, ['z', 'Z', NONE, NONE]),
+    (7, ['x', 'X', NONE, NONE]),
+    (8, ['c', 'C', NONE, NONE]),
+    (9, ['v', 'V', NONE, NONE]),
+    (11, ['b', 'B', NONE, NONE]),
+    (12, ['q', 'Q', NONE, NONE]),
+    (13, ['w', 'W', NONE, NONE]),
+    (14, ['e', 'E', NONE, NONE]),
+    (15, ['r', 'R', NONE, NONE]),
+    (16, ['y', 'Y', NONE, NONE]),
+    (17, ['t', 'T', NONE, NONE]),
+    (18, ['1', '!', NONE, NONE]),
+    (19, ['2', '@', NONE, NONE]),
+    (20, ['3', '#', NONE, NONE]),
+    (21, ['4', '$', NONE, NONE]),
+    (22, ['6', '^', NONE, NONE]),
+    (23, ['5', '%', NONE, NONE]),
+    (24, ['=', '+', NONE, NONE]),
+    (25, ['9', '(', NONE, NONE]),
+    (26, ['7', '&', NONE, NONE]),
+    (27, ['-', '_', NONE, NONE]),
+    (28, ['8', '*', NONE, NONE]),
+    (29, ['0', ')', NONE, NONE]),
+    (30, [']', '}', NONE, NONE]),
+    (31, ['o', 'O', NONE, NONE]),
+    (32, ['u', 'U', NONE, NONE]),
+    (33, ['[', '{', NONE, NONE]),
+    (34, ['i', 'I', NONE, NONE]),
+    (35, ['p', 'P', NONE, NONE]),
+    (37, ['l', 'L', NONE, NONE]),
+    (38, ['j', 'J', NONE, NONE]),
+    (39, ['\'', '"', NONE, NONE]),
+    (40, ['k', 'K', NONE, NONE]),
+    (41, [';', ':', NONE, NONE]),
+    (42, ['\\', '|', NONE, NONE]),
+    (43, [',', '<', NONE, NONE]),
+    (44, ['/', '?', NONE, NONE]),
+    (45, ['n', 'N', NONE, NONE]),
+    (46, ['m', 'M', NONE, NONE]),
+    (47, ['.', '>', NONE, NONE]),
+    (50, ['`', '~', NONE, NONE]),
+];
+
+/// Keys that differ from US on the British layout
+const UK: &[KeyChars] = &[
+    (10, ['§', '±', NONE, NONE]),
+    (20, ['3', '£', '#', NONE]),
+];
+
+/// Keys that differ from US on the German layout (dead keys left out)
+const DE: &[KeyChars] = &[
+    (6, ['y', 'Y', NONE, NONE]),
+    (16, ['z', 'Z', NONE, NONE]),
+    (14, ['e', 'E', '€', NONE]),
+    (37, ['l', 'L', '@', NONE]),
+    (19, ['2', '"', NONE, NONE]),
+    (20, ['3', '§', NONE, NONE]),
+    (21, ['4', '$', NONE, NONE]),
+    (23, ['5', '%', '[', NONE]),
+    (22, ['6', '&', ']', NONE]),
+    (26, ['7', '/', '|', '\\']),
+    (28, ['8', '(', '{', NONE]),
+    (25, ['9', ')', '}', NONE]),
+    (29, ['0', '=', NONE, NONE]),
+    (27, ['ß', '?', NONE, NONE]),
+    (24, [NONE, NONE, NONE, NONE]),
+    (33, ['ü', 'Ü', NONE, NONE]),
+    (30, ['+', '*', NONE, NONE]),
+    (41, ['ö', 'Ö', NONE, NONE]),
+    (39, ['ä', 'Ä', NONE, NONE]),
+    (42, ['#', '\'', NONE, NONE]),
+    (43, [',', ';', NONE, NONE]),
+    (47, ['.', ':', NONE, NONE]),
+    (44, ['-', '_', NONE, NONE]),
+    (10, [NONE, '°', NONE, NONE]),
+    (50, ['<', '>', NONE, NONE]),
+];
+
+/// Keys that differ from US on the French (AZERTY) layout (dead keys left out)
+const FR: &[KeyChars] = &[
+    (12, ['a', 'A', NONE, NONE]),
+    (13, ['z', 'Z', NONE, NONE]),
+    (0, ['q', 'Q', NONE, NONE]),
+    (6, ['w', 'W', NONE, NONE]),
+    (41, ['m', 'M', NONE, NONE]),
+    (10, ['@', '#', NONE, NONE]),
+    (18, ['&', '1', NONE, NONE]),
+    (19, ['é', '2', NONE, NONE]),
+    (20, ['"', '3', NONE, NONE]),
+    (21, ['\'', '4', NONE, NONE]),
+    (23, ['(', '5', '{', '[']),
+    (22, ['§', '6', NONE, NONE]),
+    (26, ['è', '7', NONE, NONE]),
+    (28, ['!', '8', NONE, NONE]),
+    (25, ['ç', '9', NONE, NONE]),
+    (29, ['à', '0', NONE, NONE]),
+    (27, [')', '°', '}', ']']),
+    (24, ['-', '_', NONE, NONE]),
+    (33, [NONE, NONE, NONE, NONE]),
+    (30, ['$', '*', '€', NONE]),
+    (37, ['l', 'L', NONE, '|']),
+    (39, ['ù', '%', NONE, NONE]),
+    (42, [NONE, '£', NONE, NONE]),
+    (46, [',', '?', NONE, NONE]),
+    (43, [';', '.', NONE, NONE]),
+    (47, [':', '/', NONE, '\\']),
+    (44, ['=', '+', NONE, NONE]),
+    (50, ['<', '>', NONE, NONE]),
+];
+
+/// Keys that differ from US on the Japanese (JIS) layout
+const JP: &[KeyChars] = &[
+    (19, ['2', '"', NONE, NONE]),
+    (22, ['6', '&', NONE, NONE]),
+    (26, ['7', '\'', NONE, NONE]),
+    (28, ['8', '(', NONE, NONE]),
+    (25, ['9', ')', NONE, NONE]),
+    (29, ['0', NONE, NONE, NONE]),
+    (27, ['-', '=', NONE, NONE]),
+    (24, ['^', '~', NONE, NONE]),
+    (93, ['¥', '|', '\\', NONE]),
+    (33, ['@', '`', NONE, NONE]),
+    (30, ['[', '{', NONE, NONE]),
+    (41, [';', '+', NONE, NONE]),
+    (39, [':', '*', NONE, NONE]),
+    (42, [']', '}', NONE, NONE]),
+    (94, [NONE, '_', NONE, NONE]),
+    (50, [NONE, NONE, NONE, NONE]),
+];
+
+/// Modifiers needed for each column of `KeyChars`
+const LAYERS: [&[KeyModifier]; 4] = [
+    &[],
+    &[KeyModifier::Shift],
+    &[KeyModifier::Alt],
+    &[KeyModifier::Alt, KeyModifier::Shift],
+];
+
+fn overrides(layout: KeyboardLayout) -> &'static [KeyChars] {
+    match layout {
+        KeyboardLayout::Us => &[],
+        KeyboardLayout::Uk => UK,
+        KeyboardLayout::De => DE,
+        KeyboardLayout::Fr => FR,
+        KeyboardLayout::Jp => JP,
+    }
+}
+
+/// Key code and modifiers that type `ch` in a layout
+///
+/// Space, Return and Tab are the same in every layout.
+pub fn key_for_char(layout: KeyboardLayout, ch: char) -> Option<(u16, &'static [KeyModifier])> {
+    match ch {
+        NONE => return None,
+        ' ' => return Some((49, LAYERS[0])),
+        '\n' | '\r' => return Some((36, LAYERS[0])),
+        '\t' => return Some((48, LAYERS[0])),
+        _ => {}
+    }
+
+    let overrides = overrides(layout);
+    let keys = overrides
+        .iter()
+        .chain(US.iter().filter(|(code, _)| !overrides.iter().any(|(other, _)| other == code)));
+
+    for (key_code, chars) in keys {
+        if let Some(layer) = chars.iter().position(|&c| c == ch) {
+            return Some((*key_code, LAYERS[layer]));
+        }
+    }
+    None
+}
+
+/// Resolve the `key` of a key event to a key code for a layout
+///
+/// Modifiers needed for the character are added to the event's own; events
+/// without `key` are returned unchanged. Events with neither `key` nor
+/// `key_code` are rejected.
+pub fn resolve_key(event: &KeyEvent, layout: KeyboardLayout) -> Result<KeyEvent> {
+    let Some(ch) = event.key else {
+        if event.key_code.is_none() {
+            bail!("Key event needs a key_code or key");
+        }
+        return Ok(event.clone());
+    };
+
+    let (key_code, layer) = key_for_char(layout, ch)
+        .ok_or_else(|| anyhow!("No key types '{}' in the {:?} keyboard layout", ch, layout))?;
+
+    let mut modifiers = event.modifiers.clone();
+    for modifier in layer {
+        if !modifiers.contains(modifier) {
+            modifiers.push(*modifier);
+        }
+    }
+
+    Ok(KeyEvent {
+        key_code: Some(key_code),
+        modifiers,
+        key: None,
+        ..event.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::KeyAction;
+
+    #[test]
+    fn test_key_for_char() {
+        use KeyModifier::{Alt, Shift};
+
+        assert_eq!(key_for_char(KeyboardLayout::Us, 'z'), Some((6, &[][..])));
+        assert_eq!(key_for_char(KeyboardLayout::De, 'z'), Some((16, &[][..])));
+        assert_eq!(key_for_char(KeyboardLayout::Fr, 'z'), Some((13, &[][..])));
+        assert_eq!(key_for_char(KeyboardLayout::Fr, 'q'), Some((0, &[][..])));
+
+        assert_eq!(key_for_char(KeyboardLayout::Us, '@'), Some((19, &[Shift][..])));
+        assert_eq!(key_for_char(KeyboardLayout::De, '@'), Some((37, &[Alt][..])));
+        assert_eq!(key_for_char(KeyboardLayout::De, '\\'), Some((26, &[Alt, Shift][..])));
+        assert_eq!(key_for_char(KeyboardLayout::Uk, '#'), Some((20, &[Alt][..])));
+        assert_eq!(key_for_char(KeyboardLayout::Jp, '"'), Some((19, &[Shift][..])));
+        assert_eq!(key_for_char(KeyboardLayout::Fr, '1'), Some((18, &[Shift][..])));
+
+        // Keys not overridden keep their US characters
+        assert_eq!(key_for_char(KeyboardLayout::De, 'a'), Some((0, &[][..])));
+        // Characters the layout has no key for
+        assert_eq!(key_for_char(KeyboardLayout::Us, 'ü'), None);
+        assert_eq!(key_for_char(KeyboardLayout::De, '`'), None);
+    }
+
+    #[test]
+    fn test_resolve_key() {
+        let event = KeyEvent {
+            window_id: 1,
+            action: KeyAction::Down,
+            key_code: None,
+            modifiers: vec![KeyModifier::Cmd],
+            key: Some('Z'),
+        };
+
+        let resolved = resolve_key(&event, KeyboardLayout::De).unwrap();
+        assert_eq!(resolved.key_code, Some(16));
+        assert_eq!(resolved.modifiers, [KeyModifier::Cmd, KeyModifier::Shift]);
+        assert_eq!(resolved.key, None);
+
+        let event = KeyEvent { key: Some('ü'), ..event };
+        assert!(resolve_key(&event, KeyboardLayout::Us).is_err());
+
+        // Without a key there is nothing to press
+        let event = KeyEvent { key: None, ..event };
+        assert!(resolve_key(&event, KeyboardLayout::Us).is_err());
+    }
+}
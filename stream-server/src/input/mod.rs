@@ -1,7 +1,20 @@
-//! Input injection module using Core Graphics
+//! Input injection module (Core Graphics on macOS, XTest on Linux)
 
+mod display;
+mod keymap;
+mod pointer;
+#[cfg(target_os = "macos")]
 mod injector;
+#[cfg(target_os = "linux")]
+mod xtest;
 
+pub use blink_protocol::input::*;
+pub use display::DisplayGeometry;
+pub use keymap::{key_for_char, resolve_key};
+pub use pointer::{accelerate, PointerState};
+#[cfg(target_os = "macos")]
 pub use injector::*;
+#[cfg(target_os = "linux")]
+pub use xtest::*;
 
 
@@ -0,0 +1,123 @@
+//! Relative pointer movement
+//!
+//! Touch clients can drive the pointer like a trackpad: mouse events with
+//! `relative` set carry a movement in window points, which is scaled by the
+//! connection's pointer settings and added to the last pointer position in
+//! the window. The position is kept as a float, so slow movements smaller
+//! than a pixel per event still add up.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use super::{MouseEvent, PointerSettings};
+use crate::capture::WindowBounds;
+
+/// Scale a movement by `speed * (1 + acceleration * distance)`
+pub fn accelerate(dx: f64, dy: f64, settings: &PointerSettings) -> (f64, f64) {
+    let distance = dx.hypot(dy);
+    let gain = settings.speed * (1.0 + settings.acceleration.max(0.0) * distance);
+    (dx * gain, dy * gain)
+}
+
+/// Pointer settings and positions of one client
+#[derive(Debug, Default)]
+pub struct PointerState {
+    settings: PointerSettings,
+    /// Last normalized pointer position per window
+    positions: HashMap<u32, (f64, f64)>,
+}
+
+impl PointerState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn settings(&self) -> PointerSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: PointerSettings) {
+        self.settings = settings;
+    }
+
+    /// Turn a mouse event into one with a normalized position
+    ///
+    /// Relative events start from the window center if the pointer has not
+    /// been placed in the window yet. `bounds` is only needed for them.
+    pub fn resolve(&mut self, event: &MouseEvent, bounds: Option<&WindowBounds>) -> Result<MouseEvent> {
+        if !event.relative {
+            self.positions.insert(event.window_id, (event.x, event.y));
+            return Ok(event.clone());
+        }
+
+        let bounds = bounds
+            .filter(|bounds| bounds.width > 0.0 && bounds.height > 0.0)
+            .ok_or_else(|| anyhow!("Window bounds not found for {}", event.window_id))?;
+
+        let (dx, dy) = accelerate(event.x, event.y, &self.settings);
+        let (x, y) = self.positions.get(&event.window_id).copied().unwrap_or((0.5, 0.5));
+        let position = (
+            (x + dx / bounds.width).clamp(0.0, 1.0),
+            (y + dy / bounds.height).clamp(0.0, 1.0),
+        );
+        self.positions.insert(event.window_id, position);
+
+        Ok(MouseEvent {
+            x: position.0,
+            y: position.1,
+            relative: false,
+            ..event.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::MouseAction;
+
+    #[test]
+    fn test_relative_movement() {
+        let bounds = WindowBounds { x: 100.0, y: 100.0, width: 800.0, height: 400.0 };
+        let mut pointer = PointerState::new();
+        let event = MouseEvent {
+            window_id: 1,
+            action: MouseAction::Move,
+            button: None,
+            x: 80.0,
+            y: -40.0,
+            scroll_delta: None,
+            relative: true,
+        };
+
+        // Starts at the window center
+        let moved = pointer.resolve(&event, Some(&bounds)).unwrap();
+        assert_eq!((moved.x, moved.y, moved.relative), (0.6, 0.4, false));
+
+        // Continues from the last absolute position, clamped to the window
+        let placed = MouseEvent { x: 0.9, y: 0.1, relative: false, ..event.clone() };
+        pointer.resolve(&placed, None).unwrap();
+        let moved = pointer.resolve(&event, Some(&bounds)).unwrap();
+        assert_eq!((moved.x, moved.y), (1.0, 0.0));
+
+        // Sub-pixel steps add up
+        let step = MouseEvent { x: -0.25, y: 0.0, ..event.clone() };
+        for _ in 0..8 {
+            pointer.resolve(&step, Some(&bounds)).unwrap();
+        }
+        let moved = pointer.resolve(&MouseEvent { x: 0.0, ..step }, Some(&bounds)).unwrap();
+        assert!((moved.x - (1.0 - 2.0 / 800.0)).abs() < 1e-9);
+
+        assert!(pointer.resolve(&event, None).is_err());
+    }
+
+    #[test]
+    fn test_accelerate() {
+        let linear = PointerSettings { speed: 2.0, acceleration: 0.0 };
+        assert_eq!(accelerate(3.0, 4.0, &linear), (6.0, 8.0));
+
+        let accelerated = PointerSettings { speed: 1.0, acceleration: 0.1 };
+        assert_eq!(accelerate(3.0, 4.0, &accelerated), (4.5, 6.0));
+    }
+}
@@ -0,0 +1,377 @@
+//! XTest input injection for X11 sessions
+//!
+//! Wayland offers no way for clients to inject input into other windows, so
+//! on a pure Wayland session (no XWayland `DISPLAY`) every injection fails
+//! with an error instead.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use parking_lot::RwLock;
+use tracing::{debug, warn};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    ConnectionExt as _, Window, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, KEY_PRESS_EVENT,
+    KEY_RELEASE_EVENT, MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+
+use super::{KeyAction, KeyEvent, KeyModifier, MouseAction, MouseButton, MouseEvent, TextEvent};
+use crate::capture::WindowBounds;
+
+/// X11 button numbers for wheel up/down
+const BUTTON_WHEEL_UP: u8 = 4;
+const BUTTON_WHEEL_DOWN: u8 = 5;
+
+const XK_SHIFT_L: u32 = 0xffe1;
+const XK_CONTROL_L: u32 = 0xffe3;
+const XK_ALT_L: u32 = 0xffe9;
+const XK_RETURN: u32 = 0xff0d;
+const XK_TAB: u32 = 0xff09;
+
+struct XTestConnection {
+    conn: RustConnection,
+    root: Window,
+}
+
+/// Handles input injection via the XTest extension
+pub struct InputInjector {
+    /// Cache of window bounds for coordinate conversion
+    window_bounds_cache: RwLock<HashMap<u32, WindowBounds>>,
+    /// None when no X server is reachable (e.g. pure Wayland)
+    x11: Option<XTestConnection>,
+}
+
+impl InputInjector {
+    pub fn new() -> Self {
+        let x11 = match x11rb::connect(None) {
+            Ok((conn, screen_num)) => {
+                let root = conn.setup().roots[screen_num].root;
+                Some(XTestConnection { conn, root })
+            }
+            Err(e) => {
+                warn!("Input injection unavailable, no X server: {}", e);
+                None
+            }
+        };
+
+        Self {
+            window_bounds_cache: RwLock::new(HashMap::new()),
+            x11,
+        }
+    }
+
+    /// Update cached window bounds
+    pub fn update_window_bounds(&self, window_id: u32, bounds: WindowBounds) {
+        self.window_bounds_cache.write().insert(window_id, bounds);
+    }
+
+    /// Get window bounds from cache
+    pub fn window_bounds(&self, window_id: u32) -> Option<WindowBounds> {
+        self.window_bounds_cache.read().get(&window_id).cloned()
+    }
+
+    fn x11(&self) -> Result<&XTestConnection> {
+        self.x11
+            .as_ref()
+            .ok_or_else(|| anyhow!("Input injection requires an X11 session"))
+    }
+
+    /// Convert normalized coordinates to root window coordinates
+    ///
+    /// X11 bounds already use a top-left origin, so no display lookup is needed.
+    fn to_screen_coords(&self, window_id: u32, norm_x: f64, norm_y: f64) -> Result<(i16, i16)> {
+        let bounds = self
+            .window_bounds(window_id)
+            .ok_or_else(|| anyhow!("Window bounds not found for {}", window_id))?;
+
+        let x = bounds.x + norm_x.clamp(0.0, 1.0) * bounds.width;
+        let y = bounds.y + norm_y.clamp(0.0, 1.0) * bounds.height;
+        Ok((x.round() as i16, y.round() as i16))
+    }
+
+    fn fake_input(&self, event_type: u8, detail: u8, x: i16, y: i16) -> Result<()> {
+        let x11 = self.x11()?;
+        x11.conn
+            .xtest_fake_input(event_type, detail, x11rb::CURRENT_TIME, x11.root, x, y, 0)?;
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.x11()?.conn.flush()?;
+        Ok(())
+    }
+
+    /// Inject a mouse event
+    pub fn inject_mouse(&self, event: &MouseEvent) -> Result<()> {
+        let (x, y) = self.to_screen_coords(event.window_id, event.x, event.y)?;
+        let button = x11_button(event.button.unwrap_or(MouseButton::Left));
+
+        // Every action starts by moving the pointer to the target position
+        self.fake_input(MOTION_NOTIFY_EVENT, 0, x, y)?;
+
+        match event.action {
+            MouseAction::Move | MouseAction::Drag => {}
+            MouseAction::Click => self.click(button, 1)?,
+            MouseAction::DoubleClick => self.click(button, 2)?,
+            MouseAction::Down => self.fake_input(BUTTON_PRESS_EVENT, button, 0, 0)?,
+            MouseAction::Up => self.fake_input(BUTTON_RELEASE_EVENT, button, 0, 0)?,
+            MouseAction::Scroll => {
+                let delta = event.scroll_delta.unwrap_or(0);
+                let wheel = if delta > 0 { BUTTON_WHEEL_UP } else { BUTTON_WHEEL_DOWN };
+                self.click(wheel, delta.unsigned_abs().min(10))?;
+            }
+        }
+
+        self.flush()?;
+        debug!("Injected mouse {:?} at ({}, {})", event.action, x, y);
+        Ok(())
+    }
+
+    fn click(&self, button: u8, count: u32) -> Result<()> {
+        for _ in 0..count {
+            self.fake_input(BUTTON_PRESS_EVENT, button, 0, 0)?;
+            self.fake_input(BUTTON_RELEASE_EVENT, button, 0, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Keyboard mapping lookup: keycode producing a keysym, and whether Shift is needed
+    fn keycode_for_keysym(&self, keysym: u32) -> Result<Option<(u8, bool)>> {
+        let x11 = self.x11()?;
+        let setup = x11.conn.setup();
+        let min_keycode = setup.min_keycode;
+        let count = setup.max_keycode - min_keycode + 1;
+        let mapping = x11.conn.get_keyboard_mapping(min_keycode, count)?.reply()?;
+
+        let per_keycode = mapping.keysyms_per_keycode as usize;
+        if per_keycode == 0 {
+            return Ok(None);
+        }
+
+        for (i, keysyms) in mapping.keysyms.chunks(per_keycode).enumerate() {
+            // Column 0 is unshifted, column 1 is with Shift
+            if let Some(column) = keysyms.iter().take(2).position(|&sym| sym == keysym) {
+                return Ok(Some((min_keycode + i as u8, column == 1)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn keycode(&self, keysym: u32) -> Result<u8> {
+        self.keycode_for_keysym(keysym)?
+            .map(|(keycode, _)| keycode)
+            .ok_or_else(|| anyhow!("No key for keysym {:#x} in the current layout", keysym))
+    }
+
+    /// Inject a keyboard event
+    ///
+    /// Key codes are macOS virtual key codes and are translated to X keysyms.
+    /// A `key` is looked up in the X server's own keymap, adding Shift where it
+    /// needs it. Cmd maps to Control so common shortcuts keep working.
+    pub fn inject_key(&self, event: &KeyEvent) -> Result<()> {
+        let (keycode, needs_shift) = match (event.key, event.key_code) {
+            (Some(ch), _) => self
+                .keycode_for_keysym(keysym_for_char(ch))?
+                .ok_or_else(|| anyhow!("No key types '{}' in the current layout", ch))?,
+            (None, Some(key_code)) => {
+                let keysym =
+                    keysym_for_mac_keycode(key_code).ok_or_else(|| anyhow!("Unsupported key code {}", key_code))?;
+                (self.keycode(keysym)?, false)
+            }
+            (None, None) => bail!("Key event needs a key_code or key"),
+        };
+
+        let mut modifiers = Vec::new();
+        if needs_shift {
+            modifiers.push(self.keycode(XK_SHIFT_L)?);
+        }
+        for modifier in &event.modifiers {
+            if let Some(sym) = modifier_keysym(*modifier) {
+                let code = self.keycode(sym)?;
+                if !modifiers.contains(&code) {
+                    modifiers.push(code);
+                }
+            }
+        }
+
+        match event.action {
+            KeyAction::Down => {
+                for &code in &modifiers {
+                    self.fake_input(KEY_PRESS_EVENT, code, 0, 0)?;
+                }
+                self.fake_input(KEY_PRESS_EVENT, keycode, 0, 0)?;
+            }
+            KeyAction::Up => {
+                self.fake_input(KEY_RELEASE_EVENT, keycode, 0, 0)?;
+                for &code in modifiers.iter().rev() {
+                    self.fake_input(KEY_RELEASE_EVENT, code, 0, 0)?;
+                }
+            }
+        }
+
+        self.flush()?;
+        debug!("Injected key {:?} (keycode: {}, modifiers: {:?})", event.action, keycode, event.modifiers);
+        Ok(())
+    }
+
+    /// Inject text input by typing each character
+    ///
+    /// Characters without a key in the current keyboard layout are skipped.
+    pub fn inject_text(&self, event: &TextEvent) -> Result<()> {
+        let shift = self.keycode(XK_SHIFT_L)?;
+
+        for ch in event.text.chars() {
+            let Some((keycode, needs_shift)) = self.keycode_for_keysym(keysym_for_char(ch))? else {
+                warn!("Cannot type '{}': no key produces it in the current layout", ch);
+                continue;
+            };
+
+            if needs_shift {
+                self.fake_input(KEY_PRESS_EVENT, shift, 0, 0)?;
+            }
+            self.fake_input(KEY_PRESS_EVENT, keycode, 0, 0)?;
+            self.fake_input(KEY_RELEASE_EVENT, keycode, 0, 0)?;
+            if needs_shift {
+                self.fake_input(KEY_RELEASE_EVENT, shift, 0, 0)?;
+            }
+        }
+
+        self.flush()?;
+        debug!("Injected {} characters of text", event.text.chars().count());
+        Ok(())
+    }
+}
+
+impl Default for InputInjector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn x11_button(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Right => 3,
+    }
+}
+
+fn modifier_keysym(modifier: KeyModifier) -> Option<u32> {
+    match modifier {
+        KeyModifier::Cmd | KeyModifier::Ctrl => Some(XK_CONTROL_L),
+        KeyModifier::Shift => Some(XK_SHIFT_L),
+        KeyModifier::Alt => Some(XK_ALT_L),
+        KeyModifier::Fn => None,
+    }
+}
+
+/// X keysym of a character (Latin-1 maps directly, the rest use Unicode keysyms)
+fn keysym_for_char(ch: char) -> u32 {
+    match ch {
+        '\n' | '\r' => XK_RETURN,
+        '\t' => XK_TAB,
+        ' '..='~' | '\u{a0}'..='\u{ff}' => ch as u32,
+        _ => 0x0100_0000 + ch as u32,
+    }
+}
+
+/// X keysym for a macOS virtual key code (US layout)
+fn keysym_for_mac_keycode(key_code: u16) -> Option<u32> {
+    let keysym = match key_code {
+        0 => 'a' as u32,
+        1 => 's' as u32,
+        2 => 'd' as u32,
+        3 => 'f' as u32,
+        4 => 'h' as u32,
+        5 => 'g' as u32,
+        6 => 'z' as u32,
+        7 => 'x' as u32,
+        8 => 'c' as u32,
+        9 => 'v' as u32,
+        11 => 'b' as u32,
+        12 => 'q' as u32,
+        13 => 'w' as u32,
+        14 => 'e' as u32,
+        15 => 'r' as u32,
+        16 => 'y' as u32,
+        17 => 't' as u32,
+        18 => '1' as u32,
+        19 => '2' as u32,
+        20 => '3' as u32,
+        21 => '4' as u32,
+        22 => '6' as u32,
+        23 => '5' as u32,
+        24 => '=' as u32,
+        25 => '9' as u32,
+        26 => '7' as u32,
+        27 => '-' as u32,
+        28 => '8' as u32,
+        29 => '0' as u32,
+        30 => ']' as u32,
+        31 => 'o' as u32,
+        32 => 'u' as u32,
+        33 => '[' as u32,
+        34 => 'i' as u32,
+        35 => 'p' as u32,
+        36 => XK_RETURN,
+        37 => 'l' as u32,
+        38 => 'j' as u32,
+        39 => '\'' as u32,
+        40 => 'k' as u32,
+        41 => ';' as u32,
+        42 => '\\' as u32,
+        43 => ',' as u32,
+        44 => '/' as u32,
+        45 => 'n' as u32,
+        46 => 'm' as u32,
+        47 => '.' as u32,
+        48 => XK_TAB,
+        49 => ' ' as u32,
+        50 => '`' as u32,
+        51 => 0xff08, // BackSpace
+        53 => 0xff1b, // Escape
+        96 => 0xffc2, // F5
+        97 => 0xffc3, // F6
+        98 => 0xffc4, // F7
+        99 => 0xffc0, // F3
+        100 => 0xffc5, // F8
+        101 => 0xffc6, // F9
+        103 => 0xffc8, // F11
+        109 => 0xffc7, // F10
+        111 => 0xffc9, // F12
+        115 => 0xff50, // Home
+        116 => 0xff55, // Page Up
+        117 => 0xffff, // Delete
+        118 => 0xffc1, // F4
+        119 => 0xff57, // End
+        120 => 0xffbf, // F2
+        121 => 0xff56, // Page Down
+        122 => 0xffbe, // F1
+        123 => 0xff51, // Left
+        124 => 0xff53, // Right
+        125 => 0xff54, // Down
+        126 => 0xff52, // Up
+        _ => return None,
+    };
+    Some(keysym)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keysym_mapping() {
+        assert_eq!(keysym_for_mac_keycode(0), Some('a' as u32));
+        assert_eq!(keysym_for_mac_keycode(36), Some(XK_RETURN));
+        assert_eq!(keysym_for_mac_keycode(126), Some(0xff52));
+        assert_eq!(keysym_for_mac_keycode(10), None);
+
+        assert_eq!(keysym_for_char('A'), 'A' as u32);
+        assert_eq!(keysym_for_char('é'), 0xe9);
+        assert_eq!(keysym_for_char('€'), 0x0100_20ac);
+        assert_eq!(keysym_for_char('\n'), XK_RETURN);
+    }
+}
@@ -5,7 +5,10 @@
 
 pub mod capture;
 pub mod config;
+pub mod diagnostics;
+pub mod http_client;
 pub mod input;
+pub mod relay;
 pub mod server;
 pub mod video;
 pub mod webrtc_handler;
@@ -5,29 +5,43 @@
 
 use anyhow::Result;
 use std::env;
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
 
 use blink_stream_server::capture;
 use blink_stream_server::config::Config;
+use blink_stream_server::diagnostics;
 use blink_stream_server::server::{mdns, Server};
 use blink_stream_server::video::VideoPipeline;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
+    // Initialize logging, plus structured diagnostics if BLINK_DIAGNOSTICS_FILE
+    // or BLINK_DIAGNOSTICS_ENDPOINT is set
+    let diagnostics_layer = diagnostics::layer_from_env()?;
+    let diagnostics_enabled = diagnostics_layer.is_some();
+    let fmt_layer = tracing_subscriber::fmt::layer()
         .with_target(true)
         .with_thread_ids(true)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+        .with_filter(LevelFilter::INFO);
+    tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(diagnostics_layer)
+        .try_init()?;
 
     info!("Starting Blink Stream Server");
+    if diagnostics_enabled {
+        for var in [diagnostics::FILE_ENV, diagnostics::ENDPOINT_ENV] {
+            if let Ok(target) = env::var(var) {
+                info!("Diagnostics enabled, writing to {}", target);
+            }
+        }
+    }
 
-    // Initialize ScreenCaptureKit bridge (required for Window Server access)
+    // Initialize the capture backend (ScreenCaptureKit on macOS, X11/PipeWire on Linux)
     capture::initialize()?;
-    info!("ScreenCaptureKit bridge initialized");
+    info!("{} capture backend initialized", capture::backend().name());
 
     // Initialize GStreamer for video processing
     VideoPipeline::init()?;
@@ -50,8 +64,8 @@ async fn main() -> Result<()> {
     let config = Config::new(port);
     let (vw, vh) = config.video_dimensions();
     info!(
-        "Configuration loaded: port={}, video={}x{}, scaling={}",
-        config.port, vw, vh, config.video_scaling_enabled
+        "Configuration loaded: port={}, video={}x{}, scaling={}, quality={:?}",
+        config.port, vw, vh, config.video_scaling_enabled, config.video_quality
     );
 
     // Start mDNS advertisement
@@ -0,0 +1,355 @@
+//! Relay mode: re-expose windows from other Blink stream-servers
+//!
+//! The relay connects to each upstream server like a regular client
+//! (WebSocket signaling plus a receive-only peer connection) and forwards
+//! RTP packets from upstream tracks into local tracks, so a client connects
+//! once and sees windows from several Macs. Upstream windows get local IDs
+//! from a reserved range so they cannot collide with local window IDs.
+
+mod upstream;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use blink_protocol::{ClientMessage, WindowInfo};
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::server::ServerState;
+
+/// First local ID handed out to upstream windows
+///
+/// macOS window IDs are small sequential numbers, far below this.
+const RELAY_ID_BASE: u32 = 0x8000_0000;
+
+/// Time allowed for an upstream server to answer a screenshot request
+pub const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Normalize an upstream address (`host:port` or a `ws://` URL) to a WebSocket URL
+pub fn upstream_url(address: &str) -> String {
+    if address.starts_with("ws://") || address.starts_with("wss://") {
+        address.to_string()
+    } else {
+        format!("ws://{}", address)
+    }
+}
+
+/// Mapping between local window IDs and (upstream index, remote window ID)
+///
+/// IDs stay stable across upstream reconnects.
+#[derive(Debug, Default)]
+pub struct RelayWindowIds {
+    next_offset: u32,
+    by_remote: HashMap<(usize, u32), u32>,
+    by_local: HashMap<u32, (usize, u32)>,
+}
+
+impl RelayWindowIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Local ID for an upstream window, allocated on first use
+    pub fn local_id(&mut self, upstream: usize, remote_id: u32) -> u32 {
+        if let Some(&local_id) = self.by_remote.get(&(upstream, remote_id)) {
+            return local_id;
+        }
+
+        let local_id = RELAY_ID_BASE + self.next_offset;
+        self.next_offset += 1;
+        self.by_remote.insert((upstream, remote_id), local_id);
+        self.by_local.insert(local_id, (upstream, remote_id));
+        local_id
+    }
+
+    /// Local ID for an upstream window, if one was allocated
+    pub fn existing_local_id(&self, upstream: usize, remote_id: u32) -> Option<u32> {
+        self.by_remote.get(&(upstream, remote_id)).copied()
+    }
+
+    /// Upstream index and remote window ID for a local ID
+    pub fn remote(&self, local_id: u32) -> Option<(usize, u32)> {
+        self.by_local.get(&local_id).copied()
+    }
+}
+
+/// State of one upstream server
+struct UpstreamSlot {
+    address: String,
+    /// Messages for the upstream connection (None while disconnected)
+    sender: RwLock<Option<mpsc::UnboundedSender<ClientMessage>>>,
+    /// Windows reported by the upstream, with local IDs
+    windows: RwLock<Vec<WindowInfo>>,
+    /// Remote IDs of subscribed windows, re-subscribed after reconnecting
+    subscribed: RwLock<HashSet<u32>>,
+}
+
+/// Screenshot requests waiting for their upstream, by (upstream, remote window ID)
+type PendingScreenshots = HashMap<(usize, u32), Vec<oneshot::Sender<String>>>;
+
+/// Connections to upstream servers and their windows
+pub struct RelayManager {
+    upstreams: Vec<UpstreamSlot>,
+    ids: RwLock<RelayWindowIds>,
+    screenshots: Mutex<PendingScreenshots>,
+}
+
+impl RelayManager {
+    /// Create a relay for the given upstream addresses (empty = relay disabled)
+    pub fn new(addresses: &[String]) -> Self {
+        Self {
+            upstreams: addresses
+                .iter()
+                .map(|address| UpstreamSlot {
+                    address: address.clone(),
+                    sender: RwLock::new(None),
+                    windows: RwLock::new(Vec::new()),
+                    subscribed: RwLock::new(HashSet::new()),
+                })
+                .collect(),
+            ids: RwLock::new(RelayWindowIds::new()),
+            screenshots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether any upstream servers are configured
+    pub fn is_enabled(&self) -> bool {
+        !self.upstreams.is_empty()
+    }
+
+    /// Start one connection task per upstream server
+    pub fn spawn(state: Arc<ServerState>, cancel: CancellationToken) {
+        for (index, slot) in state.relay.upstreams.iter().enumerate() {
+            info!("Relaying windows from {}", slot.address);
+            tokio::spawn(upstream::run_upstream(Arc::clone(&state), index, cancel.clone()));
+        }
+    }
+
+    /// Windows of all connected upstream servers, with local IDs
+    pub fn windows(&self) -> Vec<WindowInfo> {
+        self.upstreams
+            .iter()
+            .flat_map(|upstream| upstream.windows.read().clone())
+            .collect()
+    }
+
+    /// Whether a window ID refers to an upstream window
+    pub fn owns(&self, window_id: u32) -> bool {
+        window_id >= RELAY_ID_BASE && self.ids.read().remote(window_id).is_some()
+    }
+
+    /// Subscribe to an upstream window
+    pub fn subscribe(&self, window_id: u32) -> Result<()> {
+        let (upstream, remote_id) = self.resolve(window_id)?;
+        self.upstreams[upstream].subscribed.write().insert(remote_id);
+        self.send(upstream, ClientMessage::Subscribe { window_ids: vec![remote_id] })
+    }
+
+    /// Unsubscribe from an upstream window
+    ///
+    /// While the upstream is disconnected, the window is just not re-subscribed.
+    pub fn unsubscribe(&self, window_id: u32) -> Result<()> {
+        let (upstream, remote_id) = self.resolve(window_id)?;
+        let slot = &self.upstreams[upstream];
+        slot.subscribed.write().remove(&remote_id);
+        if slot.sender.read().is_none() {
+            return Ok(());
+        }
+        self.send(upstream, ClientMessage::Unsubscribe { window_ids: vec![remote_id] })
+    }
+
+    /// Forward a message about an upstream window, built with its remote ID
+    ///
+    /// Returns false (and sends nothing) if the window is not an upstream window.
+    pub fn forward<F>(&self, window_id: u32, message: F) -> Result<bool>
+    where
+        F: FnOnce(u32) -> ClientMessage,
+    {
+        if !self.owns(window_id) {
+            return Ok(false);
+        }
+
+        let (upstream, remote_id) = self.resolve(window_id)?;
+        self.send(upstream, message(remote_id))?;
+        Ok(true)
+    }
+
+    /// Ask the upstream of a window for a screenshot
+    ///
+    /// Returns a receiver for the base64 PNG, or None if the window is not an
+    /// upstream window. The receiver fails if the upstream disconnects first.
+    pub fn request_screenshot(
+        &self,
+        window_id: u32,
+        max_width: u32,
+        max_height: u32,
+    ) -> Result<Option<oneshot::Receiver<String>>> {
+        if !self.owns(window_id) {
+            return Ok(None);
+        }
+
+        let (upstream, remote_id) = self.resolve(window_id)?;
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut screenshots = self.screenshots.lock();
+            let waiting = screenshots.entry((upstream, remote_id)).or_default();
+            // Requests that were given up on
+            waiting.retain(|waiter| !waiter.is_closed());
+            waiting.push(sender);
+        }
+        self.send(upstream, ClientMessage::Screenshot { window_id: remote_id, max_width, max_height })?;
+        Ok(Some(receiver))
+    }
+
+    fn resolve(&self, window_id: u32) -> Result<(usize, u32)> {
+        self.ids
+            .read()
+            .remote(window_id)
+            .ok_or_else(|| anyhow!("Unknown relayed window {}", window_id))
+    }
+
+    fn send(&self, upstream: usize, message: ClientMessage) -> Result<()> {
+        let slot = &self.upstreams[upstream];
+        slot.sender
+            .read()
+            .as_ref()
+            .ok_or_else(|| anyhow!("Upstream {} is not connected", slot.address))?
+            .send(message)
+            .map_err(|_| anyhow!("Upstream {} connection closed", slot.address))
+    }
+
+    fn address(&self, upstream: usize) -> &str {
+        &self.upstreams[upstream].address
+    }
+
+    fn local_id(&self, upstream: usize, remote_id: u32) -> u32 {
+        self.ids.write().local_id(upstream, remote_id)
+    }
+
+    fn existing_local_id(&self, upstream: usize, remote_id: u32) -> Option<u32> {
+        self.ids.read().existing_local_id(upstream, remote_id)
+    }
+
+    fn set_sender(&self, upstream: usize, sender: Option<mpsc::UnboundedSender<ClientMessage>>) {
+        *self.upstreams[upstream].sender.write() = sender;
+    }
+
+    fn subscribed(&self, upstream: usize) -> Vec<u32> {
+        self.upstreams[upstream].subscribed.read().iter().copied().collect()
+    }
+
+    /// Replace the window list of an upstream, mapping IDs to local ones
+    fn set_windows(&self, upstream: usize, windows: Vec<WindowInfo>) {
+        let address = self.address(upstream).to_string();
+        let windows = windows
+            .into_iter()
+            .map(|window| WindowInfo {
+                id: self.local_id(upstream, window.id),
+                app: format!("{} ({})", window.app, address),
+                // Keep apps of different machines apart; remote PIDs mean nothing here
+                bundle_id: window.bundle_id.map(|bundle_id| format!("{}@{}", bundle_id, address)),
+                pid: None,
+                ..window
+            })
+            .collect();
+        *self.upstreams[upstream].windows.write() = windows;
+    }
+
+    /// Drop a closed upstream window
+    fn remove_window(&self, upstream: usize, remote_id: u32) {
+        let slot = &self.upstreams[upstream];
+        slot.subscribed.write().remove(&remote_id);
+        if let Some(local_id) = self.existing_local_id(upstream, remote_id) {
+            slot.windows.write().retain(|window| window.id != local_id);
+        }
+    }
+
+    /// Forget the windows of a disconnected upstream (subscriptions are kept)
+    fn clear_windows(&self, upstream: usize) {
+        self.upstreams[upstream].windows.write().clear();
+        self.screenshots.lock().retain(|&(index, _), _| index != upstream);
+    }
+
+    /// Hand a screenshot from an upstream to the requests waiting for it
+    fn screenshot_received(&self, upstream: usize, remote_id: u32, data: String) {
+        let waiting = self.screenshots.lock().remove(&(upstream, remote_id)).unwrap_or_default();
+        for waiter in waiting {
+            let _ = waiter.send(data.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relay_window_ids() {
+        let mut ids = RelayWindowIds::new();
+
+        let a = ids.local_id(0, 42);
+        let b = ids.local_id(1, 42);
+        assert_ne!(a, b);
+        assert!(a >= RELAY_ID_BASE && b >= RELAY_ID_BASE);
+
+        // Stable for the same upstream window
+        assert_eq!(ids.local_id(0, 42), a);
+        assert_eq!(ids.remote(b), Some((1, 42)));
+        assert_eq!(ids.existing_local_id(1, 7), None);
+        assert_eq!(ids.remote(42), None);
+    }
+
+    #[test]
+    fn test_relay_unsubscribe() {
+        let relay = RelayManager::new(&["studio.local:8080".to_string()]);
+        let local_id = relay.local_id(0, 42);
+
+        // Disconnected: only dropped from the subscriptions restored on reconnect
+        relay.upstreams[0].subscribed.write().insert(42);
+        relay.unsubscribe(local_id).unwrap();
+        assert!(relay.subscribed(0).is_empty());
+
+        let (sender, mut upstream) = mpsc::unbounded_channel();
+        relay.set_sender(0, Some(sender));
+        relay.subscribe(local_id).unwrap();
+        relay.unsubscribe(local_id).unwrap();
+        assert_eq!(upstream.try_recv().unwrap(), ClientMessage::Subscribe { window_ids: vec![42] });
+        assert_eq!(upstream.try_recv().unwrap(), ClientMessage::Unsubscribe { window_ids: vec![42] });
+        assert!(relay.subscribed(0).is_empty());
+    }
+
+    #[test]
+    fn test_relayed_screenshot() {
+        let relay = RelayManager::new(&["studio.local:8080".to_string()]);
+        let (sender, mut upstream) = mpsc::unbounded_channel();
+        relay.set_sender(0, Some(sender));
+        let local_id = relay.local_id(0, 42);
+
+        // Local windows are not relayed
+        assert!(relay.request_screenshot(7, 0, 0).unwrap().is_none());
+
+        let mut response = relay.request_screenshot(local_id, 640, 0).unwrap().unwrap();
+        assert_eq!(
+            upstream.try_recv().unwrap(),
+            ClientMessage::Screenshot { window_id: 42, max_width: 640, max_height: 0 }
+        );
+        relay.screenshot_received(0, 42, "iVBORw0K".to_string());
+        assert_eq!(response.try_recv().unwrap(), "iVBORw0K");
+
+        // Pending requests fail when the upstream disconnects
+        let mut response = relay.request_screenshot(local_id, 0, 0).unwrap().unwrap();
+        relay.clear_windows(0);
+        assert!(response.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_upstream_url() {
+        assert_eq!(upstream_url("studio.local:8080"), "ws://studio.local:8080");
+        assert_eq!(upstream_url("ws://10.0.0.2:8080"), "ws://10.0.0.2:8080");
+        assert_eq!(upstream_url("wss://mac.example.com"), "wss://mac.example.com");
+    }
+}
@@ -0,0 +1,301 @@
+//! Connection to a single upstream stream-server
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use blink_protocol::{ClientMessage, IceCandidate, ServerMessage};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::api::APIBuilder;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+use webrtc::rtp_transceiver::RTCRtpTransceiverInit;
+use webrtc::track::track_local::TrackLocalWriter;
+use webrtc::track::track_remote::TrackRemote;
+
+use super::upstream_url;
+use crate::server::ServerState;
+
+/// Delay before reconnecting to an upstream server
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Interval for re-requesting the upstream window list
+const WINDOW_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Keep a connection to an upstream server open until cancelled
+pub(super) async fn run_upstream(state: Arc<ServerState>, index: usize, cancel: CancellationToken) {
+    let url = upstream_url(state.relay.address(index));
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            result = connect(&state, index, &url) => {
+                match result {
+                    Ok(()) => info!("Relay upstream {} disconnected", url),
+                    Err(e) => warn!("Relay upstream {} failed: {}", url, e),
+                }
+            }
+        }
+
+        state.relay.set_sender(index, None);
+        state.relay.clear_windows(index);
+
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+        }
+    }
+
+    state.relay.set_sender(index, None);
+    info!("Relay upstream {} stopped", url);
+}
+
+/// Connect to an upstream server and relay it until the connection ends
+async fn connect(state: &Arc<ServerState>, index: usize, url: &str) -> Result<()> {
+    let (ws_stream, _) = connect_async(url).await?;
+    info!("Relay connected to upstream {}", url);
+
+    let (tx, rx) = mpsc::unbounded_channel::<ClientMessage>();
+    let peer_connection = create_peer_connection(Arc::clone(state), index, tx.clone()).await?;
+
+    let result = relay_session(state, index, ws_stream, &peer_connection, tx, rx).await;
+
+    if let Err(e) = peer_connection.close().await {
+        debug!("Error closing upstream peer connection: {}", e);
+    }
+    result
+}
+
+/// Signaling loop for one upstream connection
+async fn relay_session(
+    state: &Arc<ServerState>,
+    index: usize,
+    ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    peer_connection: &Arc<RTCPeerConnection>,
+    tx: mpsc::UnboundedSender<ClientMessage>,
+    mut rx: mpsc::UnboundedReceiver<ClientMessage>,
+) -> Result<()> {
+    let (mut write, mut read) = ws_stream.split();
+
+    // Receive-only offer, like a regular client
+    let offer = peer_connection.create_offer(None).await?;
+    peer_connection.set_local_description(offer.clone()).await?;
+    tx.send(ClientMessage::Offer { sdp: offer.sdp })?;
+
+    // Restore subscriptions from before a reconnect
+    let subscribed = state.relay.subscribed(index);
+    if !subscribed.is_empty() {
+        tx.send(ClientMessage::Subscribe { window_ids: subscribed })?;
+    }
+
+    state.relay.set_sender(index, Some(tx.clone()));
+
+    let mut refresh = tokio::time::interval(WINDOW_REFRESH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            outgoing = rx.recv() => {
+                let Some(outgoing) = outgoing else {
+                    return Ok(());
+                };
+                let json = serde_json::to_string(&outgoing)?;
+                write.send(Message::Text(json)).await?;
+            }
+
+            _ = refresh.tick() => {
+                tx.send(ClientMessage::GetWindows)?;
+            }
+
+            msg = read.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str::<ServerMessage>(&text) {
+                        Ok(message) => handle_upstream_message(state, index, peer_connection, &tx, message).await?,
+                        Err(e) => debug!("Ignoring upstream message: {}", e),
+                    },
+                    Some(Ok(Message::Ping(data))) => write.send(Message::Pong(data)).await?,
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                }
+            }
+        }
+    }
+}
+
+/// Handle a message from an upstream server
+async fn handle_upstream_message(
+    state: &ServerState,
+    index: usize,
+    peer_connection: &RTCPeerConnection,
+    tx: &mpsc::UnboundedSender<ClientMessage>,
+    message: ServerMessage,
+) -> Result<()> {
+    let relay = &state.relay;
+
+    match message {
+        ServerMessage::Answer { sdp } => {
+            peer_connection
+                .set_remote_description(RTCSessionDescription::answer(sdp)?)
+                .await?;
+        }
+
+        ServerMessage::Offer { sdp } => {
+            // Renegotiation after subscribing to a window
+            peer_connection
+                .set_remote_description(RTCSessionDescription::offer(sdp)?)
+                .await?;
+            let answer = peer_connection.create_answer(None).await?;
+            peer_connection.set_local_description(answer.clone()).await?;
+            tx.send(ClientMessage::Answer { sdp: answer.sdp })?;
+        }
+
+        ServerMessage::Ice { candidate } => {
+            peer_connection
+                .add_ice_candidate(RTCIceCandidateInit {
+                    candidate: candidate.candidate,
+                    sdp_mid: candidate.sdp_mid,
+                    sdp_mline_index: candidate.sdp_m_line_index,
+                    username_fragment: None,
+                })
+                .await?;
+        }
+
+        ServerMessage::WindowList { windows, .. } => {
+            relay.set_windows(index, windows);
+        }
+
+        ServerMessage::WindowClosed { id } => {
+            if let Some(local_id) = relay.existing_local_id(index, id) {
+                relay.remove_window(index, id);
+                state.broadcast(ServerMessage::WindowClosed { id: local_id });
+            }
+        }
+
+        ServerMessage::WindowBoundsChanged { window_id, bounds } => {
+            if let Some(window_id) = relay.existing_local_id(index, window_id) {
+                state.broadcast(ServerMessage::WindowBoundsChanged { window_id, bounds });
+            }
+        }
+
+        ServerMessage::CaptureError { window_id, message, retrying, attempt } => {
+            if let Some(window_id) = relay.existing_local_id(index, window_id) {
+                state.broadcast(ServerMessage::CaptureError { window_id, message, retrying, attempt });
+            }
+        }
+
+        ServerMessage::Screenshot { window_id, data } => {
+            relay.screenshot_received(index, window_id, data);
+        }
+
+        ServerMessage::Error { message, .. } => {
+            warn!("Relay upstream {} reported an error: {}", relay.address(index), message);
+        }
+
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Create the receive-only peer connection for an upstream server
+async fn create_peer_connection(
+    state: Arc<ServerState>,
+    index: usize,
+    tx: mpsc::UnboundedSender<ClientMessage>,
+) -> Result<Arc<RTCPeerConnection>> {
+    let mut media_engine = MediaEngine::default();
+    media_engine.register_default_codecs()?;
+    let api = APIBuilder::new().with_media_engine(media_engine).build();
+
+    let config = RTCConfiguration {
+        ice_servers: vec![RTCIceServer {
+            urls: vec!["stun:stun.l.google.com:19302".to_string()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let peer_connection = Arc::new(api.new_peer_connection(config).await?);
+
+    peer_connection.on_ice_candidate(Box::new(move |candidate| {
+        let tx = tx.clone();
+        Box::pin(async move {
+            let Some(candidate) = candidate else {
+                return;
+            };
+            match candidate.to_json() {
+                Ok(init) => {
+                    let _ = tx.send(ClientMessage::Ice {
+                        candidate: IceCandidate {
+                            candidate: init.candidate,
+                            sdp_mid: init.sdp_mid,
+                            sdp_m_line_index: init.sdp_mline_index,
+                        },
+                    });
+                }
+                Err(e) => debug!("Could not serialize ICE candidate: {}", e),
+            }
+        })
+    }));
+
+    peer_connection.on_track(Box::new(move |track, _, _| {
+        // Upstream tracks are named after their window ("window-<id>")
+        match track.id().strip_prefix("window-").and_then(|id| id.parse::<u32>().ok()) {
+            Some(remote_id) => {
+                tokio::spawn(forward_track(Arc::clone(&state), index, remote_id, track));
+            }
+            None => warn!("Ignoring upstream track with unexpected id {}", track.id()),
+        }
+        Box::pin(async {})
+    }));
+
+    peer_connection
+        .add_transceiver_from_kind(
+            RTPCodecType::Video,
+            Some(RTCRtpTransceiverInit {
+                direction: RTCRtpTransceiverDirection::Recvonly,
+                send_encodings: vec![],
+            }),
+        )
+        .await?;
+
+    Ok(peer_connection)
+}
+
+/// Copy RTP packets from an upstream track into the local track of the window
+async fn forward_track(state: Arc<ServerState>, index: usize, remote_id: u32, track: Arc<TrackRemote>) {
+    let local_id = state.relay.local_id(index, remote_id);
+    info!("Relaying upstream window {} as window {}", remote_id, local_id);
+
+    loop {
+        let packet = match track.read_rtp().await {
+            Ok((packet, _)) => packet,
+            Err(e) => {
+                debug!("Upstream track for window {} ended: {}", local_id, e);
+                break;
+            }
+        };
+
+        if state.is_stream_paused(local_id) {
+            continue;
+        }
+
+        let local_track = state.webrtc_manager.read().await.get_track(local_id);
+        if let Some(local_track) = local_track {
+            match local_track.write_rtp(&packet).await {
+                Ok(_) => state.sessions.record_bytes(local_id, packet.payload.len()),
+                Err(e) => debug!("Failed to relay packet for window {}: {}", local_id, e),
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Admin messages
+//!
+//! `reconfigure` changes the video output of the running server: active
+//! captures are updated in place, so no connection or track is dropped. It
+//! is only accepted with the token set in `BLINK_ADMIN_TOKEN`; without one,
+//! admin messages are rejected. `set_quality` also changes the output for
+//! every client, so once a token is set it needs the token as well.
+
+use std::borrow::Cow;
+
+use anyhow::{anyhow, bail, Result};
+use tracing::{info, warn};
+
+use crate::video::{QualityPreset, VideoConfig};
+
+use super::websocket::ServerMessage;
+use super::ServerState;
+
+/// Requested changes to the video output
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reconfigure {
+    pub preset: Option<QualityPreset>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub scaling: Option<bool>,
+}
+
+/// Check the admin token of a message
+pub fn authorize(state: &ServerState, token: &str) -> Result<()> {
+    let expected = state
+        .admin_token
+        .as_deref()
+        .ok_or_else(|| anyhow!("Admin messages are disabled (BLINK_ADMIN_TOKEN is not set)"))?;
+    if !token_matches(expected, token) {
+        warn!("Rejected admin message with an invalid token");
+        bail!("Invalid admin token");
+    }
+    Ok(())
+}
+
+/// Check the token of a message that is open while no admin token is set
+pub fn authorize_if_required(state: &ServerState, token: Option<&str>) -> Result<()> {
+    match state.admin_token {
+        Some(_) => authorize(state, token.unwrap_or_default()),
+        None => Ok(()),
+    }
+}
+
+/// A raw client message with the value of its `token` field masked, for logging
+pub fn redact_token(text: &str) -> Cow<'_, str> {
+    if !text.contains("\"token\"") {
+        return Cow::Borrowed(text);
+    }
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(mut message) => {
+            if let Some(token) = message.get_mut("token") {
+                *token = serde_json::Value::from("***");
+            }
+            Cow::Owned(message.to_string())
+        }
+        Err(_) => Cow::Borrowed("<unparsable message with a token>"),
+    }
+}
+
+/// Compare tokens in time independent of where they differ
+pub(crate) fn token_matches(expected: &str, token: &str) -> bool {
+    expected.len() == token.len()
+        && expected
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Video configuration after applying `changes` to `current`
+pub fn reconfigured(current: &VideoConfig, changes: Reconfigure) -> Result<VideoConfig> {
+    let mut video_config = changes.preset.map(VideoConfig::from).unwrap_or_else(|| current.clone());
+
+    match (changes.width, changes.height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => {
+            video_config.target_width = width;
+            video_config.target_height = height;
+        }
+        (None, None) => {}
+        _ => bail!("Output size needs a non-zero width and height"),
+    }
+    if let Some(scaling) = changes.scaling {
+        video_config.enable_scaling = scaling;
+    }
+    Ok(video_config)
+}
+
+/// Apply changes to the video output of every capture and notify all clients
+pub fn reconfigure(state: &ServerState, changes: Reconfigure) -> Result<VideoConfig> {
+    let video_config = reconfigured(&state.video_config(), changes)?;
+    *state.video_config.write() = video_config.clone();
+    state.capture_manager.apply_video_config(&video_config)?;
+    info!("Reconfigured video output: {:?}", video_config);
+
+    let (width, height) = video_config.max_dimensions();
+    state.broadcast(ServerMessage::Reconfigured {
+        width,
+        height,
+        scaling: video_config.enable_scaling,
+        bitrate: video_config.bitrate,
+        fps: video_config.fps,
+    });
+    Ok(video_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secret", "secreT"));
+        assert!(!token_matches("secret", "secret2"));
+        assert!(!token_matches("secret", ""));
+    }
+
+    #[test]
+    fn test_redact_token() {
+        let text = r#"{"type": "get_windows"}"#;
+        assert_eq!(redact_token(text), text);
+
+        let redacted = redact_token(r#"{"type": "reconfigure", "token": "s3cret", "preset": "high"}"#);
+        assert!(!redacted.contains("s3cret"));
+        assert!(redacted.contains(r#""preset":"high""#));
+
+        assert!(!redact_token(r#"{"token": "s3cret""#).contains("s3cret"));
+    }
+
+    #[test]
+    fn test_reconfigured() {
+        let current = VideoConfig::from(QualityPreset::Medium);
+
+        let changes = Reconfigure { scaling: Some(false), ..Default::default() };
+        let video_config = reconfigured(&current, changes).unwrap();
+        assert!(!video_config.enable_scaling);
+        assert_eq!(video_config.bitrate, current.bitrate);
+
+        // The preset is applied first, then the size
+        let changes = Reconfigure {
+            preset: Some(QualityPreset::High),
+            width: Some(2560),
+            height: Some(1440),
+            ..Default::default()
+        };
+        let video_config = reconfigured(&current, changes).unwrap();
+        assert_eq!((video_config.target_width, video_config.target_height), (2560, 1440));
+        assert_eq!(video_config.bitrate, QualityPreset::High.bitrate());
+
+        let changes = Reconfigure { width: Some(1280), ..Default::default() };
+        assert!(reconfigured(&current, changes).is_err());
+    }
+}
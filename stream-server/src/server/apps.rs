@@ -0,0 +1,346 @@
+//! Application grouping and app subscriptions
+//!
+//! Window lists are grouped by owning application. A client can subscribe to
+//! an application instead of a window ID: the server streams the app's
+//! frontmost window and switches to another of its windows when that comes to
+//! the front, replaces a closed one, or the app restarts with new window IDs.
+//! Follows belong to the client that sent `subscribe_app` and end with
+//! `unsubscribe_app` or when the client disconnects.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine;
+use blink_protocol::AppInfo;
+use parking_lot::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use super::client::ClientId;
+use super::websocket::ServerMessage;
+use super::ServerState;
+use crate::capture::{CaptureManager, WindowInfo};
+
+/// Edge length of app icons in window lists (pixels)
+const APP_ICON_SIZE: u32 = 64;
+
+/// Group windows (listed frontmost first) by application, frontmost app first
+///
+/// Windows without a bundle ID are left out.
+pub fn group_by_app(windows: &[WindowInfo]) -> Vec<AppInfo> {
+    let mut apps: Vec<AppInfo> = Vec::new();
+    for window in windows {
+        let Some(bundle_id) = &window.bundle_id else {
+            continue;
+        };
+        match apps.iter_mut().find(|app| &app.bundle_id == bundle_id) {
+            Some(app) => app.window_ids.push(window.id),
+            None => apps.push(AppInfo {
+                bundle_id: bundle_id.clone(),
+                name: window.app.clone(),
+                pid: window.pid,
+                icon: None,
+                window_ids: vec![window.id],
+            }),
+        }
+    }
+    apps
+}
+
+/// Frontmost window of an application
+pub fn frontmost_window(windows: &[WindowInfo], bundle_id: &str) -> Option<u32> {
+    windows
+        .iter()
+        .find(|window| window.bundle_id.as_deref() == Some(bundle_id))
+        .map(|window| window.id)
+}
+
+/// The streamed window of a followed app changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FollowChange {
+    pub client_id: ClientId,
+    pub bundle_id: String,
+    pub previous: Option<u32>,
+    pub current: Option<u32>,
+}
+
+/// App icons and the apps followed through `subscribe_app`
+pub struct AppTracker {
+    /// Base64 PNG icons by bundle ID (None when the app has no icon)
+    icons: Mutex<HashMap<String, Option<String>>>,
+    /// Streamed window of each app followed by a client
+    follows: Mutex<HashMap<(ClientId, String), Option<u32>>>,
+}
+
+impl AppTracker {
+    pub fn new() -> Self {
+        Self {
+            icons: Mutex::new(HashMap::new()),
+            follows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Apps of a window list, with icons
+    pub fn apps(&self, windows: &[WindowInfo], capture_manager: &CaptureManager) -> Vec<AppInfo> {
+        let mut apps = group_by_app(windows);
+        let mut icons = self.icons.lock();
+        for app in &mut apps {
+            let Some(pid) = app.pid else {
+                continue;
+            };
+            app.icon = icons
+                .entry(app.bundle_id.clone())
+                .or_insert_with(|| {
+                    capture_manager
+                        .app_icon(pid, APP_ICON_SIZE)
+                        .map(|png| base64::engine::general_purpose::STANDARD.encode(png))
+                })
+                .clone();
+        }
+        apps
+    }
+
+    /// Follow an app for a client, currently streaming `window_id`
+    pub fn follow(&self, client_id: ClientId, bundle_id: &str, window_id: Option<u32>) {
+        self.follows.lock().insert((client_id, bundle_id.to_string()), window_id);
+    }
+
+    /// Stop following an app, returning the window that was streamed for it
+    pub fn unfollow(&self, client_id: ClientId, bundle_id: &str) -> Option<u32> {
+        self.follows.lock().remove(&(client_id, bundle_id.to_string())).flatten()
+    }
+
+    /// Stop every follow of a client, returning the windows streamed for them
+    pub fn unfollow_client(&self, client_id: ClientId) -> Vec<u32> {
+        let mut windows = Vec::new();
+        self.follows.lock().retain(|(follower, _), streamed| {
+            if *follower != client_id {
+                return true;
+            }
+            windows.extend(*streamed);
+            false
+        });
+        windows
+    }
+
+    pub fn has_follows(&self) -> bool {
+        !self.follows.lock().is_empty()
+    }
+
+    /// Whether a follow currently streams a window
+    pub fn is_followed(&self, window_id: u32) -> bool {
+        self.follows.lock().values().any(|streamed| *streamed == Some(window_id))
+    }
+
+    /// Point follows of a window at the window that replaced it
+    pub fn replace_window(&self, window_id: u32, new_id: u32) {
+        for streamed in self.follows.lock().values_mut() {
+            if *streamed == Some(window_id) {
+                *streamed = Some(new_id);
+            }
+        }
+    }
+
+    /// Record the frontmost window of every followed app and return the ones that changed
+    pub fn update_follows(&self, windows: &[WindowInfo]) -> Vec<FollowChange> {
+        let mut follows = self.follows.lock();
+        let mut changes = Vec::new();
+        for ((client_id, bundle_id), streamed) in follows.iter_mut() {
+            let current = frontmost_window(windows, bundle_id);
+            if current != *streamed {
+                changes.push(FollowChange {
+                    client_id: *client_id,
+                    bundle_id: bundle_id.clone(),
+                    previous: *streamed,
+                    current,
+                });
+                *streamed = current;
+            }
+        }
+        changes
+    }
+}
+
+impl Default for AppTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Switch followed apps to their frontmost window every `interval` until cancelled
+pub async fn run_app_follow(state: Arc<ServerState>, interval: Duration, cancel: CancellationToken) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = ticker.tick() => {
+                if !state.apps.has_follows() {
+                    continue;
+                }
+
+                // Window enumeration blocks on ScreenCaptureKit
+                let state_for_windows = Arc::clone(&state);
+                let windows = match tokio::task::spawn_blocking(move || state_for_windows.windows()).await {
+                    Ok(windows) => windows,
+                    Err(e) => {
+                        warn!("App follow task failed: {}", e);
+                        continue;
+                    }
+                };
+
+                // Several clients may follow the same app; release each window once
+                let mut switched: Vec<(u32, Option<u32>)> = Vec::new();
+                for change in state.apps.update_follows(&windows) {
+                    let current = change.current;
+                    if let Some(previous) = switch_window(&state, change).await {
+                        if !switched.iter().any(|(window_id, _)| *window_id == previous) {
+                            switched.push((previous, current));
+                        }
+                    }
+                }
+                for (previous, current) in switched {
+                    release_followed_window(&state, &windows, previous, current).await;
+                }
+            }
+        }
+    }
+
+    debug!("App follow stopped");
+}
+
+/// Stream the new frontmost window of a followed app
+///
+/// Returns the previously streamed window, which the follow no longer needs.
+async fn switch_window(state: &ServerState, change: FollowChange) -> Option<u32> {
+    info!(
+        "Following {} for {}: window {:?} -> {:?}",
+        change.bundle_id, change.client_id, change.previous, change.current
+    );
+
+    let mut offer = None;
+    if let Some(window_id) = change.current {
+        match state.subscribe_window(window_id).await {
+            Ok(sdp) => offer = sdp,
+            Err(e) => {
+                // Keep the previous window and retry on the next tick
+                warn!("Failed to switch {} to window {}: {}", change.bundle_id, window_id, e);
+                state.apps.follow(change.client_id, &change.bundle_id, change.previous);
+                return None;
+            }
+        }
+    }
+
+    state.send_to(
+        change.client_id,
+        ServerMessage::AppWindowChanged {
+            bundle_id: change.bundle_id.clone(),
+            window_id: change.current,
+        },
+    );
+    if let (Some(sdp), Some(window_id)) = (offer, change.current) {
+        state.send_offer_to_peer(sdp).await;
+        if let Err(e) = state.request_keyframe(window_id) {
+            debug!("Could not request keyframe for {}: {}", window_id, e);
+        }
+    }
+
+    change.previous
+}
+
+/// Stop streaming a window a follow moved away from, unless a client still wants it
+async fn release_followed_window(state: &ServerState, windows: &[WindowInfo], window_id: u32, current: Option<u32>) {
+    if state.is_window_wanted(window_id) {
+        debug!("Window {} is still wanted by another client", window_id);
+        return;
+    }
+
+    // Sessions viewing the window are now viewing the app's new window
+    let current = current.and_then(|id| windows.iter().find(|w| w.id == id));
+    if let Err(e) = state.sessions.window_replaced(window_id, current).await {
+        warn!("Failed to log the switch from window {}: {}", window_id, e);
+    }
+    state.unsubscribe_window(window_id).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::test_window;
+
+    fn window(id: u32, bundle_id: Option<&str>) -> WindowInfo {
+        WindowInfo {
+            bundle_id: bundle_id.map(str::to_string),
+            pid: Some(100),
+            ..test_window(id, "App", &format!("Window {}", id))
+        }
+    }
+
+    #[test]
+    fn test_group_by_app() {
+        let windows = [
+            window(3, Some("com.apple.Terminal")),
+            window(1, Some("com.apple.Safari")),
+            window(2, Some("com.apple.Terminal")),
+            window(4, None),
+        ];
+
+        let apps = group_by_app(&windows);
+        assert_eq!(apps.len(), 2);
+        assert_eq!(apps[0].bundle_id, "com.apple.Terminal");
+        assert_eq!(apps[0].window_ids, [3, 2]);
+        assert_eq!(apps[1].window_ids, [1]);
+        assert_eq!(frontmost_window(&windows, "com.apple.Terminal"), Some(3));
+        assert_eq!(frontmost_window(&windows, "com.apple.Notes"), None);
+    }
+
+    #[test]
+    fn test_update_follows() {
+        let tracker = AppTracker::new();
+        let client_id = ClientId::next();
+        tracker.follow(client_id, "com.apple.Terminal", Some(3));
+
+        let windows = [window(3, Some("com.apple.Terminal")), window(2, Some("com.apple.Terminal"))];
+        assert!(tracker.update_follows(&windows).is_empty());
+
+        // Another Terminal window came to the front
+        let windows = [window(2, Some("com.apple.Terminal")), window(3, Some("com.apple.Terminal"))];
+        let changes = tracker.update_follows(&windows);
+        assert_eq!(
+            changes,
+            [FollowChange {
+                client_id,
+                bundle_id: "com.apple.Terminal".to_string(),
+                previous: Some(3),
+                current: Some(2),
+            }]
+        );
+
+        // Terminal quit, then restarted with a new window
+        assert_eq!(tracker.update_follows(&[])[0].current, None);
+        let changes = tracker.update_follows(&[window(9, Some("com.apple.Terminal"))]);
+        assert_eq!((changes[0].previous, changes[0].current), (None, Some(9)));
+    }
+
+    #[test]
+    fn test_follows_per_client() {
+        let tracker = AppTracker::new();
+        let (a, b) = (ClientId::next(), ClientId::next());
+        tracker.follow(a, "com.apple.Terminal", Some(3));
+        tracker.follow(b, "com.apple.Terminal", Some(3));
+        tracker.follow(b, "com.apple.Safari", Some(5));
+
+        // Window 3 stays followed until both clients let go
+        assert_eq!(tracker.unfollow(a, "com.apple.Terminal"), Some(3));
+        assert_eq!(tracker.unfollow(a, "com.apple.Terminal"), None);
+        assert!(tracker.is_followed(3));
+
+        tracker.replace_window(5, 6);
+        let mut windows = tracker.unfollow_client(b);
+        windows.sort_unstable();
+        assert_eq!(windows, [3, 6]);
+        assert!(!tracker.has_follows());
+    }
+}
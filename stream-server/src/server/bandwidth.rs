@@ -0,0 +1,156 @@
+//! Outgoing bandwidth budget
+//!
+//! With several windows streamed at once, each encoding at the preset bitrate,
+//! the uplink of the host can saturate. When a budget is configured it is
+//! divided among the local captures, weighted by their encoded frame size,
+//! and each encoder is set to its share.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use super::ServerState;
+
+/// Interval for redistributing the budget
+const BUDGET_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Lowest bitrate a track is given, even if that exceeds the budget (bits/s)
+const MIN_TRACK_BITRATE: u32 = 250_000;
+
+/// Frame area assumed for windows of unknown size streamed at native size (1080p)
+const UNKNOWN_FRAME_AREA: f64 = 1920.0 * 1080.0;
+
+/// Divide `budget` (bits/s) among tracks in proportion to their weights
+///
+/// No track gets more than `max_bitrate` or less than `MIN_TRACK_BITRATE`;
+/// bandwidth left over by capped tracks goes to the others.
+pub fn allocate(budget: u32, max_bitrate: u32, weights: &[(u32, f64)]) -> HashMap<u32, u32> {
+    let mut allocation = HashMap::new();
+    let mut remaining: Vec<(u32, f64)> = weights.iter().map(|&(id, weight)| (id, weight.max(1.0))).collect();
+    let mut budget = budget as f64;
+
+    // Fix capped tracks first, then split what is left among the rest
+    loop {
+        let total_weight: f64 = remaining.iter().map(|(_, weight)| weight).sum();
+        let capped: Vec<(u32, f64)> = remaining
+            .iter()
+            .copied()
+            .filter(|(_, weight)| budget * weight / total_weight >= max_bitrate as f64)
+            .collect();
+        if capped.is_empty() {
+            break;
+        }
+        for (id, _) in &capped {
+            allocation.insert(*id, max_bitrate);
+            budget -= max_bitrate as f64;
+        }
+        remaining.retain(|(id, _)| !allocation.contains_key(id));
+    }
+
+    let total_weight: f64 = remaining.iter().map(|(_, weight)| weight).sum();
+    for (id, weight) in remaining {
+        let share = (budget.max(0.0) * weight / total_weight) as u32;
+        allocation.insert(id, share.clamp(MIN_TRACK_BITRATE.min(max_bitrate), max_bitrate));
+    }
+    allocation
+}
+
+/// Keep encoder bitrates of local captures within `budget` (bits/s) until cancelled
+pub async fn run_bandwidth_budget(state: Arc<ServerState>, budget: u32, cancel: CancellationToken) {
+    info!("Bandwidth budget of {} kbit/s enabled", budget / 1000);
+    let mut ticker = tokio::time::interval(BUDGET_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = ticker.tick() => apply_budget(&state, budget),
+        }
+    }
+
+    debug!("Bandwidth budget stopped");
+}
+
+/// Set every local capture to its share of the budget
+fn apply_budget(state: &ServerState, budget: u32) {
+    let window_ids = state.capture_manager.active_window_ids();
+    if window_ids.is_empty() {
+        return;
+    }
+
+    // Weighted by encoded frame area: the window size, limited by the output size
+    let video_config = state.video_config();
+    let (max_width, max_height) = video_config.max_dimensions();
+    let weights: Vec<(u32, f64)> = window_ids
+        .iter()
+        .map(|&window_id| {
+            let area = state
+                .input_injector
+                .window_bounds(window_id)
+                .map(|bounds| bounds.width * bounds.height);
+            (window_id, frame_area(area, max_width, max_height))
+        })
+        .collect();
+
+    for (window_id, bitrate) in allocate(budget, video_config.bitrate, &weights) {
+        if let Err(e) = state.capture_manager.set_bitrate(window_id, bitrate) {
+            warn!("Failed to set bitrate of window {}: {}", window_id, e);
+        }
+    }
+}
+
+/// Encoded frame area of a window of `area` (None if unknown)
+///
+/// A maximum output size of 0 means windows are streamed at native size.
+fn frame_area(area: Option<f64>, max_width: u32, max_height: u32) -> f64 {
+    let max_area = match (max_width, max_height) {
+        (0, _) | (_, 0) => f64::INFINITY,
+        _ => max_width as f64 * max_height as f64,
+    };
+    match area {
+        Some(area) => area.min(max_area),
+        None if max_area.is_finite() => max_area,
+        None => UNKNOWN_FRAME_AREA,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate() {
+        // Split by weight
+        let allocation = allocate(6_000_000, 6_000_000, &[(1, 3.0), (2, 1.0)]);
+        assert_eq!(allocation[&1], 4_500_000);
+        assert_eq!(allocation[&2], 1_500_000);
+
+        // Leftover of a capped track goes to the others
+        let allocation = allocate(10_000_000, 6_000_000, &[(1, 3.0), (2, 1.0), (3, 1.0)]);
+        assert_eq!(allocation[&1], 6_000_000);
+        assert_eq!(allocation[&2], 2_000_000);
+        assert_eq!(allocation[&3], 2_000_000);
+
+        // Everything fits
+        let allocation = allocate(20_000_000, 6_000_000, &[(1, 3.0), (2, 1.0)]);
+        assert_eq!((allocation[&1], allocation[&2]), (6_000_000, 6_000_000));
+
+        // Tiny tracks keep a usable bitrate
+        let allocation = allocate(1_000_000, 6_000_000, &[(1, 1000.0), (2, 1.0)]);
+        assert_eq!(allocation[&2], MIN_TRACK_BITRATE);
+    }
+
+    #[test]
+    fn test_frame_area() {
+        assert_eq!(frame_area(Some(4_000_000.0), 1280, 720), 1280.0 * 720.0);
+        assert_eq!(frame_area(None, 1280, 720), 1280.0 * 720.0);
+
+        // Native size keeps windows apart
+        assert_eq!(frame_area(Some(4_000_000.0), 0, 0), 4_000_000.0);
+        assert_eq!(frame_area(Some(100_000.0), 0, 0), 100_000.0);
+        assert_eq!(frame_area(None, 0, 0), UNKNOWN_FRAME_AREA);
+    }
+}
@@ -0,0 +1,88 @@
+//! Periodic window bounds refresh
+//!
+//! Windows can move or resize while they are streamed. This re-queries the
+//! bounds of captured windows and propagates changes to the input injector,
+//! the capture output size and connected clients.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use super::websocket::ServerMessage;
+use super::ServerState;
+use crate::capture::WindowBounds;
+
+/// Re-query bounds of captured windows every `interval` until cancelled
+pub async fn run_bounds_refresh(state: Arc<ServerState>, interval: Duration, cancel: CancellationToken) {
+    info!("Window bounds refresh started (every {:?})", interval);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = ticker.tick() => {
+                // Window enumeration blocks on ScreenCaptureKit
+                let state_for_refresh = Arc::clone(&state);
+                let changes = match tokio::task::spawn_blocking(move || refresh_bounds(&state_for_refresh)).await {
+                    Ok(changes) => changes,
+                    Err(e) => {
+                        warn!("Bounds refresh task failed: {}", e);
+                        continue;
+                    }
+                };
+
+                for (window_id, bounds) in changes {
+                    state.broadcast(ServerMessage::WindowBoundsChanged { window_id, bounds });
+                }
+            }
+        }
+    }
+
+    info!("Window bounds refresh stopped");
+}
+
+/// Compare current bounds of captured windows against the injector cache
+///
+/// Updates the cache (and the capture size on resize) for every window that
+/// moved or resized, and returns the changed windows.
+fn refresh_bounds(state: &ServerState) -> Vec<(u32, WindowBounds)> {
+    let window_ids = state.capture_manager.active_window_ids();
+    if window_ids.is_empty() {
+        return Vec::new();
+    }
+
+    let windows = state.capture_manager.get_windows();
+    let mut changes = Vec::new();
+
+    for window_id in window_ids {
+        // Windows that disappeared are handled by capture error recovery
+        let Some(window) = windows.iter().find(|w| w.id == window_id) else {
+            continue;
+        };
+
+        let previous = state.input_injector.window_bounds(window_id);
+        if previous.as_ref() == Some(&window.bounds) {
+            continue;
+        }
+
+        debug!("Window {} bounds changed: {:?} -> {:?}", window_id, previous, window.bounds);
+        state.input_injector.update_window_bounds(window_id, window.bounds.clone());
+
+        let resized = previous
+            .map(|p| p.width != window.bounds.width || p.height != window.bounds.height)
+            .unwrap_or(false);
+        if resized {
+            let (width, height) = (window.bounds.width as u32, window.bounds.height as u32);
+            if let Err(e) = state.capture_manager.update_source_size(window_id, width, height) {
+                warn!("Failed to follow resize of window {}: {}", window_id, e);
+            }
+        }
+
+        changes.push((window_id, window.bounds.clone()));
+    }
+
+    changes
+}
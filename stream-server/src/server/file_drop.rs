@@ -0,0 +1,231 @@
+//! File drops onto streamed windows
+//!
+//! Files of a `file_drop` message are written to a new directory below
+//! `blink-drops` in the system temp directory and then handed to the window
+//! by the capture backend. Their content is sent inline (base64) or fetched
+//! from a plain `http://` URL, e.g. a file server on the client's network.
+//! URLs resolving to loopback or link-local addresses are refused, so a client
+//! cannot reach services only the server itself can see.
+//!
+//! The files are left in place so the application can keep using them; drops
+//! older than a day are deleted when a new drop arrives.
+
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use tracing::{debug, info, warn};
+
+use crate::http_client;
+use crate::input::{DroppedFile, FileDrop};
+
+use super::ServerState;
+
+/// Directory below the system temp directory that holds dropped files
+const DROP_DIR: &str = "blink-drops";
+
+/// Largest total size of the files of one drop
+pub const MAX_DROP_SIZE: usize = 64 * 1024 * 1024;
+
+/// Time allowed for fetching one file by URL
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long dropped files are kept
+const DROP_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Write the files of a drop and hand them to the window
+///
+/// Returns the paths the files were written to.
+pub async fn drop_files(state: &Arc<ServerState>, drop: &FileDrop) -> Result<Vec<PathBuf>> {
+    // Window enumeration blocks on ScreenCaptureKit
+    let state_for_windows = Arc::clone(state);
+    let window = tokio::task::spawn_blocking(move || state_for_windows.windows())
+        .await?
+        .into_iter()
+        .find(|window| window.id == drop.window_id)
+        .ok_or_else(|| anyhow!("Window {} not found", drop.window_id))?;
+
+    let paths = receive_files(&drop.files).await?;
+
+    // The backend waits for the application to accept the files
+    let (x, y, dropped) = (drop.x, drop.y, paths.clone());
+    tokio::task::spawn_blocking(move || crate::capture::drop_files(&window, x, y, &dropped)).await??;
+
+    info!("Dropped {} files onto window {}", paths.len(), drop.window_id);
+    Ok(paths)
+}
+
+/// Write dropped files to a new temp directory
+async fn receive_files(files: &[DroppedFile]) -> Result<Vec<PathBuf>> {
+    if files.is_empty() {
+        bail!("No files to drop");
+    }
+
+    let mut names = Vec::with_capacity(files.len());
+    for file in files {
+        let name = sanitize_name(&file.name).ok_or_else(|| anyhow!("Invalid file name: {:?}", file.name))?;
+        if names.contains(&name) {
+            bail!("File {} is dropped twice", name);
+        }
+        names.push(name);
+    }
+
+    // Pruning old drops walks the whole drop directory
+    let dir = tokio::task::spawn_blocking(new_drop_dir).await??;
+    let mut paths = Vec::with_capacity(files.len());
+    let mut total = 0;
+    for (file, name) in files.iter().zip(names) {
+        let content = file_content(file, MAX_DROP_SIZE - total).await?;
+        total += content.len();
+
+        let path = dir.join(name);
+        tokio::fs::write(&path, content)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        debug!("Wrote dropped file {}", path.display());
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Create an empty directory for the files of one drop
+fn new_drop_dir() -> Result<PathBuf> {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    let root = std::env::temp_dir().join(DROP_DIR);
+    prune_drop_dirs(&root, DROP_RETENTION);
+
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let dir = root.join(format!("{}-{}", millis, NEXT.fetch_add(1, Ordering::Relaxed)));
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Delete drop directories below `root` last modified more than `max_age` ago
+fn prune_drop_dirs(root: &Path, max_age: Duration) {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified.elapsed().unwrap_or_default() >= max_age);
+        if expired {
+            match std::fs::remove_dir_all(entry.path()) {
+                Ok(()) => debug!("Deleted old drop {}", entry.path().display()),
+                Err(e) => warn!("Failed to delete old drop {}: {}", entry.path().display(), e),
+            }
+        }
+    }
+}
+
+/// Name a dropped file can safely be written under
+///
+/// Directories are stripped, so a name cannot point outside the drop
+/// directory. Returns None for names that are empty after that.
+fn sanitize_name(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let name: String = name.chars().filter(|c| !c.is_control()).collect();
+    let name = name.trim();
+    match name {
+        "" | "." | ".." => None,
+        _ => Some(name.to_string()),
+    }
+}
+
+/// Content of a dropped file, at most `limit` bytes
+async fn file_content(file: &DroppedFile, limit: usize) -> Result<Vec<u8>> {
+    let content = match (&file.data, &file.url) {
+        (Some(data), _) => base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .with_context(|| format!("Invalid base64 data for {}", file.name))?,
+        (None, Some(url)) => {
+            let url = url.clone();
+            tokio::task::spawn_blocking(move || fetch_http(&url, limit)).await??
+        }
+        (None, None) => bail!("File {} has neither data nor url", file.name),
+    };
+
+    if content.len() > limit {
+        bail!("Dropped files exceed {} MiB", MAX_DROP_SIZE / (1024 * 1024));
+    }
+    Ok(content)
+}
+
+/// Fetch the body of a plain `http://` URL, failing if it exceeds `limit` bytes
+///
+/// Blocks until the file is fetched or `FETCH_TIMEOUT` has passed.
+fn fetch_http(url: &str, limit: usize) -> Result<Vec<u8>> {
+    let (authority, path) = http_client::parse_http_url(url)?;
+
+    // Connect to the checked address rather than resolving the name again
+    let address = http_client::resolve(authority)?
+        .into_iter()
+        .find(|address| !is_blocked_address(address.ip()))
+        .ok_or_else(|| anyhow!("Refusing to fetch {}: loopback and link-local addresses are not allowed", url))?;
+    let body = http_client::get(address, authority, path, limit, FETCH_TIMEOUT)
+        .with_context(|| format!("Failed to fetch {}", url))?;
+    debug!("Fetched {} bytes from {}", body.len(), url);
+    Ok(body)
+}
+
+/// Whether dropped files must not be fetched from `ip`
+///
+/// Loopback and link-local addresses lead to services of the server itself,
+/// like cloud metadata endpoints, rather than to the client's network.
+fn is_blocked_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast() || ip.is_multicast()
+        }
+        IpAddr::V6(ip) => {
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (ip.segments()[0] & 0xffc0) == 0xfe80
+                || ip.to_ipv4_mapped().is_some_and(|ip| is_blocked_address(IpAddr::V4(ip)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("report.pdf").as_deref(), Some("report.pdf"));
+        assert_eq!(sanitize_name("../../etc/passwd").as_deref(), Some("passwd"));
+        assert_eq!(sanitize_name("C:\\Users\\me\\notes.txt").as_deref(), Some("notes.txt"));
+        assert_eq!(sanitize_name(" a\u{0}b.txt ").as_deref(), Some("ab.txt"));
+        assert_eq!(sanitize_name("dir/"), None);
+        assert_eq!(sanitize_name(".."), None);
+    }
+
+    #[test]
+    fn test_is_blocked_address() {
+        for ip in ["127.0.0.1", "169.254.169.254", "0.0.0.0", "::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(is_blocked_address(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["192.168.1.20", "10.0.0.5", "fd00::5"] {
+            assert!(!is_blocked_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_prune_drop_dirs() {
+        let root = std::env::temp_dir().join(format!("blink-drops-test-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("1-1")).unwrap();
+
+        prune_drop_dirs(&root, Duration::from_secs(3600));
+        assert!(root.join("1-1").exists());
+
+        prune_drop_dirs(&root, Duration::ZERO);
+        assert!(!root.join("1-1").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
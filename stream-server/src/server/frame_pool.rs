@@ -0,0 +1,110 @@
+//! Reusable buffers for encoded frames
+//!
+//! The encoder callback copies every frame out of Swift-owned memory. Instead
+//! of allocating a fresh `Vec` per frame, buffers are taken from a pool and
+//! returned when the frame task drops them. The pool is bounded in both the
+//! number of idle buffers and their capacity, so an occasional huge keyframe
+//! does not stay pinned in memory.
+
+use std::ops::Deref;
+
+use parking_lot::Mutex;
+
+/// Pool of reusable frame buffers
+pub struct FramePool {
+    free: Mutex<Vec<Vec<u8>>>,
+    /// Maximum number of idle buffers kept
+    max_buffers: usize,
+    /// Buffers that grew beyond this capacity are freed instead of pooled
+    max_capacity: usize,
+}
+
+impl FramePool {
+    pub const fn new(max_buffers: usize, max_capacity: usize) -> Self {
+        Self {
+            free: Mutex::new(Vec::new()),
+            max_buffers,
+            max_capacity,
+        }
+    }
+
+    /// Copy `data` into a pooled buffer
+    pub fn copy_from(&self, data: &[u8]) -> PooledBuffer<'_> {
+        let mut buffer = self.free.lock().pop().unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(data);
+        PooledBuffer { buffer, pool: self }
+    }
+
+    /// Number of idle buffers in the pool
+    pub fn idle(&self) -> usize {
+        self.free.lock().len()
+    }
+
+    fn release(&self, buffer: Vec<u8>) {
+        if buffer.capacity() > self.max_capacity {
+            return;
+        }
+
+        let mut free = self.free.lock();
+        if free.len() < self.max_buffers {
+            free.push(buffer);
+        }
+    }
+}
+
+/// Frame data borrowed from a [`FramePool`], returned to it on drop
+pub struct PooledBuffer<'a> {
+    buffer: Vec<u8>,
+    pool: &'a FramePool,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.release(std::mem::take(&mut self.buffer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_pool_reuses_buffers() {
+        let pool = FramePool::new(2, 1024);
+
+        let first = pool.copy_from(&[1, 2, 3]);
+        assert_eq!(&*first, &[1, 2, 3]);
+        let ptr = first.as_ptr();
+        drop(first);
+        assert_eq!(pool.idle(), 1);
+
+        // The released allocation is handed out again
+        let second = pool.copy_from(&[4, 5]);
+        assert_eq!(&*second, &[4, 5]);
+        assert_eq!(second.as_ptr(), ptr);
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[test]
+    fn test_frame_pool_is_bounded() {
+        let pool = FramePool::new(2, 1024);
+
+        let buffers: Vec<_> = (0..4).map(|_| pool.copy_from(&[0; 16])).collect();
+        drop(buffers);
+        assert_eq!(pool.idle(), 2);
+
+        // Oversized buffers are freed rather than kept
+        let pool = FramePool::new(2, 1024);
+        drop(pool.copy_from(&[0; 4096]));
+        assert_eq!(pool.idle(), 0);
+    }
+}
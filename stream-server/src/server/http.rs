@@ -0,0 +1,202 @@
+//! Plain HTTP endpoints on the WebSocket port
+//!
+//! Connections whose request line targets one of these routes are answered
+//! directly instead of being upgraded to a WebSocket:
+//!
+//! - `GET /sessions?limit=N` - session history as JSON (most recent first)
+//! - `GET /metrics` - frame pipeline and per-track encoder metrics for Prometheus
+//!
+//! The session history names peers and the windows they viewed, so it needs
+//! `Authorization: Bearer <BLINK_ADMIN_TOKEN>`; without an admin token it is
+//! only served to requests from this machine.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+use super::admin::token_matches;
+use super::stats::prometheus_metrics;
+use super::ServerState;
+
+/// Sessions returned by `/sessions` without a `limit`
+const DEFAULT_SESSION_LIMIT: u32 = 100;
+
+/// Upper bound for the `limit` query parameter
+const MAX_SESSION_LIMIT: u32 = 1000;
+
+/// Largest request head read before answering
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Time allowed for the request line to arrive
+const REQUEST_LINE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Delay between peeks at an incomplete request line
+const PEEK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Request target of a plain HTTP request on `stream`, without consuming it
+///
+/// Returns None for WebSocket handshakes and anything that is not one of the
+/// HTTP routes.
+pub async fn http_route(stream: &TcpStream) -> Result<Option<String>> {
+    let mut buf = [0u8; 512];
+    let deadline = tokio::time::Instant::now() + REQUEST_LINE_TIMEOUT;
+    loop {
+        // Peeking returns what has arrived so far, which may be part of the line
+        let n = stream.peek(&mut buf).await?;
+        let data = &buf[..n];
+        let complete = n == 0 || n == buf.len() || data.windows(2).any(|w| w == b"\r\n");
+        if complete || tokio::time::Instant::now() >= deadline {
+            return Ok(request_target(data)
+                .filter(|target| matches!(route(target), "/sessions" | "/metrics"))
+                .map(str::to_string));
+        }
+        tokio::time::sleep(PEEK_INTERVAL).await;
+    }
+}
+
+/// Answer a plain HTTP request
+pub async fn serve(mut stream: TcpStream, target: &str, state: &ServerState) -> Result<()> {
+    // Consume the request head so the client sees a clean response
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_SIZE {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+
+    debug!("HTTP request for {}", target);
+    let local = stream.peer_addr().is_ok_and(|addr| addr.ip().is_loopback());
+    let (status, content_type, body) = if route(target) == "/metrics" {
+        (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            prometheus_metrics(&state.frame_timings, &state.picture_quality),
+        )
+    } else if !sessions_allowed(state.admin_token.as_deref(), &head, local) {
+        warn!("Rejected unauthorized request for {}", target);
+        (
+            "401 Unauthorized",
+            "application/json",
+            serde_json::json!({ "error": "Session history needs the admin token" }).to_string(),
+        )
+    } else {
+        match state.sessions.sessions(session_limit(target)).await {
+            Ok(sessions) => ("200 OK", "application/json", serde_json::to_string(&sessions)?),
+            Err(e) => (
+                "500 Internal Server Error",
+                "application/json",
+                serde_json::json!({ "error": e.to_string() }).to_string(),
+            ),
+        }
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Target of a `GET` request line
+fn request_target(data: &[u8]) -> Option<&str> {
+    let end = data.windows(2).position(|w| w == b"\r\n")?;
+    let line = std::str::from_utf8(&data[..end]).ok()?;
+    let mut parts = line.split(' ');
+    if parts.next()? != "GET" {
+        return None;
+    }
+    parts.next()
+}
+
+/// Path of a request target, without the query
+fn route(target: &str) -> &str {
+    target.split('?').next().unwrap_or(target)
+}
+
+/// Whether a request may read the session history
+///
+/// With an admin token the request must carry it as a bearer token; without
+/// one only local requests are allowed.
+fn sessions_allowed(admin_token: Option<&str>, head: &[u8], local: bool) -> bool {
+    let Some(expected) = admin_token else {
+        return local;
+    };
+    std::str::from_utf8(head)
+        .ok()
+        .and_then(|head| {
+            head.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim().eq_ignore_ascii_case("authorization").then(|| value.trim())
+            })
+        })
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token_matches(expected, token.trim()))
+}
+
+/// `limit` query parameter of a `/sessions` request
+fn session_limit(target: &str) -> u32 {
+    target
+        .split_once('?')
+        .and_then(|(_, query)| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("limit="))
+                .and_then(|limit| limit.parse().ok())
+        })
+        .unwrap_or(DEFAULT_SESSION_LIMIT)
+        .min(MAX_SESSION_LIMIT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_target_and_limit() {
+        let target = request_target(b"GET /sessions?limit=5 HTTP/1.1\r\nHost: mac\r\n\r\n").unwrap();
+        assert_eq!(route(target), "/sessions");
+        assert_eq!(session_limit(target), 5);
+
+        assert_eq!(session_limit("/sessions"), DEFAULT_SESSION_LIMIT);
+        assert_eq!(session_limit("/sessions?limit=99999"), MAX_SESSION_LIMIT);
+        assert_eq!(request_target(b"POST /sessions HTTP/1.1\r\n"), None);
+        // Request line not complete yet
+        assert_eq!(request_target(b"GET /sess"), None);
+    }
+
+    #[test]
+    fn test_sessions_allowed() {
+        let head = b"GET /sessions HTTP/1.1\r\nHost: mac\r\nauthorization: Bearer s3cret\r\n\r\n";
+        assert!(sessions_allowed(Some("s3cret"), head, false));
+        assert!(!sessions_allowed(Some("other"), head, true));
+        assert!(!sessions_allowed(Some("s3cret"), b"GET /sessions HTTP/1.1\r\n\r\n", true));
+
+        // Without an admin token only local requests are answered
+        assert!(sessions_allowed(None, head, true));
+        assert!(!sessions_allowed(None, head, false));
+    }
+
+    #[tokio::test]
+    async fn test_http_route_split_request_line() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        client.write_all(b"GET /metr").await.unwrap();
+        let route = tokio::spawn(async move { http_route(&server).await.unwrap() });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        client.write_all(b"ics HTTP/1.1\r\n\r\n").await.unwrap();
+        assert_eq!(route.await.unwrap().as_deref(), Some("/metrics"));
+    }
+}
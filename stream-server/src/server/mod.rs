@@ -1,30 +1,83 @@
 //! WebSocket server module
 
+pub mod admin;
+pub mod apps;
+pub mod bandwidth;
+pub mod bounds;
+pub mod client;
+pub mod file_drop;
+pub mod frame_pool;
+pub mod http;
 pub mod mdns;
+pub mod reattach;
+pub mod send_queue;
+pub mod sessions;
+pub mod stats;
+pub mod subscriptions;
 pub mod websocket;
 
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::Result;
 use parking_lot::RwLock as SyncRwLock;
 use tokio::net::TcpListener;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{broadcast, mpsc, RwLock};
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
 
-use crate::capture::{CaptureManager, EncodedFrame, set_frame_callback};
+use crate::capture::{
+    CaptureManager, EncodedFrame, RestartAction, WindowBounds, WindowInfo, set_capture_error_callback,
+    set_frame_callback, set_idle_fps,
+};
 use crate::config::Config;
 use crate::input::InputInjector;
-use crate::video::{VideoConfig, Viewport};
+use crate::relay::RelayManager;
+use crate::video::{QualityPreset, VideoConfig, Viewport};
 use crate::webrtc_handler::{WebRtcManager, H264RtpPacketizer};
+use apps::AppTracker;
+use client::ClientId;
+use frame_pool::{FramePool, PooledBuffer};
+use send_queue::{QueuedFrame, SendQueues};
+use sessions::SessionLog;
+use stats::{FrameStage, FrameTimings, PictureQuality};
+use subscriptions::Subscriptions;
+use websocket::{ClientMessage, ServerMessage};
+
+/// Capacity of the server event broadcast channel
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// Idle encoded-frame buffers kept for reuse
+const FRAME_POOL_BUFFERS: usize = 8;
+
+/// Largest buffer kept in the frame pool (bigger keyframes are freed after use)
+const FRAME_POOL_MAX_CAPACITY: usize = 4 * 1024 * 1024;
 
 /// Frame data to be sent via channel (owned version of EncodedFrame)
 struct FrameData {
     window_id: u32,
     timestamp_ms: u64,
-    data: Vec<u8>,
+    is_keyframe: bool,
+    data: PooledBuffer<'static>,
+    /// When the encoder callback handed the frame over
+    received_at: Instant,
+}
+
+/// Server-initiated message on the event channel
+#[derive(Debug, Clone)]
+pub struct ServerEvent {
+    pub message: ServerMessage,
+    /// Only this client receives the message (None = every client)
+    pub client_id: Option<ClientId>,
+}
+
+/// Capture error reported by the Swift bridge (owned copy for the channel)
+struct CaptureError {
+    window_id: u32,
+    message: String,
 }
 
 /// Optional frame saver for debugging/testing
@@ -77,19 +130,47 @@ fn get_frame_saver() -> &'static Option<FrameSaver> {
     })
 }
 
+/// Buffers for frames copied out of the encoder callback
+static FRAME_POOL: FramePool = FramePool::new(FRAME_POOL_BUFFERS, FRAME_POOL_MAX_CAPACITY);
+
 /// Global channel sender for frame callback
 static FRAME_SENDER: SyncRwLock<Option<mpsc::UnboundedSender<FrameData>>> = SyncRwLock::new(None);
 
+/// Global channel sender for capture error callback
+static CAPTURE_ERROR_SENDER: SyncRwLock<Option<mpsc::UnboundedSender<CaptureError>>> = SyncRwLock::new(None);
+
 /// Shared server state
 pub struct ServerState {
     pub capture_manager: CaptureManager,
     pub webrtc_manager: RwLock<WebRtcManager>,
     pub input_injector: InputInjector,
     pub rtp_packetizer: H264RtpPacketizer,
-    /// Video configuration for scaling
-    pub video_config: VideoConfig,
+    /// Video configuration for scaling, bitrate and FPS (switchable at runtime)
+    pub video_config: SyncRwLock<VideoConfig>,
     /// Viewport per window (for crop/zoom)
     pub viewports: SyncRwLock<HashMap<u32, Viewport>>,
+    /// Server-initiated messages pushed to connected clients
+    pub events: broadcast::Sender<ServerEvent>,
+    /// Per-stage latency of the frame pipeline
+    pub frame_timings: FrameTimings,
+    /// Encoder output statistics per track
+    pub picture_quality: PictureQuality,
+    /// Paused streams, with whether their capture was stopped too
+    pub paused_streams: SyncRwLock<HashMap<u32, bool>>,
+    /// Windows relayed from other stream-servers (disabled without upstreams)
+    pub relay: RelayManager,
+    /// History of client sessions
+    pub sessions: SessionLog,
+    /// App icons and apps followed through `subscribe_app`
+    pub apps: AppTracker,
+    /// Windows each client subscribed to through `subscribe`
+    pub subscriptions: Subscriptions,
+    /// Local windows being streamed, as they were when subscribed
+    pub streamed_windows: SyncRwLock<HashMap<u32, WindowInfo>>,
+    /// Whether streams move to the replacement when an app recreates a window
+    pub reattach_windows: bool,
+    /// Token required for admin messages (None = admin messages disabled)
+    pub admin_token: Option<String>,
 }
 
 impl ServerState {
@@ -103,9 +184,184 @@ impl ServerState {
             webrtc_manager: RwLock::new(WebRtcManager::new()),
             input_injector: InputInjector::new(),
             rtp_packetizer: H264RtpPacketizer::new(),
-            video_config,
+            video_config: SyncRwLock::new(video_config),
             viewports: SyncRwLock::new(HashMap::new()),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            frame_timings: FrameTimings::new(),
+            picture_quality: PictureQuality::new(),
+            paused_streams: SyncRwLock::new(HashMap::new()),
+            relay: RelayManager::new(&[]),
+            sessions: SessionLog::in_memory(),
+            apps: AppTracker::new(),
+            subscriptions: Subscriptions::new(),
+            streamed_windows: SyncRwLock::new(HashMap::new()),
+            reattach_windows: true,
+            admin_token: None,
+        }
+    }
+    
+    /// Push a message to all connected clients
+    pub fn broadcast(&self, message: ServerMessage) {
+        // Sending only fails when no client is connected
+        let _ = self.events.send(ServerEvent { message, client_id: None });
+    }
+    
+    /// Push a message to one connected client
+    pub fn send_to(&self, client_id: ClientId, message: ServerMessage) {
+        let _ = self.events.send(ServerEvent { message, client_id: Some(client_id) });
+    }
+    
+    /// Send a renegotiation offer to the client that owns the peer connection
+    ///
+    /// Other clients have no peer connection the offer could apply to.
+    pub async fn send_offer_to_peer(&self, sdp: String) {
+        match self.webrtc_manager.read().await.client_id() {
+            Some(client_id) => self.send_to(client_id, ServerMessage::Offer { sdp }),
+            None => debug!("No peer connection to send the renegotiation offer to"),
+        }
+    }
+    
+    /// Get the current video configuration
+    pub fn video_config(&self) -> VideoConfig {
+        self.video_config.read().clone()
+    }
+    
+    /// Switch to a quality preset and return the resulting video configuration
+    ///
+    /// Only updates the stored config; callers apply it to active captures.
+    pub fn set_quality(&self, preset: QualityPreset) -> VideoConfig {
+        let video_config = VideoConfig::from(preset);
+        *self.video_config.write() = video_config.clone();
+        info!("Quality preset set to {:?}: {:?}", preset, video_config);
+        video_config
+    }
+    
+    /// Stop sending frames for a window (the track stays in place)
+    pub fn pause_stream(&self, window_id: u32, stop_capture: bool) {
+        let mut paused = self.paused_streams.write();
+        *paused.entry(window_id).or_insert(false) |= stop_capture;
+        info!("Paused stream for window {} (capture stopped: {})", window_id, paused[&window_id]);
+    }
+    
+    /// Resume sending frames for a window
+    ///
+    /// Returns whether the capture was stopped while paused, or `None` if the
+    /// stream was not paused.
+    pub fn resume_stream(&self, window_id: u32) -> Option<bool> {
+        let capture_stopped = self.paused_streams.write().remove(&window_id)?;
+        info!("Resumed stream for window {}", window_id);
+        Some(capture_stopped)
+    }
+    
+    /// Local windows followed by windows relayed from upstream servers
+    pub fn windows(&self) -> Vec<WindowInfo> {
+        let mut windows = self.capture_manager.get_windows();
+        windows.extend(self.relay.windows());
+        windows
+    }
+    
+    /// Window list message, with the windows grouped by application
+    pub fn window_list(&self) -> ServerMessage {
+        let windows = self.windows();
+        let apps = self.apps.apps(&windows, &self.capture_manager);
+        ServerMessage::WindowList { windows, apps }
+    }
+    
+    /// Bounds of a local or relayed window, without enumerating windows
+    ///
+    /// Local windows are known once they have been subscribed.
+    pub fn window_bounds(&self, window_id: u32) -> Option<WindowBounds> {
+        if self.relay.owns(window_id) {
+            return self
+                .relay
+                .windows()
+                .into_iter()
+                .find(|window| window.id == window_id)
+                .map(|window| window.bounds);
+        }
+        self.input_injector.window_bounds(window_id)
+    }
+    
+    /// Start streaming a local or relayed window
+    ///
+    /// Returns the renegotiation offer for the client if a track was added.
+    pub async fn subscribe_window(&self, window_id: u32) -> Result<Option<String>> {
+        if self.relay.owns(window_id) {
+            // Captured by the upstream server; its packets are forwarded to our track
+            self.relay.subscribe(window_id)?;
+        } else {
+            self.capture_manager.start_capture(window_id, &self.video_config())?;
+            
+            let window = self.capture_manager.get_windows().into_iter().find(|w| w.id == window_id);
+            if let Some(window) = window {
+                // Update input injector with window bounds for coordinate conversion
+                self.input_injector.update_window_bounds(window_id, window.bounds);
+                debug!("Updated input bounds for window {}", window_id);
+                self.streamed_windows.write().insert(window_id, window);
+            }
+        }
+        
+        self.webrtc_manager.write().await.add_window_track(window_id).await
+    }
+    
+    /// Stop streaming a window and drop its track
+    pub async fn unsubscribe_window(&self, window_id: u32) {
+        if self.relay.owns(window_id) {
+            if let Err(e) = self.relay.unsubscribe(window_id) {
+                debug!("Could not unsubscribe from relayed window {}: {}", window_id, e);
+            }
+        } else if let Err(e) = self.capture_manager.stop_capture(window_id) {
+            debug!("Could not stop capture for window {}: {}", window_id, e);
+        }
+        if let Err(e) = self.webrtc_manager.write().await.remove_window_track(window_id).await {
+            debug!("Could not remove track for window {}: {}", window_id, e);
+        }
+        self.picture_quality.remove(window_id);
+        self.streamed_windows.write().remove(&window_id);
+        self.subscriptions.forget_window(window_id);
+        if let Err(e) = self.sessions.window_closed(window_id).await {
+            warn!("Failed to log the end of window {}: {}", window_id, e);
+        }
+    }
+    
+    /// Whether a client still subscribes to a window or follows its app
+    pub fn is_window_wanted(&self, window_id: u32) -> bool {
+        self.subscriptions.is_subscribed(window_id) || self.apps.is_followed(window_id)
+    }
+    
+    /// Stop streaming a window a client let go of, unless another client still wants it
+    pub async fn release_window(&self, window_id: u32) {
+        if self.is_window_wanted(window_id) {
+            debug!("Window {} is still wanted by another client", window_id);
+            return;
+        }
+        self.unsubscribe_window(window_id).await;
+    }
+    
+    /// Drop the subscriptions and app follows of a client that disconnected
+    pub async fn client_disconnected(&self, client_id: ClientId) {
+        let mut windows = self.subscriptions.remove_client(client_id);
+        windows.extend(self.apps.unfollow_client(client_id));
+        windows.sort_unstable();
+        windows.dedup();
+        for window_id in windows {
+            self.release_window(window_id).await;
+        }
+    }
+    
+    /// Request a keyframe for a local or relayed window
+    pub fn request_keyframe(&self, window_id: u32) -> Result<()> {
+        if self.relay.forward(window_id, |window_id| ClientMessage::RequestKeyframe { window_id })? {
+            crate::diag!(window_id, relayed = true, "Keyframe requested");
+            return Ok(());
         }
+        crate::diag!(window_id, relayed = false, "Keyframe requested");
+        crate::capture::request_keyframe(window_id)
+    }
+    
+    /// Whether frames for a window are currently withheld
+    pub fn is_stream_paused(&self, window_id: u32) -> bool {
+        self.paused_streams.read().contains_key(&window_id)
     }
     
     /// Set viewport for a window
@@ -165,10 +421,13 @@ extern "C" fn on_encoded_frame(frame_ptr: *const EncodedFrame) {
         let frame_data = FrameData {
             window_id: frame.window_id,
             timestamp_ms: frame.timestamp_ms,
-            data: data.to_vec(),
+            is_keyframe: frame.is_keyframe,
+            data: FRAME_POOL.copy_from(data),
+            received_at: Instant::now(),
         };
         
         if let Err(e) = sender.send(frame_data) {
+            crate::diag!(window_id = frame.window_id, reason = "channel closed", "Frame dropped");
             error!("Failed to send frame to channel: {}", e);
         }
     } else {
@@ -176,6 +435,95 @@ extern "C" fn on_encoded_frame(frame_ptr: *const EncodedFrame) {
     }
 }
 
+/// Capture error callback that receives stream failures from Swift and sends via channel
+extern "C" fn on_capture_error(window_id: u32, message_ptr: *const c_char) {
+    let message = if message_ptr.is_null() {
+        "Capture stream stopped".to_string()
+    } else {
+        unsafe { CStr::from_ptr(message_ptr) }.to_string_lossy().into_owned()
+    };
+    
+    warn!("Capture error for window {}: {}", window_id, message);
+    
+    if let Some(sender) = CAPTURE_ERROR_SENDER.read().as_ref() {
+        if let Err(e) = sender.send(CaptureError { window_id, message }) {
+            error!("Failed to send capture error to channel: {}", e);
+        }
+    }
+}
+
+/// Restart a failed capture with backoff, notifying clients of each attempt
+///
+/// Moves the stream over if the app replaced the window with a new one. Gives
+/// up (and reports the window as closed) once the restart policy is exhausted.
+async fn recover_capture(state: Arc<ServerState>, window_id: u32, mut message: String) {
+    if !state.capture_manager.mark_failed(window_id) {
+        debug!("Capture for window {} already recovering or stopped", window_id);
+        return;
+    }
+    
+    loop {
+        if state.reattach_windows && reattach::try_reattach(&state, window_id).await {
+            return;
+        }
+        
+        match state.capture_manager.next_restart(window_id) {
+            RestartAction::Retry { attempt, delay } => {
+                info!("Restarting capture for window {} in {:?} (attempt {})", window_id, delay, attempt);
+                crate::diag!(
+                    window_id,
+                    attempt,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %message,
+                    "Capture restart scheduled"
+                );
+                state.broadcast(ServerMessage::CaptureError {
+                    window_id,
+                    message: message.clone(),
+                    retrying: true,
+                    attempt,
+                });
+                
+                tokio::time::sleep(delay).await;
+                
+                match state.capture_manager.restart_capture(window_id) {
+                    Ok(()) => {
+                        crate::diag!(window_id, attempt, "Capture restarted");
+                        // Fresh encoder state for the client's decoder
+                        if let Err(e) = crate::capture::request_keyframe(window_id) {
+                            debug!("Could not request keyframe for {}: {}", window_id, e);
+                        }
+                        return;
+                    }
+                    Err(e) => {
+                        crate::diag!(window_id, attempt, error = %e, "Capture restart failed");
+                        debug!("Restart attempt {} for window {} failed: {}", attempt, window_id, e);
+                        message = e.to_string();
+                    }
+                }
+            }
+            RestartAction::GiveUp => {
+                crate::diag!(window_id, error = %message, "Capture restarts exhausted");
+                state.broadcast(ServerMessage::CaptureError {
+                    window_id,
+                    message,
+                    retrying: false,
+                    attempt: 0,
+                });
+                if let Err(e) = state.webrtc_manager.write().await.remove_window_track(window_id).await {
+                    debug!("Could not remove track for window {}: {}", window_id, e);
+                }
+                state.picture_quality.remove(window_id);
+                state.streamed_windows.write().remove(&window_id);
+                state.subscriptions.forget_window(window_id);
+                state.broadcast(ServerMessage::WindowClosed { id: window_id });
+                return;
+            }
+            RestartAction::Ignore => return,
+        }
+    }
+}
+
 /// Main WebSocket server
 pub struct Server {
     config: Config,
@@ -191,19 +539,29 @@ impl Server {
     /// Create a server with a custom cancellation token for graceful shutdown
     pub fn with_cancel_token(config: Config, cancel_token: CancellationToken) -> Self {
         // Create video config from server config
-        let (target_width, target_height) = config.video_dimensions();
-        let video_config = VideoConfig {
-            target_width,
-            target_height,
-            enable_scaling: config.video_scaling_enabled,
-        };
-        
-        let state = Arc::new(ServerState::with_video_config(video_config));
+        let mut state = ServerState::with_video_config(config.video_config());
+        state.relay = RelayManager::new(&config.relay_upstreams);
+        state.rtp_packetizer = H264RtpPacketizer::with_pacing_rate(config.pacing_rate);
+        state.reattach_windows = config.reattach_windows;
+        state.admin_token = config.admin_token.clone();
+        if let Some(path) = &config.session_db {
+            match SessionLog::open(path) {
+                Ok(sessions) => state.sessions = sessions,
+                Err(e) => warn!("Failed to open session log {}, keeping sessions in memory: {}", path, e),
+            }
+        }
+        let state = Arc::new(state);
         
         // Register the frame callback
         set_frame_callback(on_encoded_frame);
         info!("Frame callback registered for video streaming");
         
+        // Register the capture error callback for automatic restart
+        set_capture_error_callback(on_capture_error);
+        
+        // Throttle static windows (applies to every capture)
+        set_idle_fps(config.idle_fps);
+        
         Self {
             config,
             state,
@@ -241,6 +599,7 @@ impl Server {
         tokio::spawn(async move {
             info!("Frame processing task started");
             let mut frame_count: u64 = 0;
+            let mut send_queues = SendQueues::new(Arc::clone(&state_for_frames));
             
             loop {
                 tokio::select! {
@@ -254,12 +613,24 @@ impl Server {
                         };
                         
                         frame_count += 1;
+                        let timings = &state_for_frames.frame_timings;
+                        timings.record_frame();
+                        timings.record(FrameStage::Channel, frame.received_at.elapsed());
+                        state_for_frames.picture_quality.record(frame.window_id, &frame.data, frame.is_keyframe);
+                        
+                        if state_for_frames.is_stream_paused(frame.window_id) {
+                            crate::diag!(window_id = frame.window_id, frame = frame_count, reason = "paused", "Frame dropped");
+                            continue;
+                        }
                         
                         // Get the track for this window
                         let webrtc = state_for_frames.webrtc_manager.read().await;
-                        let track = match webrtc.get_track(frame.window_id) {
-                            Some(t) => t,
-                            None => {
+                        // Tracks only exist on a client's peer connection
+                        let (track, client_id) = match (webrtc.get_track(frame.window_id), webrtc.client_id()) {
+                            (Some(t), Some(client_id)) => (t, client_id),
+                            _ => {
+                                send_queues.remove(frame.window_id);
+                                crate::diag!(window_id = frame.window_id, frame = frame_count, reason = "no track", "Frame dropped");
                                 if frame_count % 30 == 1 {
                                     debug!("No track for window {} (frame #{})", frame.window_id, frame_count);
                                 }
@@ -273,17 +644,22 @@ impl Server {
                         
                         // Log every 30th frame
                         if frame_count % 30 == 1 {
-                            info!("Sending frame #{} for window {}, size={} bytes", 
-                                  frame_count, frame.window_id, frame.data.len());
+                            info!("Sending frame #{} for window {} to {}, size={} bytes", 
+                                  frame_count, frame.window_id, client_id, frame.data.len());
                         }
                         
-                        // Packetize and send
-                        if let Err(e) = state_for_frames.rtp_packetizer
-                            .packetize_and_send(&track, &frame.data, rtp_timestamp)
-                            .await 
-                        {
-                            debug!("Failed to send frame: {}", e);
-                        }
+                        // Packetize here, send (paced) on the track's own task
+                        let packetize_start = Instant::now();
+                        let packets = state_for_frames.rtp_packetizer.packetize(&frame.data, rtp_timestamp);
+                        timings.record(FrameStage::Packetize, packetize_start.elapsed());
+                        
+                        send_queues.send(track, QueuedFrame {
+                            window_id: frame.window_id,
+                            frame_number: frame_count,
+                            packets,
+                            bytes: frame.data.len(),
+                            received_at: frame.received_at,
+                        });
                     }
                 }
             }
@@ -291,6 +667,60 @@ impl Server {
             info!("Frame processing task ended");
         });
         
+        // Create channel for capture errors
+        let (error_tx, mut error_rx) = mpsc::unbounded_channel::<CaptureError>();
+        *CAPTURE_ERROR_SENDER.write() = Some(error_tx);
+        
+        // Spawn capture recovery task
+        let state_for_errors = Arc::clone(&self.state);
+        let cancel_for_errors = self.cancel_token.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = cancel_for_errors.cancelled() => break,
+                    capture_error = error_rx.recv() => {
+                        let Some(capture_error) = capture_error else {
+                            break;
+                        };
+                        tokio::spawn(recover_capture(
+                            Arc::clone(&state_for_errors),
+                            capture_error.window_id,
+                            capture_error.message,
+                        ));
+                    }
+                }
+            }
+        });
+        
+        // Spawn window bounds refresh and app follow tasks (same polling interval)
+        if self.config.bounds_refresh_ms > 0 {
+            let interval = std::time::Duration::from_millis(self.config.bounds_refresh_ms);
+            tokio::spawn(bounds::run_bounds_refresh(
+                Arc::clone(&self.state),
+                interval,
+                self.cancel_token.clone(),
+            ));
+            tokio::spawn(apps::run_app_follow(
+                Arc::clone(&self.state),
+                interval,
+                self.cancel_token.clone(),
+            ));
+        }
+        
+        // Share the outgoing bandwidth budget among captures
+        if self.config.bandwidth_budget > 0 {
+            tokio::spawn(bandwidth::run_bandwidth_budget(
+                Arc::clone(&self.state),
+                self.config.bandwidth_budget,
+                self.cancel_token.clone(),
+            ));
+        }
+        
+        // Connect to upstream servers in relay mode
+        if self.state.relay.is_enabled() {
+            RelayManager::spawn(Arc::clone(&self.state), self.cancel_token.clone());
+        }
+        
         let addr = format!("0.0.0.0:{}", self.config.port);
         let listener = TcpListener::bind(&addr).await?;
 
@@ -336,8 +766,9 @@ impl Server {
 
 impl Drop for Server {
     fn drop(&mut self) {
-        // Clear the channel sender
+        // Clear the channel senders
         let mut sender_guard = FRAME_SENDER.write();
         *sender_guard = None;
+        *CAPTURE_ERROR_SENDER.write() = None;
     }
 }
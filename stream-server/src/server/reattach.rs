@@ -0,0 +1,129 @@
+//! Reattaching streams to recreated windows
+//!
+//! Some apps replace a window with a new one (new window ID, same app and
+//! title), e.g. when switching documents or after a renderer restart. When a
+//! streamed window's capture fails and the window is gone, the stream is
+//! moved to the matching new window and clients get a `window_replaced`
+//! message instead of `window_closed`.
+
+use std::sync::Arc;
+
+use tracing::{debug, info, warn};
+
+use super::websocket::ServerMessage;
+use super::ServerState;
+use crate::capture::WindowInfo;
+
+/// Whether two windows belong to the same application
+fn same_app(a: &WindowInfo, b: &WindowInfo) -> bool {
+    match (&a.bundle_id, &b.bundle_id) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.app == b.app,
+    }
+}
+
+/// Window that replaces `old` in a window list
+///
+/// Prefers a window of the same app with the same title. Without one, the
+/// app's only window that is not already being captured is taken. Windows in
+/// `captured` are never matched.
+pub fn find_replacement<'a>(old: &WindowInfo, windows: &'a [WindowInfo], captured: &[u32]) -> Option<&'a WindowInfo> {
+    let candidates: Vec<&WindowInfo> = windows
+        .iter()
+        .filter(|window| window.id != old.id && !captured.contains(&window.id) && same_app(window, old))
+        .collect();
+
+    if let Some(window) = candidates.iter().find(|window| window.title == old.title) {
+        return Some(window);
+    }
+    match candidates.as_slice() {
+        [only] => Some(only),
+        _ => None,
+    }
+}
+
+/// Move the stream of a vanished window to its replacement, if there is one
+///
+/// Returns true if the stream was reattached.
+pub async fn try_reattach(state: &Arc<ServerState>, window_id: u32) -> bool {
+    let Some(old) = state.streamed_windows.read().get(&window_id).cloned() else {
+        return false;
+    };
+
+    // Window enumeration blocks on ScreenCaptureKit
+    let state_for_windows = Arc::clone(state);
+    let windows = match tokio::task::spawn_blocking(move || state_for_windows.windows()).await {
+        Ok(windows) => windows,
+        Err(e) => {
+            warn!("Window enumeration for reattaching window {} failed: {}", window_id, e);
+            return false;
+        }
+    };
+    if windows.iter().any(|window| window.id == window_id) {
+        // Still there; a regular restart may bring it back
+        return false;
+    }
+
+    let captured = state.capture_manager.active_window_ids();
+    let Some(new) = find_replacement(&old, &windows, &captured) else {
+        debug!("No replacement for window {} ({} - {})", window_id, old.app, old.title);
+        return false;
+    };
+    let new_id = new.id;
+
+    info!("Window {} was replaced by {} ({} - {})", window_id, new_id, new.app, new.title);
+    let offer = match state.subscribe_window(new_id).await {
+        Ok(offer) => offer,
+        Err(e) => {
+            warn!("Failed to reattach window {} to {}: {}", window_id, new_id, e);
+            return false;
+        }
+    };
+
+    // Carry the client's zoom and session records over and drop the dead stream
+    state.set_viewport(new_id, state.get_viewport(window_id));
+    state.subscriptions.replace(window_id, new_id);
+    state.apps.replace_window(window_id, new_id);
+    if let Err(e) = state.sessions.window_replaced(window_id, Some(new)).await {
+        warn!("Failed to log the reattach of window {}: {}", window_id, e);
+    }
+    state.unsubscribe_window(window_id).await;
+
+    state.broadcast(ServerMessage::WindowReplaced {
+        old_window_id: window_id,
+        new_window_id: new_id,
+    });
+    if let Some(sdp) = offer {
+        state.send_offer_to_peer(sdp).await;
+        if let Err(e) = state.request_keyframe(new_id) {
+            debug!("Could not request keyframe for {}: {}", new_id, e);
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::test_window as window;
+
+    #[test]
+    fn test_find_replacement() {
+        let old = window(1, "Cursor", "main.rs - blink");
+        let windows = [
+            window(2, "Cursor", "lib.rs - blink"),
+            window(3, "Cursor", "main.rs - blink"),
+            window(4, "Terminal", "main.rs - blink"),
+            window(5, "Cursor", "Settings"),
+        ];
+
+        // Same title wins
+        assert_eq!(find_replacement(&old, &windows, &[]).map(|w| w.id), Some(3));
+        // Ambiguous without it
+        assert_eq!(find_replacement(&old, &windows, &[3]).map(|w| w.id), None);
+        // The app's only uncaptured window
+        assert_eq!(find_replacement(&old, &windows, &[3, 5]).map(|w| w.id), Some(2));
+        // Other apps never match
+        assert_eq!(find_replacement(&old, &windows[2..3], &[]).map(|w| w.id), None);
+    }
+}
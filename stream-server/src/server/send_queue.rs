@@ -0,0 +1,111 @@
+//! Per-track RTP send queues
+//!
+//! The packets of large frames are paced (see `H264RtpPacketizer::send_packets`),
+//! which can take up to 10 ms per frame. Each track therefore gets its own send
+//! task fed by a short queue, so a keyframe being paced on one window never
+//! holds back the frames of the others.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use webrtc::rtp::packet::Packet;
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
+
+use super::stats::FrameStage;
+use super::ServerState;
+
+/// Frames waiting to be sent per track before new frames are dropped
+const TRACK_QUEUE_FRAMES: usize = 8;
+
+/// RTP packets of one frame, ready to be sent
+pub struct QueuedFrame {
+    pub window_id: u32,
+    /// Frame counter of the frame task (for diagnostics)
+    pub frame_number: u64,
+    pub packets: Vec<Packet>,
+    /// Encoded size of the frame
+    pub bytes: usize,
+    /// When the encoder callback handed the frame over
+    pub received_at: Instant,
+}
+
+/// Send task of one track
+struct TrackQueue {
+    track: Arc<TrackLocalStaticRTP>,
+    frames: mpsc::Sender<QueuedFrame>,
+}
+
+/// Send queues of the tracks frames are currently sent on
+///
+/// Dropping a queue ends its send task once the queued frames are sent.
+pub struct SendQueues {
+    state: Arc<ServerState>,
+    queues: HashMap<u32, TrackQueue>,
+}
+
+impl SendQueues {
+    pub fn new(state: Arc<ServerState>) -> Self {
+        Self {
+            state,
+            queues: HashMap::new(),
+        }
+    }
+
+    /// Queue a frame for sending on `track`
+    ///
+    /// A new send task is started when the window has none yet or its track
+    /// was replaced. If the queue is full, the frame is dropped and a keyframe
+    /// is requested so the decoder can recover.
+    pub fn send(&mut self, track: Arc<TrackLocalStaticRTP>, frame: QueuedFrame) {
+        let window_id = frame.window_id;
+        let current = self
+            .queues
+            .get(&window_id)
+            .is_some_and(|queue| Arc::ptr_eq(&queue.track, &track) && !queue.frames.is_closed());
+        if !current {
+            let (frames, receiver) = mpsc::channel(TRACK_QUEUE_FRAMES);
+            tokio::spawn(run_track_queue(Arc::clone(&self.state), Arc::clone(&track), receiver));
+            self.queues.insert(window_id, TrackQueue { track, frames });
+        }
+
+        if let Err(mpsc::error::TrySendError::Full(frame)) = self.queues[&window_id].frames.try_send(frame) {
+            crate::diag!(window_id, frame = frame.frame_number, reason = "send queue full", "Frame dropped");
+            warn!("Send queue of window {} is full, dropping frame #{}", window_id, frame.frame_number);
+            if let Err(e) = self.state.request_keyframe(window_id) {
+                debug!("Failed to request keyframe for window {}: {}", window_id, e);
+            }
+        }
+    }
+
+    /// Stop the send task of a window that no longer has a track
+    pub fn remove(&mut self, window_id: u32) {
+        self.queues.remove(&window_id);
+    }
+}
+
+/// Send the queued frames of one track until its queue is dropped
+async fn run_track_queue(
+    state: Arc<ServerState>,
+    track: Arc<TrackLocalStaticRTP>,
+    mut frames: mpsc::Receiver<QueuedFrame>,
+) {
+    let timings = &state.frame_timings;
+    while let Some(frame) = frames.recv().await {
+        let send_start = Instant::now();
+        match state.rtp_packetizer.send_packets(&track, &frame.packets).await {
+            Ok(paced) => {
+                timings.record(FrameStage::Pacing, paced);
+                timings.record(FrameStage::Send, send_start.elapsed().saturating_sub(paced));
+                state.sessions.record_bytes(frame.window_id, frame.bytes);
+                timings.record(FrameStage::Total, frame.received_at.elapsed());
+            }
+            Err(e) => {
+                crate::diag!(window_id = frame.window_id, frame = frame.frame_number, reason = "send failed", error = %e, "Frame dropped");
+                debug!("Failed to send frame for window {}: {}", frame.window_id, e);
+            }
+        }
+    }
+}
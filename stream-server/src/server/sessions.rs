@@ -0,0 +1,407 @@
+//! Persistent stream session records
+//!
+//! Every WebSocket connection is logged as a session: the peer address, when
+//! it connected and disconnected, which windows it viewed and how many bytes
+//! of video were sent. Records are kept in SQLite so shared machines have a
+//! history of who watched what; `GET /sessions` serves them as JSON.
+//!
+//! Database access runs on the blocking thread pool, so a slow disk never
+//! stalls the WebSocket and frame tasks.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::capture::WindowInfo;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS sessions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        peer TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        ended_at INTEGER,
+        bytes_sent INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS session_windows (
+        session_id INTEGER NOT NULL REFERENCES sessions(id),
+        window_id INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        app TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        ended_at INTEGER,
+        bytes_sent INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (session_id, window_id)
+    );
+";
+
+/// A logged session
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionRecord {
+    pub id: i64,
+    pub peer: String,
+    /// Unix time in milliseconds
+    pub started_at: i64,
+    /// None while the session is still connected
+    pub ended_at: Option<i64>,
+    pub bytes_sent: u64,
+    pub windows: Vec<SessionWindowRecord>,
+}
+
+/// A window viewed during a session
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionWindowRecord {
+    pub window_id: u32,
+    pub title: String,
+    pub app: String,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    pub bytes_sent: u64,
+}
+
+/// Session log backed by SQLite
+///
+/// Byte counters of viewed windows are kept in memory and written when the
+/// window is closed or the session ends.
+pub struct SessionLog {
+    db: Arc<Mutex<Connection>>,
+    /// Bytes sent per open window, per connected session
+    active: Mutex<HashMap<i64, HashMap<u32, u64>>>,
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+impl SessionLog {
+    /// Open (or create) the session database at `path`
+    pub fn open(path: &str) -> Result<Self> {
+        let db = Connection::open(path)?;
+        db.execute_batch(SCHEMA)?;
+
+        // Sessions still open from a previous run ended when it stopped
+        db.execute("UPDATE sessions SET ended_at = started_at WHERE ended_at IS NULL", [])?;
+        db.execute("UPDATE session_windows SET ended_at = started_at WHERE ended_at IS NULL", [])?;
+
+        info!("Session log at {}", path);
+        Ok(Self {
+            db: Arc::new(Mutex::new(db)),
+            active: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Session log that only lives as long as the process
+    pub fn in_memory() -> Self {
+        let db = Connection::open_in_memory().expect("in-memory SQLite database");
+        db.execute_batch(SCHEMA).expect("session log schema");
+        Self {
+            db: Arc::new(Mutex::new(db)),
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run a database access on the blocking thread pool
+    async fn with_db<T, F>(&self, access: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || access(&mut db.lock())).await?
+    }
+
+    /// Record a new connection
+    ///
+    /// The session ends with [`ActiveSession::end`], or in the background
+    /// when the returned guard is dropped.
+    pub async fn start_session(&self, peer: &str) -> Result<ActiveSession<'_>> {
+        let peer = peer.to_string();
+        let id = self
+            .with_db(move |db| {
+                db.execute(
+                    "INSERT INTO sessions (peer, started_at) VALUES (?1, ?2)",
+                    params![peer, now_ms()],
+                )?;
+                Ok(db.last_insert_rowid())
+            })
+            .await?;
+        self.active.lock().insert(id, HashMap::new());
+        Ok(ActiveSession { log: self, id, ended: false })
+    }
+
+    /// Record that a session started viewing a window
+    pub async fn window_viewed(&self, session_id: i64, window_id: u32, title: &str, app: &str) -> Result<()> {
+        let (title, app) = (title.to_string(), app.to_string());
+        self.with_db(move |db| {
+            insert_window(db, session_id, window_id, &title, &app, now_ms())?;
+            Ok(())
+        })
+        .await?;
+        if let Some(windows) = self.active.lock().get_mut(&session_id) {
+            windows.entry(window_id).or_insert(0);
+        }
+        Ok(())
+    }
+
+    /// Count video bytes sent for a window to every session viewing it
+    pub fn record_bytes(&self, window_id: u32, bytes: usize) {
+        for windows in self.active.lock().values_mut() {
+            if let Some(sent) = windows.get_mut(&window_id) {
+                *sent += bytes as u64;
+            }
+        }
+    }
+
+    /// Close the record of a window in every session viewing it
+    pub async fn window_closed(&self, window_id: u32) -> Result<()> {
+        self.window_replaced(window_id, None).await
+    }
+
+    /// Close the record of a window and open one for its successor
+    ///
+    /// Used when a stream moves to another window (app follow, reattach), so
+    /// every session viewing the old window is logged as viewing the new one.
+    pub async fn window_replaced(&self, window_id: u32, successor: Option<&WindowInfo>) -> Result<()> {
+        let closed: Vec<(i64, u64)> = self
+            .active
+            .lock()
+            .iter_mut()
+            .filter_map(|(&session_id, windows)| {
+                let bytes = windows.remove(&window_id)?;
+                if let Some(successor) = successor {
+                    windows.entry(successor.id).or_insert(0);
+                }
+                Some((session_id, bytes))
+            })
+            .collect();
+        if closed.is_empty() {
+            return Ok(());
+        }
+
+        let successor = successor.map(|w| (w.id, w.title.clone(), w.app.clone()));
+        self.with_db(move |db| {
+            let now = now_ms();
+            let tx = db.transaction()?;
+            for (session_id, bytes) in closed {
+                close_window(&tx, session_id, window_id, bytes, now)?;
+                if let Some((id, title, app)) = &successor {
+                    insert_window(&tx, session_id, *id, title, app, now)?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Close a session and write its byte counts
+    async fn end_session(&self, session_id: i64) -> Result<()> {
+        let windows = self.active.lock().remove(&session_id).unwrap_or_default();
+        self.with_db(move |db| write_session_end(db, session_id, &windows, now_ms()))
+            .await
+    }
+
+    /// Most recent sessions first, including connected ones with live byte counts
+    pub async fn sessions(&self, limit: u32) -> Result<Vec<SessionRecord>> {
+        let active = self.active.lock().clone();
+        self.with_db(move |db| session_records(db, limit, &active)).await
+    }
+}
+
+/// Insert the record of a window viewed by a session, or reopen it
+fn insert_window(db: &Connection, session_id: i64, window_id: u32, title: &str, app: &str, now: i64) -> Result<()> {
+    db.execute(
+        "INSERT INTO session_windows (session_id, window_id, title, app, started_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (session_id, window_id) DO UPDATE SET ended_at = NULL",
+        params![session_id, window_id, title, app, now],
+    )?;
+    Ok(())
+}
+
+/// Write the end time of a window record and add the bytes sent since it was opened
+fn close_window(db: &Connection, session_id: i64, window_id: u32, bytes: u64, now: i64) -> Result<()> {
+    db.execute(
+        "UPDATE session_windows SET ended_at = ?1, bytes_sent = bytes_sent + ?2
+         WHERE session_id = ?3 AND window_id = ?4",
+        params![now, bytes as i64, session_id, window_id],
+    )?;
+    Ok(())
+}
+
+/// Close a session and its open windows
+///
+/// The session's byte count covers windows closed earlier as well.
+fn write_session_end(db: &mut Connection, session_id: i64, windows: &HashMap<u32, u64>, now: i64) -> Result<()> {
+    let tx = db.transaction()?;
+    for (&window_id, &bytes) in windows {
+        close_window(&tx, session_id, window_id, bytes, now)?;
+    }
+    tx.execute(
+        "UPDATE sessions SET ended_at = ?1,
+             bytes_sent = (SELECT COALESCE(SUM(bytes_sent), 0) FROM session_windows WHERE session_id = ?2)
+         WHERE id = ?2",
+        params![now, session_id],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Session records from the database, with live byte counts of open windows
+fn session_records(db: &Connection, limit: u32, active: &HashMap<i64, HashMap<u32, u64>>) -> Result<Vec<SessionRecord>> {
+    let mut stmt = db.prepare(
+        "SELECT id, peer, started_at, ended_at, bytes_sent FROM sessions ORDER BY id DESC LIMIT ?1",
+    )?;
+    let mut sessions = stmt
+        .query_map(params![limit], |row| {
+            Ok(SessionRecord {
+                id: row.get(0)?,
+                peer: row.get(1)?,
+                started_at: row.get(2)?,
+                ended_at: row.get(3)?,
+                bytes_sent: row.get::<_, i64>(4)? as u64,
+                windows: Vec::new(),
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut stmt = db.prepare(
+        "SELECT window_id, title, app, started_at, ended_at, bytes_sent
+         FROM session_windows WHERE session_id = ?1 ORDER BY started_at, rowid",
+    )?;
+    for session in &mut sessions {
+        let live = active.get(&session.id);
+        session.windows = stmt
+            .query_map(params![session.id], |row| {
+                let window_id: u32 = row.get(0)?;
+                let stored = row.get::<_, i64>(5)? as u64;
+                Ok(SessionWindowRecord {
+                    window_id,
+                    title: row.get(1)?,
+                    app: row.get(2)?,
+                    started_at: row.get(3)?,
+                    ended_at: row.get(4)?,
+                    bytes_sent: stored + live.and_then(|w| w.get(&window_id)).copied().unwrap_or(0),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        if live.is_some() {
+            session.bytes_sent = session.windows.iter().map(|w| w.bytes_sent).sum();
+        }
+    }
+
+    Ok(sessions)
+}
+
+/// A connected session
+///
+/// End it with [`end`](Self::end); a session dropped without that, e.g. on a
+/// connection error, is closed in the background.
+pub struct ActiveSession<'a> {
+    log: &'a SessionLog,
+    id: i64,
+    ended: bool,
+}
+
+impl ActiveSession<'_> {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Close the session and write its byte counts
+    pub async fn end(mut self) {
+        self.ended = true;
+        if let Err(e) = self.log.end_session(self.id).await {
+            warn!("Failed to close session {}: {}", self.id, e);
+        }
+    }
+}
+
+impl Drop for ActiveSession<'_> {
+    fn drop(&mut self) {
+        if self.ended {
+            return;
+        }
+
+        let id = self.id;
+        let windows = self.log.active.lock().remove(&id).unwrap_or_default();
+        let db = Arc::clone(&self.log.db);
+        let close = move || {
+            if let Err(e) = write_session_end(&mut db.lock(), id, &windows, now_ms()) {
+                warn!("Failed to close session {}: {}", id, e);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(close)),
+            Err(_) => close(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::test_window;
+
+    #[tokio::test]
+    async fn test_session_log_records_windows_and_bytes() {
+        let log = SessionLog::in_memory();
+
+        let session = log.start_session("192.168.1.20:51234").await.unwrap();
+        let id = session.id();
+        log.window_viewed(id, 7, "main.rs", "Cursor").await.unwrap();
+        log.record_bytes(7, 1000);
+        log.record_bytes(8, 500); // not viewed by this session
+        log.record_bytes(7, 250);
+
+        // Live counts while connected
+        let sessions = log.sessions(10).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].ended_at, None);
+        assert_eq!(sessions[0].bytes_sent, 1250);
+
+        session.end().await;
+        let session = &log.sessions(10).await.unwrap()[0];
+        assert_eq!(session.peer, "192.168.1.20:51234");
+        assert!(session.ended_at.is_some());
+        assert_eq!(session.bytes_sent, 1250);
+        assert_eq!(session.windows.len(), 1);
+        assert_eq!(session.windows[0].app, "Cursor");
+        assert_eq!(session.windows[0].bytes_sent, 1250);
+    }
+
+    #[tokio::test]
+    async fn test_session_log_closes_replaced_windows() {
+        let log = SessionLog::in_memory();
+
+        let session = log.start_session("192.168.1.20:51234").await.unwrap();
+        log.window_viewed(session.id(), 7, "Window 7", "Safari").await.unwrap();
+        log.record_bytes(7, 1000);
+
+        // The stream moves to window 8 (app follow or reattach)
+        log.window_replaced(7, Some(&test_window(8, "Safari", "Window 8"))).await.unwrap();
+        log.record_bytes(7, 100);
+        log.record_bytes(8, 500);
+
+        let windows = &log.sessions(10).await.unwrap()[0].windows;
+        assert_eq!(windows.len(), 2);
+        assert!(windows[0].ended_at.is_some());
+        assert_eq!(windows[0].bytes_sent, 1000);
+        assert_eq!((windows[1].window_id, windows[1].ended_at, windows[1].bytes_sent), (8, None, 500));
+
+        log.window_closed(8).await.unwrap();
+        session.end().await;
+        let session = &log.sessions(10).await.unwrap()[0];
+        assert!(session.windows[1].ended_at.is_some());
+        assert_eq!(session.bytes_sent, 1500);
+    }
+}
@@ -0,0 +1,407 @@
+//! Frame pipeline timing and encoder output statistics
+//!
+//! Records per-stage latency of the frame path (encoder callback → channel →
+//! RTP packetization → paced track write) in fixed-bucket histograms. Recording is
+//! lock-free so it can run on the FFI callback thread.
+//!
+//! Frame sizes, keyframes and slice QPs are aggregated per track so quality
+//! regressions show up in `get_stats` and the Prometheus `/metrics` endpoint.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use blink_protocol::{StageLatency, TrackQuality};
+use parking_lot::Mutex;
+
+use crate::video::SliceQpParser;
+
+/// Upper bounds of the histogram buckets in microseconds
+///
+/// Samples above the last bound fall into an overflow bucket.
+const BUCKET_BOUNDS_US: [u64; 12] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 1_000_000,
+];
+
+/// A stage of the frame pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameStage {
+    /// Wait in the channel between the encoder callback and the frame task
+    Channel,
+    /// Splitting the access unit into RTP packets
+    Packetize,
+    /// Waiting between the paced RTP packets of a frame
+    Pacing,
+    /// Writing RTP packets to the track (without pacing waits)
+    Send,
+    /// Encoder callback until the last packet was written
+    Total,
+}
+
+impl FrameStage {
+    pub const ALL: [FrameStage; 5] = [
+        FrameStage::Channel,
+        FrameStage::Packetize,
+        FrameStage::Pacing,
+        FrameStage::Send,
+        FrameStage::Total,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameStage::Channel => "channel",
+            FrameStage::Packetize => "packetize",
+            FrameStage::Pacing => "pacing",
+            FrameStage::Send => "send",
+            FrameStage::Total => "total",
+        }
+    }
+}
+
+/// Fixed-bucket latency histogram
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; BUCKET_BOUNDS_US.len() + 1],
+    count: AtomicU64,
+    sum_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum_us: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+        }
+    }
+
+    /// Record one sample
+    pub fn record(&self, latency: Duration) {
+        let us = latency.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = BUCKET_BOUNDS_US
+            .iter()
+            .position(|&bound| us <= bound)
+            .unwrap_or(BUCKET_BOUNDS_US.len());
+
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    /// Approximate percentile (0.0 - 1.0) as the upper bound of its bucket
+    ///
+    /// The overflow bucket reports the maximum sample.
+    pub fn percentile(&self, p: f64) -> u64 {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return 0;
+        }
+
+        let rank = ((count as f64 * p).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= rank {
+                return BUCKET_BOUNDS_US
+                    .get(i)
+                    .copied()
+                    .unwrap_or_else(|| self.max_us.load(Ordering::Relaxed));
+            }
+        }
+
+        self.max_us.load(Ordering::Relaxed)
+    }
+
+    /// Summarize the histogram for a stage
+    pub fn summary(&self, stage: FrameStage) -> StageLatency {
+        let count = self.count.load(Ordering::Relaxed);
+        let sum_us = self.sum_us.load(Ordering::Relaxed);
+
+        StageLatency {
+            stage: stage.name().to_string(),
+            count,
+            mean_us: sum_us.checked_div(count).unwrap_or(0),
+            p50_us: self.percentile(0.50),
+            p95_us: self.percentile(0.95),
+            p99_us: self.percentile(0.99),
+            max_us: self.max_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-stage latency histograms for the frame pipeline
+pub struct FrameTimings {
+    frames: AtomicU64,
+    stages: [LatencyHistogram; FrameStage::ALL.len()],
+}
+
+impl FrameTimings {
+    pub fn new() -> Self {
+        Self {
+            frames: AtomicU64::new(0),
+            stages: std::array::from_fn(|_| LatencyHistogram::new()),
+        }
+    }
+
+    /// Count a frame received from the encoder
+    pub fn record_frame(&self) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the latency of one stage for a frame
+    pub fn record(&self, stage: FrameStage, latency: Duration) {
+        self.stages[stage as usize].record(latency);
+    }
+
+    /// Frames received from the encoder
+    pub fn frames(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    /// Latency summary of every stage
+    pub fn summary(&self) -> Vec<StageLatency> {
+        FrameStage::ALL
+            .iter()
+            .map(|&stage| self.stages[stage as usize].summary(stage))
+            .collect()
+    }
+}
+
+impl Default for FrameTimings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encoder output counters of one track
+#[derive(Default)]
+struct TrackCounters {
+    parser: SliceQpParser,
+    frames: u64,
+    keyframes: u64,
+    bytes: u64,
+    max_frame_bytes: u64,
+    qp_sum: i64,
+    qp_slices: u64,
+    min_qp: Option<i32>,
+    max_qp: Option<i32>,
+}
+
+impl TrackCounters {
+    fn summary(&self, window_id: u32) -> TrackQuality {
+        TrackQuality {
+            window_id,
+            frames: self.frames,
+            keyframes: self.keyframes,
+            bytes: self.bytes,
+            mean_frame_bytes: self.bytes.checked_div(self.frames).unwrap_or(0),
+            max_frame_bytes: self.max_frame_bytes,
+            mean_qp: (self.qp_slices > 0).then(|| self.qp_sum as f32 / self.qp_slices as f32),
+            min_qp: self.min_qp,
+            max_qp: self.max_qp,
+        }
+    }
+}
+
+/// Per-track encoder output statistics (frame sizes, keyframes, slice QP)
+///
+/// Recorded from the frame task rather than the FFI callback, since reading
+/// the QP means parsing slice headers.
+pub struct PictureQuality {
+    tracks: Mutex<HashMap<u32, TrackCounters>>,
+}
+
+impl PictureQuality {
+    pub fn new() -> Self {
+        Self {
+            tracks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record an encoded access unit (Annex-B) of a window
+    pub fn record(&self, window_id: u32, data: &[u8], is_keyframe: bool) {
+        let mut tracks = self.tracks.lock();
+        let track = tracks.entry(window_id).or_default();
+
+        track.frames += 1;
+        track.keyframes += is_keyframe as u64;
+        track.bytes += data.len() as u64;
+        track.max_frame_bytes = track.max_frame_bytes.max(data.len() as u64);
+
+        for qp in track.parser.slice_qps(data) {
+            track.qp_sum += qp as i64;
+            track.qp_slices += 1;
+            track.min_qp = Some(track.min_qp.map_or(qp, |min| min.min(qp)));
+            track.max_qp = Some(track.max_qp.map_or(qp, |max| max.max(qp)));
+        }
+    }
+
+    /// Forget a window whose capture ended
+    pub fn remove(&self, window_id: u32) {
+        self.tracks.lock().remove(&window_id);
+    }
+
+    /// Statistics of every track, ordered by window ID
+    pub fn summary(&self) -> Vec<TrackQuality> {
+        let tracks = self.tracks.lock();
+        let mut summary: Vec<TrackQuality> = tracks
+            .iter()
+            .map(|(&window_id, track)| track.summary(window_id))
+            .collect();
+        summary.sort_by_key(|track| track.window_id);
+        summary
+    }
+}
+
+impl Default for PictureQuality {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-track Prometheus metric: name, type, help and value
+type TrackMetric = (&'static str, &'static str, &'static str, fn(&TrackCounters) -> Option<i64>);
+
+const TRACK_METRICS: [TrackMetric; 8] = [
+    ("blink_track_frames_total", "counter", "Encoded frames per window", |t| Some(t.frames as i64)),
+    ("blink_track_keyframes_total", "counter", "Encoded keyframes per window", |t| Some(t.keyframes as i64)),
+    ("blink_track_bytes_total", "counter", "Encoded bytes per window", |t| Some(t.bytes as i64)),
+    ("blink_track_max_frame_bytes", "gauge", "Largest encoded frame per window", |t| Some(t.max_frame_bytes as i64)),
+    ("blink_track_qp_sum", "counter", "Sum of slice QPs per window", |t| Some(t.qp_sum)),
+    ("blink_track_qp_slices_total", "counter", "Slices with a parsed QP per window", |t| Some(t.qp_slices as i64)),
+    ("blink_track_qp_min", "gauge", "Lowest slice QP per window", |t| t.min_qp.map(i64::from)),
+    ("blink_track_qp_max", "gauge", "Highest slice QP per window", |t| t.max_qp.map(i64::from)),
+];
+
+/// Render frame timings and per-track quality in the Prometheus text format
+///
+/// QP is exported as a sum and slice count so dashboards can compute the
+/// mean over any time range with `rate()`.
+pub fn prometheus_metrics(timings: &FrameTimings, quality: &PictureQuality) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# HELP blink_frames_total Frames received from the encoder");
+    let _ = writeln!(out, "# TYPE blink_frames_total counter");
+    let _ = writeln!(out, "blink_frames_total {}", timings.frames());
+
+    let _ = writeln!(out, "# HELP blink_frame_stage_latency_us Latency of frame pipeline stages");
+    let _ = writeln!(out, "# TYPE blink_frame_stage_latency_us summary");
+    for &stage in FrameStage::ALL.iter() {
+        let histogram = &timings.stages[stage as usize];
+        let name = stage.name();
+        for quantile in [0.5, 0.95, 0.99] {
+            let _ = writeln!(
+                out,
+                "blink_frame_stage_latency_us{{stage=\"{}\",quantile=\"{}\"}} {}",
+                name,
+                quantile,
+                histogram.percentile(quantile)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "blink_frame_stage_latency_us_sum{{stage=\"{}\"}} {}",
+            name,
+            histogram.sum_us.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "blink_frame_stage_latency_us_count{{stage=\"{}\"}} {}",
+            name,
+            histogram.count.load(Ordering::Relaxed)
+        );
+    }
+
+    let tracks = quality.tracks.lock();
+    let mut window_ids: Vec<u32> = tracks.keys().copied().collect();
+    window_ids.sort_unstable();
+
+    for (name, kind, help, value) in TRACK_METRICS {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for window_id in &window_ids {
+            if let Some(value) = value(&tracks[window_id]) {
+                let _ = writeln!(out, "{}{{window_id=\"{}\"}} {}", name, window_id, value);
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_histogram_percentiles() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(histogram.percentile(0.5), 0);
+
+        // 90 fast samples, 9 slower ones and one outlier past the last bucket
+        for _ in 0..90 {
+            histogram.record(Duration::from_micros(80));
+        }
+        for _ in 0..9 {
+            histogram.record(Duration::from_millis(3));
+        }
+        histogram.record(Duration::from_secs(2));
+
+        let summary = histogram.summary(FrameStage::Send);
+        assert_eq!(summary.stage, "send");
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50_us, 100);
+        assert_eq!(summary.p95_us, 5_000);
+        assert_eq!(summary.p99_us, 5_000);
+        assert_eq!(summary.max_us, 2_000_000);
+        assert_eq!(summary.mean_us, (90 * 80 + 9 * 3_000 + 2_000_000) / 100);
+    }
+
+    #[test]
+    fn test_frame_timings_summary_order() {
+        let timings = FrameTimings::new();
+        timings.record_frame();
+        timings.record(FrameStage::Total, Duration::from_millis(1));
+
+        let summary = timings.summary();
+        let names: Vec<&str> = summary.iter().map(|s| s.stage.as_str()).collect();
+        assert_eq!(names, ["channel", "packetize", "pacing", "send", "total"]);
+        assert_eq!(summary[4].count, 1);
+        assert_eq!(timings.frames(), 1);
+    }
+
+    #[test]
+    fn test_picture_quality_summary() {
+        let quality = PictureQuality::new();
+        quality.record(2, &[0, 0, 0, 1, 0x65, 0x88, 0x84], true);
+        quality.record(2, &[0, 0, 0, 1, 0x41, 0x9a], false);
+        quality.record(1, &[0, 0, 0, 1, 0x41, 0x9a, 0x02], false);
+
+        let summary = quality.summary();
+        assert_eq!(summary.iter().map(|t| t.window_id).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(summary[1].frames, 2);
+        assert_eq!(summary[1].keyframes, 1);
+        assert_eq!(summary[1].bytes, 13);
+        assert_eq!(summary[1].mean_frame_bytes, 6);
+        assert_eq!(summary[1].max_frame_bytes, 7);
+        // No parameter sets, so no QP
+        assert_eq!(summary[1].mean_qp, None);
+
+        let metrics = prometheus_metrics(&FrameTimings::new(), &quality);
+        assert!(metrics.contains("blink_track_keyframes_total{window_id=\"2\"} 1\n"));
+        assert!(metrics.contains("blink_frame_stage_latency_us_count{stage=\"send\"} 0\n"));
+        assert!(!metrics.contains("blink_track_qp_max{"));
+
+        quality.remove(2);
+        assert_eq!(quality.summary().len(), 1);
+    }
+}
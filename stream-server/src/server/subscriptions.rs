@@ -0,0 +1,105 @@
+//! Window subscriptions of each client
+//!
+//! Tracks are shared by all clients, so a window keeps streaming while any
+//! client wants it, either through `subscribe` or by following its app. A
+//! window is only unsubscribed once the last of them lets go of it.
+
+use std::collections::{HashMap, HashSet};
+
+use parking_lot::Mutex;
+
+use super::client::ClientId;
+
+/// Windows subscribed to with `subscribe`, by client
+pub struct Subscriptions {
+    windows: Mutex<HashMap<ClientId, HashSet<u32>>>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self {
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn add(&self, client_id: ClientId, window_id: u32) {
+        self.windows.lock().entry(client_id).or_default().insert(window_id);
+    }
+
+    /// Drop a subscription, returning whether the client had it
+    pub fn remove(&self, client_id: ClientId, window_id: u32) -> bool {
+        let mut windows = self.windows.lock();
+        let Some(subscribed) = windows.get_mut(&client_id) else {
+            return false;
+        };
+        let removed = subscribed.remove(&window_id);
+        if subscribed.is_empty() {
+            windows.remove(&client_id);
+        }
+        removed
+    }
+
+    /// Drop every subscription of a client, returning its windows
+    pub fn remove_client(&self, client_id: ClientId) -> Vec<u32> {
+        self.windows
+            .lock()
+            .remove(&client_id)
+            .map(|subscribed| subscribed.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Move the subscriptions of a window to the window that replaced it
+    pub fn replace(&self, window_id: u32, new_id: u32) {
+        for subscribed in self.windows.lock().values_mut() {
+            if subscribed.remove(&window_id) {
+                subscribed.insert(new_id);
+            }
+        }
+    }
+
+    /// Drop a window that is no longer streamed from every client
+    pub fn forget_window(&self, window_id: u32) {
+        self.windows.lock().retain(|_, subscribed| {
+            subscribed.remove(&window_id);
+            !subscribed.is_empty()
+        });
+    }
+
+    /// Whether any client subscribed to a window
+    pub fn is_subscribed(&self, window_id: u32) -> bool {
+        self.windows.lock().values().any(|subscribed| subscribed.contains(&window_id))
+    }
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscriptions() {
+        let subscriptions = Subscriptions::new();
+        let (a, b) = (ClientId::next(), ClientId::next());
+        subscriptions.add(a, 1);
+        subscriptions.add(b, 1);
+        subscriptions.add(b, 2);
+
+        // Window 1 stays wanted until both clients let go
+        assert!(subscriptions.remove(a, 1));
+        assert!(!subscriptions.remove(a, 1));
+        assert!(subscriptions.is_subscribed(1));
+
+        subscriptions.replace(2, 3);
+        assert!(!subscriptions.is_subscribed(2));
+
+        let mut windows = subscriptions.remove_client(b);
+        windows.sort_unstable();
+        assert_eq!(windows, [1, 3]);
+        assert!(!subscriptions.is_subscribed(1));
+    }
+}
@@ -3,148 +3,158 @@
 use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
+use base64::Engine;
 use futures_util::{SinkExt, StreamExt};
-use serde::{Deserialize, Serialize};
 use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
 use tokio_tungstenite::{accept_async, tungstenite::Message};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+pub use blink_protocol::{ClientMessage, IceCandidate, ServerMessage};
 
-use super::ServerState;
 use crate::capture::WindowInfo;
-use crate::input::{KeyEvent, MouseEvent, TextEvent};
-
-/// ICE candidate with full WebRTC fields
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct IceCandidate {
-    /// The SDP candidate string
-    pub candidate: String,
-    /// The SDP media stream identification tag
-    #[serde(default)]
-    pub sdp_mid: Option<String>,
-    /// The index of the media description in the SDP
-    #[serde(default, alias = "sdpMLineIndex")]
-    pub sdp_m_line_index: Option<u16>,
-}
+use crate::input::{resolve_key, FileDrop, KeyEvent, KeyboardLayout, MouseEvent, PointerState, TextEvent};
+use crate::relay;
 
-/// Incoming WebSocket message types
-#[derive(Debug, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum IncomingMessage {
-    /// WebRTC offer from client (initial connection)
-    Offer { sdp: String },
-    /// WebRTC answer from client (response to server's renegotiation offer)
-    Answer { sdp: String },
-    /// ICE candidate from client
-    Ice { candidate: IceCandidate },
-    /// Subscribe to window streams
-    Subscribe { window_ids: Vec<u32> },
-    /// Update viewport for a window (crop region for zoom)
-    Viewport {
-        window_id: u32,
-        /// Left edge (0.0 = left, 1.0 = right)
-        x: f32,
-        /// Top edge (0.0 = top, 1.0 = bottom)
-        y: f32,
-        /// Width as fraction of source (1.0 = full width)
-        width: f32,
-        /// Height as fraction of source (1.0 = full height)
-        height: f32,
-    },
-    /// Mouse input event
-    Mouse(MouseEvent),
-    /// Keyboard input event
-    Key(KeyEvent),
-    /// Text input event (for typing text)
-    Text(TextEvent),
-    /// Request window list
-    GetWindows,
-}
+use super::client::ClientId;
+use super::{admin, apps, file_drop, http, ServerState};
 
-/// Outgoing WebSocket message types
-#[derive(Debug, Serialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum OutgoingMessage {
-    /// WebRTC answer to client (response to client's offer)
-    Answer { sdp: String },
-    /// WebRTC offer to client (renegotiation - server initiated)
-    Offer { sdp: String },
-    /// ICE candidate to client
-    Ice { candidate: IceCandidate },
-    /// List of available windows
-    WindowList { windows: Vec<WindowInfo> },
-    /// Window closed notification
-    WindowClosed { id: u32 },
-    /// Error response
-    Error { message: String },
+/// Input settings chosen by one client
+#[derive(Default)]
+struct ClientInput {
+    /// Layout for resolving characters in key events
+    keyboard_layout: KeyboardLayout,
+    /// Settings and positions for relative mouse events
+    pointer: PointerState,
 }
 
 /// Handle a WebSocket connection
 pub async fn handle_connection(stream: TcpStream, state: Arc<ServerState>) -> Result<()> {
+    if let Some(target) = http::http_route(&stream).await? {
+        return http::serve(stream, &target, &state).await;
+    }
+
+    let client_id = ClientId::next();
+    handle_client(stream, state, client_id)
+        .instrument(info_span!("client", id = %client_id))
+        .await
+}
+
+/// Serve a WebSocket client until it disconnects
+async fn handle_client(stream: TcpStream, state: Arc<ServerState>, client_id: ClientId) -> Result<()> {
+    let peer = stream
+        .peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
     let ws_stream = accept_async(stream).await?;
     let (mut write, mut read) = ws_stream.split();
 
-    info!("WebSocket connection established");
+    info!("WebSocket connection established from {}", peer);
+
+    // Logged until the connection ends; a broken session log does not stop streaming
+    let session = match state.sessions.start_session(&peer).await {
+        Ok(session) => Some(session),
+        Err(e) => {
+            warn!("Failed to log session for {}: {}", peer, e);
+            None
+        }
+    };
+    let session_id = session.as_ref().map(|session| session.id());
 
     // Send initial window list
-    let windows = state.capture_manager.get_windows();
-    let msg = OutgoingMessage::WindowList { windows };
+    let msg = state.window_list();
     let json = serde_json::to_string(&msg)?;
     write.send(Message::Text(json)).await?;
 
-    // Process incoming messages
-    while let Some(msg) = read.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                debug!("Received message: {}", text);
-                match serde_json::from_str::<IncomingMessage>(&text) {
-                    Ok(incoming) => {
-                        if let Err(e) = handle_message(incoming, &state, &mut write).await {
-                            error!("Error handling message: {}", e);
-                            let error_msg = OutgoingMessage::Error {
-                                message: e.to_string(),
-                            };
-                            let json = serde_json::to_string(&error_msg)?;
-                            write.send(Message::Text(json)).await?;
+    // Subscribe to server-initiated events (capture errors, window changes)
+    let mut events = state.events.subscribe();
+
+    let mut input = ClientInput::default();
+
+    // Process incoming messages and forward server events
+    loop {
+        tokio::select! {
+            msg = read.next() => {
+                let Some(msg) = msg else {
+                    break;
+                };
+
+                match msg {
+                    Ok(Message::Text(text)) => {
+                        debug!("Received message: {}", admin::redact_token(&text));
+                        match serde_json::from_str::<ClientMessage>(&text) {
+                            Ok(incoming) => {
+                                if let Err(e) = handle_message(incoming, &state, client_id, session_id, &mut input, &mut write).await {
+                                    error!("Error handling message: {}", e);
+                                    let error_msg = ServerMessage::Error {
+                                        message: e.to_string(),
+                                        client_id: Some(client_id.get()),
+                                    };
+                                    let json = serde_json::to_string(&error_msg)?;
+                                    write.send(Message::Text(json)).await?;
+                                }
+                            }
+                            Err(e) => {
+                                warn!("Failed to parse message: {}", e);
+                                let error_msg = ServerMessage::Error {
+                                    message: format!("Invalid message format: {}", e),
+                                    client_id: Some(client_id.get()),
+                                };
+                                let json = serde_json::to_string(&error_msg)?;
+                                write.send(Message::Text(json)).await?;
+                            }
                         }
                     }
+                    Ok(Message::Binary(_)) => {
+                        warn!("Received unexpected binary message");
+                    }
+                    Ok(Message::Ping(data)) => {
+                        write.send(Message::Pong(data)).await?;
+                    }
+                    Ok(Message::Pong(_)) => {}
+                    Ok(Message::Close(_)) => {
+                        info!("WebSocket connection closed by client");
+                        break;
+                    }
+                    Ok(Message::Frame(_)) => {}
                     Err(e) => {
-                        warn!("Failed to parse message: {}", e);
-                        let error_msg = OutgoingMessage::Error {
-                            message: format!("Invalid message format: {}", e),
-                        };
-                        let json = serde_json::to_string(&error_msg)?;
-                        write.send(Message::Text(json)).await?;
+                        error!("WebSocket error: {}", e);
+                        break;
                     }
                 }
             }
-            Ok(Message::Binary(_)) => {
-                warn!("Received unexpected binary message");
-            }
-            Ok(Message::Ping(data)) => {
-                write.send(Message::Pong(data)).await?;
-            }
-            Ok(Message::Pong(_)) => {}
-            Ok(Message::Close(_)) => {
-                info!("WebSocket connection closed by client");
-                break;
-            }
-            Ok(Message::Frame(_)) => {}
-            Err(e) => {
-                error!("WebSocket error: {}", e);
-                break;
+
+            event = events.recv() => {
+                match event {
+                    Ok(event) if event.client_id.is_some_and(|id| id != client_id) => {}
+                    Ok(event) => {
+                        let json = serde_json::to_string(&event.message)?;
+                        write.send(Message::Text(json)).await?;
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Client lagging, dropped {} server events", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
             }
         }
     }
 
+    if let Some(session) = session {
+        session.end().await;
+    }
+    state.client_disconnected(client_id).await;
     info!("WebSocket connection ended");
     Ok(())
 }
 
 /// Handle a parsed incoming message
 async fn handle_message<S>(
-    message: IncomingMessage,
-    state: &ServerState,
+    message: ClientMessage,
+    state: &Arc<ServerState>,
+    client_id: ClientId,
+    session_id: Option<i64>,
+    input: &mut ClientInput,
     write: &mut S,
 ) -> Result<()>
 where
@@ -152,10 +162,10 @@ where
     S::Error: std::error::Error + Send + Sync + 'static,
 {
     match message {
-        IncomingMessage::Offer { sdp } => {
+        ClientMessage::Offer { sdp } => {
             info!("Received WebRTC offer");
-            let answer_sdp = state.webrtc_manager.write().await.handle_offer(&sdp).await?;
-            let response = OutgoingMessage::Answer { sdp: answer_sdp };
+            let answer_sdp = state.webrtc_manager.write().await.handle_offer(&sdp, client_id).await?;
+            let response = ServerMessage::Answer { sdp: answer_sdp };
             let json = serde_json::to_string(&response)?;
             write
                 .send(Message::Text(json))
@@ -163,79 +173,288 @@ where
                 .map_err(|e| anyhow!("Send error: {}", e))?;
         }
 
-        IncomingMessage::Answer { sdp } => {
+        ClientMessage::Answer { sdp } => {
             info!("Received renegotiation answer from client");
             state.webrtc_manager.write().await.handle_renegotiation_answer(&sdp).await?;
         }
 
-        IncomingMessage::Ice { candidate } => {
+        ClientMessage::Ice { candidate } => {
             debug!("Received ICE candidate: {:?}", candidate);
             state.webrtc_manager.write().await.add_ice_candidate(candidate).await?;
         }
 
-        IncomingMessage::Subscribe { window_ids } => {
+        ClientMessage::Subscribe { window_ids } => {
             info!("Subscribe request for windows: {:?}", window_ids);
             
+            let windows = state.windows();
             for window_id in window_ids {
-                state.capture_manager.start_capture(window_id)?;
+                log_window_viewed(state, session_id, &windows, window_id).await;
                 
-                // Update input injector with window bounds for coordinate conversion
-                if let Some(bounds) = state.capture_manager.get_window_bounds(window_id) {
-                    state.input_injector.update_window_bounds(window_id, bounds);
-                    debug!("Updated input bounds for window {}", window_id);
+                // Start the stream and get renegotiation offer if a track was added
+                let offer = state.subscribe_window(window_id).await?;
+                state.subscriptions.add(client_id, window_id);
+                if let Some(offer_sdp) = offer {
+                    send_offer(state, write, window_id, offer_sdp).await?;
                 }
-                
-                // Add track and get renegotiation offer if needed
-                if let Some(offer_sdp) = state.webrtc_manager.write().await.add_window_track(window_id).await? {
-                    // Send renegotiation offer to client
-                    let response = OutgoingMessage::Offer { sdp: offer_sdp };
-                    let json = serde_json::to_string(&response)?;
-                    write
-                        .send(Message::Text(json))
-                        .await
-                        .map_err(|e| anyhow!("Send error: {}", e))?;
-                    info!("Sent renegotiation offer to client for window {}", window_id);
-                    
-                    // Request a keyframe so client gets fresh decoder state after renegotiation
-                    if let Err(e) = crate::capture::request_keyframe(window_id) {
-                        debug!("Could not request keyframe for {}: {}", window_id, e);
-                    }
+            }
+        }
+
+        ClientMessage::Unsubscribe { window_ids } => {
+            info!("Unsubscribe request for windows: {:?}", window_ids);
+            
+            for window_id in window_ids {
+                if state.subscriptions.remove(client_id, window_id) {
+                    state.release_window(window_id).await;
+                }
+            }
+        }
+
+        ClientMessage::SubscribeApp { bundle_id } => {
+            info!("Subscribe request for app {}", bundle_id);
+            
+            let windows = state.windows();
+            let window_id = apps::frontmost_window(&windows, &bundle_id);
+            
+            let offer = match window_id {
+                Some(window_id) => {
+                    log_window_viewed(state, session_id, &windows, window_id).await;
+                    state.subscribe_window(window_id).await?
                 }
+                // Followed once a window of the app appears
+                None => None,
+            };
+            // Only followed once streaming, so a failed subscribe leaves nothing behind
+            state.apps.follow(client_id, &bundle_id, window_id);
+            
+            let response = ServerMessage::AppWindowChanged { bundle_id, window_id };
+            let json = serde_json::to_string(&response)?;
+            write
+                .send(Message::Text(json))
+                .await
+                .map_err(|e| anyhow!("Send error: {}", e))?;
+            
+            if let (Some(offer_sdp), Some(window_id)) = (offer, window_id) {
+                send_offer(state, write, window_id, offer_sdp).await?;
+            }
+        }
+
+        ClientMessage::UnsubscribeApp { bundle_id } => {
+            info!("Unsubscribe request for app {}", bundle_id);
+            
+            if let Some(window_id) = state.apps.unfollow(client_id, &bundle_id) {
+                state.release_window(window_id).await;
             }
         }
 
-        IncomingMessage::Viewport { window_id, x, y, width, height } => {
+        ClientMessage::Viewport { window_id, x, y, width, height } => {
             debug!("Viewport update for window {}: x={}, y={}, w={}, h={}", 
                    window_id, x, y, width, height);
             
-            let viewport = crate::video::Viewport { x, y, width, height };
-            state.set_viewport(window_id, viewport);
-            
-            // Request a keyframe when viewport changes significantly
-            // This ensures the client gets a fresh frame with the new crop
-            if let Err(e) = crate::capture::request_keyframe(window_id) {
-                debug!("Could not request keyframe for viewport change: {}", e);
+            let relayed = state.relay.forward(window_id, |window_id| {
+                ClientMessage::Viewport { window_id, x, y, width, height }
+            })?;
+            if !relayed {
+                let viewport = crate::video::Viewport { x, y, width, height };
+                state.set_viewport(window_id, viewport);
+                
+                // Request a keyframe when viewport changes significantly
+                // This ensures the client gets a fresh frame with the new crop
+                if let Err(e) = crate::capture::request_keyframe(window_id) {
+                    debug!("Could not request keyframe for viewport change: {}", e);
+                }
             }
         }
 
-        IncomingMessage::Mouse(event) => {
+        ClientMessage::Mouse(event) => {
             debug!("Mouse event: {:?}", event);
-            state.input_injector.inject_mouse(&event)?;
+            let bounds = event.relative.then(|| state.window_bounds(event.window_id)).flatten();
+            let event = input.pointer.resolve(&event, bounds.as_ref())?;
+            let relayed = state.relay.forward(event.window_id, |window_id| {
+                ClientMessage::Mouse(MouseEvent { window_id, ..event.clone() })
+            })?;
+            if !relayed {
+                state.input_injector.inject_mouse(&event)?;
+            }
         }
 
-        IncomingMessage::Key(event) => {
+        ClientMessage::Key(event) => {
             debug!("Key event: {:?}", event);
-            state.input_injector.inject_key(&event)?;
+            // Layouts describe macOS hosts; X11 looks `key` up in its own keymap
+            let event = if cfg!(target_os = "macos") || state.relay.owns(event.window_id) {
+                resolve_key(&event, input.keyboard_layout)?
+            } else {
+                event
+            };
+            let relayed = state.relay.forward(event.window_id, |window_id| {
+                ClientMessage::Key(KeyEvent { window_id, ..event.clone() })
+            })?;
+            if !relayed {
+                state.input_injector.inject_key(&event)?;
+            }
         }
 
-        IncomingMessage::Text(event) => {
+        ClientMessage::Text(event) => {
             debug!("Text event: {:?}", event);
-            state.input_injector.inject_text(&event)?;
+            let relayed = state.relay.forward(event.window_id, |window_id| {
+                ClientMessage::Text(TextEvent { window_id, ..event.clone() })
+            })?;
+            if !relayed {
+                state.input_injector.inject_text(&event)?;
+            }
         }
 
-        IncomingMessage::GetWindows => {
-            let windows = state.capture_manager.get_windows();
-            let response = OutgoingMessage::WindowList { windows };
+        ClientMessage::FileDrop(drop) => {
+            info!("Drop of {} files onto window {}", drop.files.len(), drop.window_id);
+            let relayed = state.relay.forward(drop.window_id, |window_id| {
+                ClientMessage::FileDrop(FileDrop { window_id, ..drop.clone() })
+            })?;
+            if !relayed {
+                let paths = file_drop::drop_files(state, &drop).await?;
+                
+                let response = ServerMessage::FilesDropped {
+                    window_id: drop.window_id,
+                    paths: paths.iter().map(|path| path.display().to_string()).collect(),
+                };
+                let json = serde_json::to_string(&response)?;
+                write
+                    .send(Message::Text(json))
+                    .await
+                    .map_err(|e| anyhow!("Send error: {}", e))?;
+            }
+        }
+
+        ClientMessage::SetQuality { preset, token } => {
+            admin::authorize_if_required(state, token.as_deref())?;
+            info!("Quality change requested: {:?}", preset);
+            let video_config = state.set_quality(preset);
+            state.capture_manager.apply_video_config(&video_config)?;
+            
+            let (width, height) = video_config.max_dimensions();
+            let response = ServerMessage::QualityChanged {
+                preset,
+                width,
+                height,
+                bitrate: video_config.bitrate,
+                fps: video_config.fps,
+            };
+            let json = serde_json::to_string(&response)?;
+            write
+                .send(Message::Text(json))
+                .await
+                .map_err(|e| anyhow!("Send error: {}", e))?;
+        }
+
+        ClientMessage::SetKeyboardLayout { layout } => {
+            info!("Keyboard layout set to {:?}", layout);
+            input.keyboard_layout = layout;
+            
+            let response = ServerMessage::KeyboardLayoutChanged { layout };
+            let json = serde_json::to_string(&response)?;
+            write
+                .send(Message::Text(json))
+                .await
+                .map_err(|e| anyhow!("Send error: {}", e))?;
+        }
+
+        ClientMessage::SetPointerSettings(settings) => {
+            info!("Pointer settings set to {:?}", settings);
+            input.pointer.set_settings(settings);
+            
+            let response = ServerMessage::PointerSettingsChanged(input.pointer.settings());
+            let json = serde_json::to_string(&response)?;
+            write
+                .send(Message::Text(json))
+                .await
+                .map_err(|e| anyhow!("Send error: {}", e))?;
+        }
+
+        ClientMessage::Screenshot { window_id, max_width, max_height } => {
+            debug!("Screenshot requested for window {}", window_id);
+            let data = match state.relay.request_screenshot(window_id, max_width, max_height)? {
+                // Captured by the upstream server, which answers with the encoded PNG
+                Some(response) => tokio::time::timeout(relay::SCREENSHOT_TIMEOUT, response)
+                    .await
+                    .map_err(|_| anyhow!("Timed out waiting for the screenshot of relayed window {}", window_id))?
+                    .map_err(|_| anyhow!("Upstream of relayed window {} disconnected", window_id))?,
+                None => {
+                    let png = tokio::task::spawn_blocking(move || {
+                        crate::capture::capture_screenshot(window_id, max_width, max_height)
+                    })
+                    .await??;
+                    base64::engine::general_purpose::STANDARD.encode(png)
+                }
+            };
+
+            let response = ServerMessage::Screenshot { window_id, data };
+            let json = serde_json::to_string(&response)?;
+            write
+                .send(Message::Text(json))
+                .await
+                .map_err(|e| anyhow!("Send error: {}", e))?;
+        }
+
+        ClientMessage::PauseStream { window_id, stop_capture } => {
+            // Relayed windows keep capturing upstream; their packets are dropped here
+            let stop_capture = stop_capture && !state.relay.owns(window_id);
+            state.pause_stream(window_id, stop_capture);
+            if stop_capture {
+                state.capture_manager.stop_capture(window_id)?;
+            }
+            
+            let response = ServerMessage::StreamPaused { window_id };
+            let json = serde_json::to_string(&response)?;
+            write
+                .send(Message::Text(json))
+                .await
+                .map_err(|e| anyhow!("Send error: {}", e))?;
+        }
+
+        ClientMessage::ResumeStream { window_id } => {
+            match state.resume_stream(window_id) {
+                Some(true) => state.capture_manager.start_capture(window_id, &state.video_config())?,
+                Some(false) => {}
+                None => debug!("Stream for window {} was not paused", window_id),
+            }
+            
+            // Frames were dropped while paused; the decoder needs a fresh keyframe
+            if let Err(e) = state.request_keyframe(window_id) {
+                debug!("Could not request keyframe for {}: {}", window_id, e);
+            }
+            
+            let response = ServerMessage::StreamResumed { window_id };
+            let json = serde_json::to_string(&response)?;
+            write
+                .send(Message::Text(json))
+                .await
+                .map_err(|e| anyhow!("Send error: {}", e))?;
+        }
+
+        ClientMessage::RequestKeyframe { window_id } => {
+            debug!("Keyframe requested for window {}", window_id);
+            state.request_keyframe(window_id)?;
+        }
+
+        ClientMessage::GetStats => {
+            let response = ServerMessage::Stats {
+                frames: state.frame_timings.frames(),
+                stages: state.frame_timings.summary(),
+                quality: state.picture_quality.summary(),
+            };
+            let json = serde_json::to_string(&response)?;
+            write
+                .send(Message::Text(json))
+                .await
+                .map_err(|e| anyhow!("Send error: {}", e))?;
+        }
+
+        ClientMessage::Reconfigure { token, preset, width, height, scaling } => {
+            admin::authorize(state, &token)?;
+            // Every client, this one included, is notified through the event channel
+            admin::reconfigure(state, admin::Reconfigure { preset, width, height, scaling })?;
+        }
+
+        ClientMessage::GetWindows => {
+            let response = state.window_list();
             let json = serde_json::to_string(&response)?;
             write
                 .send(Message::Text(json))
@@ -246,3 +465,36 @@ where
 
     Ok(())
 }
+
+/// Send a renegotiation offer for a newly added track
+async fn send_offer<S>(state: &ServerState, write: &mut S, window_id: u32, sdp: String) -> Result<()>
+where
+    S: SinkExt<Message> + Unpin,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    let response = ServerMessage::Offer { sdp };
+    let json = serde_json::to_string(&response)?;
+    write
+        .send(Message::Text(json))
+        .await
+        .map_err(|e| anyhow!("Send error: {}", e))?;
+    info!("Sent renegotiation offer to client for window {}", window_id);
+    
+    // Request a keyframe so client gets fresh decoder state after renegotiation
+    if let Err(e) = state.request_keyframe(window_id) {
+        debug!("Could not request keyframe for {}: {}", window_id, e);
+    }
+    Ok(())
+}
+
+/// Record in the session log that a window is being viewed
+async fn log_window_viewed(state: &ServerState, session_id: Option<i64>, windows: &[WindowInfo], window_id: u32) {
+    let Some(session_id) = session_id else {
+        return;
+    };
+    if let Some(window) = windows.iter().find(|w| w.id == window_id) {
+        if let Err(e) = state.sessions.window_viewed(session_id, window_id, &window.title, &window.app).await {
+            warn!("Failed to log window {} for session {}: {}", window_id, session_id, e);
+        }
+    }
+}
@@ -20,6 +20,10 @@ pub struct VideoConfig {
     pub target_height: u32,
     /// Whether scaling is enabled
     pub enable_scaling: bool,
+    /// Target encoder bitrate in bits per second
+    pub bitrate: u32,
+    /// Target capture/encode frame rate
+    pub fps: u32,
 }
 
 impl Default for VideoConfig {
@@ -28,17 +32,28 @@ impl Default for VideoConfig {
             target_width: 1280,
             target_height: 720,
             enable_scaling: true,
+            bitrate: 2_500_000,
+            fps: 30,
         }
     }
 }
 
 impl VideoConfig {
+    /// Largest output size the capture may be scaled to, or `(0, 0)` for native size
+    pub fn max_dimensions(&self) -> (u32, u32) {
+        if self.enable_scaling {
+            (self.target_width, self.target_height)
+        } else {
+            (0, 0)
+        }
+    }
+
     /// Create a 480p configuration
     pub fn resolution_480p() -> Self {
         Self {
             target_width: 854,
             target_height: 480,
-            enable_scaling: true,
+            ..Self::default()
         }
     }
 
@@ -47,7 +62,7 @@ impl VideoConfig {
         Self {
             target_width: 1280,
             target_height: 720,
-            enable_scaling: true,
+            ..Self::default()
         }
     }
 
@@ -56,7 +71,7 @@ impl VideoConfig {
         Self {
             target_width: 1920,
             target_height: 1080,
-            enable_scaling: true,
+            ..Self::default()
         }
     }
 }
@@ -0,0 +1,499 @@
+//! Minimal H.264 bitstream parsing
+//!
+//! Neither VideoToolbox nor the GStreamer encoders report the quantization
+//! parameter of the frames they produce, so it is read back from the
+//! bitstream. Only as much of the parameter sets and slice headers is parsed
+//! as needed to reach `slice_qp_delta`.
+
+use std::collections::HashMap;
+
+use crate::webrtc_handler::parse_annex_b;
+
+const NAL_SLICE: u8 = 1;
+const NAL_IDR_SLICE: u8 = 5;
+const NAL_SPS: u8 = 7;
+const NAL_PPS: u8 = 8;
+
+/// Slice header bytes unescaped before giving up on reaching the QP
+const MAX_SLICE_HEADER_BYTES: usize = 512;
+
+/// Slice types (`slice_type % 5`)
+const SLICE_P: u32 = 0;
+const SLICE_B: u32 = 1;
+const SLICE_I: u32 = 2;
+const SLICE_SP: u32 = 3;
+const SLICE_SI: u32 = 4;
+
+/// Reader over the RBSP of a NAL unit (emulation prevention bytes removed)
+struct BitReader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitReader {
+    fn new(payload: &[u8], limit: usize) -> Self {
+        let mut data = Vec::with_capacity(payload.len().min(limit));
+        let mut zeros = 0;
+        for &byte in payload.iter().take(limit) {
+            if zeros >= 2 && byte == 3 {
+                zeros = 0;
+                continue;
+            }
+            zeros = if byte == 0 { zeros + 1 } else { 0 };
+            data.push(byte);
+        }
+        Self { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        let byte = *self.data.get(self.pos / 8)?;
+        self.pos += 1;
+        Some(((byte >> (7 - (self.pos - 1) % 8)) & 1) as u32)
+    }
+
+    fn bits(&mut self, n: u32) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.bit()?;
+        }
+        Some(value)
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        Some(self.bit()? == 1)
+    }
+
+    /// Unsigned Exp-Golomb code
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.bit()? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some(((1u64 << zeros) - 1 + self.bits(zeros)? as u64) as u32)
+    }
+
+    /// Signed Exp-Golomb code
+    fn se(&mut self) -> Option<i32> {
+        let code = self.ue()? as i64;
+        let value = if code % 2 == 1 { (code + 1) / 2 } else { -(code / 2) };
+        Some(value as i32)
+    }
+}
+
+/// Sequence parameter set fields used by slice headers
+struct Sps {
+    separate_colour_plane: bool,
+    chroma_array_type: u32,
+    log2_max_frame_num: u32,
+    pic_order_cnt_type: u32,
+    log2_max_pic_order_cnt_lsb: u32,
+    delta_pic_order_always_zero: bool,
+    frame_mbs_only: bool,
+}
+
+/// Picture parameter set fields used by slice headers
+struct Pps {
+    sps_id: u32,
+    entropy_coding_mode: bool,
+    bottom_field_pic_order_in_frame_present: bool,
+    num_ref_idx_l0_default_active: u32,
+    num_ref_idx_l1_default_active: u32,
+    weighted_pred: bool,
+    weighted_bipred_idc: u32,
+    pic_init_qp: i32,
+    redundant_pic_cnt_present: bool,
+}
+
+/// Reads slice QPs from the access units of one stream
+///
+/// Parameter sets are remembered across frames since encoders only repeat
+/// them with keyframes.
+#[derive(Default)]
+pub struct SliceQpParser {
+    sps: HashMap<u32, Sps>,
+    pps: HashMap<u32, Pps>,
+}
+
+impl SliceQpParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// QP of every slice in an Annex-B access unit
+    ///
+    /// Empty until the stream's SPS and PPS have been seen.
+    pub fn slice_qps(&mut self, access_unit: &[u8]) -> Vec<i32> {
+        let mut qps = Vec::new();
+        for nal in parse_annex_b(access_unit) {
+            let Some(&header) = nal.first() else {
+                continue;
+            };
+            let nal_type = header & 0x1F;
+            let payload = &nal[1..];
+            match nal_type {
+                NAL_SPS => {
+                    if let Some((id, sps)) = parse_sps(&mut BitReader::new(payload, usize::MAX)) {
+                        self.sps.insert(id, sps);
+                    }
+                }
+                NAL_PPS => {
+                    if let Some((id, pps)) = parse_pps(&mut BitReader::new(payload, usize::MAX)) {
+                        self.pps.insert(id, pps);
+                    }
+                }
+                NAL_SLICE | NAL_IDR_SLICE => {
+                    let mut reader = BitReader::new(payload, MAX_SLICE_HEADER_BYTES);
+                    if let Some(qp) = self.slice_qp(&mut reader, nal_type, (header >> 5) & 0x3) {
+                        qps.push(qp);
+                    }
+                }
+                _ => {}
+            }
+        }
+        qps
+    }
+
+    /// Parse a slice header up to `slice_qp_delta`
+    fn slice_qp(&self, r: &mut BitReader, nal_type: u8, nal_ref_idc: u8) -> Option<i32> {
+        let idr = nal_type == NAL_IDR_SLICE;
+
+        r.ue()?; // first_mb_in_slice
+        let slice_type = r.ue()? % 5;
+        let pps = self.pps.get(&r.ue()?)?;
+        let sps = self.sps.get(&pps.sps_id)?;
+
+        if sps.separate_colour_plane {
+            r.bits(2)?; // colour_plane_id
+        }
+        r.bits(sps.log2_max_frame_num)?; // frame_num
+
+        let mut field_pic = false;
+        if !sps.frame_mbs_only {
+            field_pic = r.flag()?;
+            if field_pic {
+                r.flag()?; // bottom_field_flag
+            }
+        }
+        if idr {
+            r.ue()?; // idr_pic_id
+        }
+        if sps.pic_order_cnt_type == 0 {
+            r.bits(sps.log2_max_pic_order_cnt_lsb)?;
+            if pps.bottom_field_pic_order_in_frame_present && !field_pic {
+                r.se()?;
+            }
+        }
+        if sps.pic_order_cnt_type == 1 && !sps.delta_pic_order_always_zero {
+            r.se()?;
+            if pps.bottom_field_pic_order_in_frame_present && !field_pic {
+                r.se()?;
+            }
+        }
+        if pps.redundant_pic_cnt_present {
+            r.ue()?;
+        }
+
+        let is_b = slice_type == SLICE_B;
+        let is_p = slice_type == SLICE_P || slice_type == SLICE_SP;
+        let is_intra = slice_type == SLICE_I || slice_type == SLICE_SI;
+
+        if is_b {
+            r.flag()?; // direct_spatial_mv_pred_flag
+        }
+        let mut num_ref_idx_l0 = pps.num_ref_idx_l0_default_active;
+        let mut num_ref_idx_l1 = pps.num_ref_idx_l1_default_active;
+        if (is_p || is_b) && r.flag()? {
+            num_ref_idx_l0 = r.ue()? + 1;
+            if is_b {
+                num_ref_idx_l1 = r.ue()? + 1;
+            }
+        }
+
+        if !is_intra {
+            skip_ref_pic_list_modification(r)?;
+        }
+        if is_b {
+            skip_ref_pic_list_modification(r)?;
+        }
+
+        if (pps.weighted_pred && is_p) || (pps.weighted_bipred_idc == 1 && is_b) {
+            let chroma = sps.chroma_array_type != 0;
+            r.ue()?; // luma_log2_weight_denom
+            if chroma {
+                r.ue()?;
+            }
+            skip_pred_weights(r, num_ref_idx_l0, chroma)?;
+            if is_b {
+                skip_pred_weights(r, num_ref_idx_l1, chroma)?;
+            }
+        }
+
+        if nal_ref_idc != 0 {
+            if idr {
+                r.flag()?; // no_output_of_prior_pics_flag
+                r.flag()?; // long_term_reference_flag
+            } else if r.flag()? {
+                skip_memory_management_operations(r)?;
+            }
+        }
+
+        if pps.entropy_coding_mode && !is_intra {
+            r.ue()?; // cabac_init_idc
+        }
+
+        Some(pps.pic_init_qp + r.se()?)
+    }
+}
+
+fn parse_sps(r: &mut BitReader) -> Option<(u32, Sps)> {
+    let profile_idc = r.bits(8)?;
+    r.bits(16)?; // constraint flags and level_idc
+    let id = r.ue()?;
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    if matches!(profile_idc, 100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135) {
+        chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = r.flag()?;
+        }
+        r.ue()?; // bit_depth_luma_minus8
+        r.ue()?; // bit_depth_chroma_minus8
+        r.flag()?; // qpprime_y_zero_transform_bypass_flag
+        if r.flag()? {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.flag()? {
+                    skip_scaling_list(r, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    let log2_max_frame_num = r.ue()? + 4;
+    let pic_order_cnt_type = r.ue()?;
+    let mut log2_max_pic_order_cnt_lsb = 0;
+    let mut delta_pic_order_always_zero = false;
+    if pic_order_cnt_type == 0 {
+        log2_max_pic_order_cnt_lsb = r.ue()? + 4;
+    } else if pic_order_cnt_type == 1 {
+        delta_pic_order_always_zero = r.flag()?;
+        r.se()?; // offset_for_non_ref_pic
+        r.se()?; // offset_for_top_to_bottom_field
+        for _ in 0..r.ue()? {
+            r.se()?;
+        }
+    }
+
+    r.ue()?; // max_num_ref_frames
+    r.flag()?; // gaps_in_frame_num_value_allowed_flag
+    r.ue()?; // pic_width_in_mbs_minus1
+    r.ue()?; // pic_height_in_map_units_minus1
+    let frame_mbs_only = r.flag()?;
+
+    Some((
+        id,
+        Sps {
+            separate_colour_plane,
+            chroma_array_type: if separate_colour_plane { 0 } else { chroma_format_idc },
+            log2_max_frame_num,
+            pic_order_cnt_type,
+            log2_max_pic_order_cnt_lsb,
+            delta_pic_order_always_zero,
+            frame_mbs_only,
+        },
+    ))
+}
+
+fn parse_pps(r: &mut BitReader) -> Option<(u32, Pps)> {
+    let id = r.ue()?;
+    let sps_id = r.ue()?;
+    let entropy_coding_mode = r.flag()?;
+    let bottom_field_pic_order_in_frame_present = r.flag()?;
+    if r.ue()? != 0 {
+        // Slice groups (FMO) are not produced by the encoders we use
+        return None;
+    }
+    let num_ref_idx_l0_default_active = r.ue()? + 1;
+    let num_ref_idx_l1_default_active = r.ue()? + 1;
+    let weighted_pred = r.flag()?;
+    let weighted_bipred_idc = r.bits(2)?;
+    let pic_init_qp = 26 + r.se()?;
+    r.se()?; // pic_init_qs_minus26
+    r.se()?; // chroma_qp_index_offset
+    r.flag()?; // deblocking_filter_control_present_flag
+    r.flag()?; // constrained_intra_pred_flag
+    let redundant_pic_cnt_present = r.flag()?;
+
+    Some((
+        id,
+        Pps {
+            sps_id,
+            entropy_coding_mode,
+            bottom_field_pic_order_in_frame_present,
+            num_ref_idx_l0_default_active,
+            num_ref_idx_l1_default_active,
+            weighted_pred,
+            weighted_bipred_idc,
+            pic_init_qp,
+            redundant_pic_cnt_present,
+        },
+    ))
+}
+
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Option<()> {
+    let mut last = 8;
+    let mut next = 8;
+    for _ in 0..size {
+        if next != 0 {
+            next = (last + r.se()? + 256) % 256;
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Some(())
+}
+
+fn skip_ref_pic_list_modification(r: &mut BitReader) -> Option<()> {
+    if r.flag()? {
+        while r.ue()? != 3 {
+            r.ue()?;
+        }
+    }
+    Some(())
+}
+
+fn skip_pred_weights(r: &mut BitReader, num_ref_idx: u32, chroma: bool) -> Option<()> {
+    for _ in 0..num_ref_idx {
+        if r.flag()? {
+            r.se()?;
+            r.se()?;
+        }
+        if chroma && r.flag()? {
+            for _ in 0..4 {
+                r.se()?;
+            }
+        }
+    }
+    Some(())
+}
+
+fn skip_memory_management_operations(r: &mut BitReader) -> Option<()> {
+    loop {
+        let operation = r.ue()?;
+        if operation == 0 {
+            return Some(());
+        }
+        if matches!(operation, 1 | 2 | 3 | 4 | 6) {
+            r.ue()?;
+        }
+        if operation == 3 {
+            r.ue()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes RBSP bits with Exp-Golomb codes, for building test NAL units
+    #[derive(Default)]
+    struct BitWriter {
+        bits: Vec<bool>,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u32, n: u32) -> &mut Self {
+            for i in (0..n).rev() {
+                self.bits.push((value >> i) & 1 == 1);
+            }
+            self
+        }
+
+        fn ue(&mut self, value: u32) -> &mut Self {
+            let code = value + 1;
+            let len = 32 - code.leading_zeros();
+            self.bits(0, len - 1).bits(code, len)
+        }
+
+        fn se(&mut self, value: i32) -> &mut Self {
+            let code = if value > 0 { 2 * value - 1 } else { -2 * value };
+            self.ue(code as u32)
+        }
+
+        /// NAL unit with start code, trailing bits and emulation prevention
+        // `usize::is_multiple_of` needs Rust 1.87
+        #[allow(clippy::manual_is_multiple_of)]
+        fn nal(&mut self, header: u8) -> Vec<u8> {
+            self.bits.push(true);
+            while self.bits.len() % 8 != 0 {
+                self.bits.push(false);
+            }
+            let rbsp: Vec<u8> = self
+                .bits
+                .chunks(8)
+                .map(|byte| byte.iter().fold(0, |acc, &bit| (acc << 1) | bit as u8))
+                .collect();
+
+            let mut nal = vec![0, 0, 0, 1, header];
+            let mut zeros = 0;
+            for byte in rbsp {
+                if zeros >= 2 && byte <= 3 {
+                    nal.push(3);
+                    zeros = 0;
+                }
+                zeros = if byte == 0 { zeros + 1 } else { 0 };
+                nal.push(byte);
+            }
+            nal
+        }
+    }
+
+    /// High profile SPS (poc type 0) and a CABAC PPS with pic_init_qp 30
+    fn parameter_sets() -> Vec<u8> {
+        let mut sps = BitWriter::default();
+        sps.bits(100, 8).bits(0, 8).bits(31, 8).ue(0); // profile, constraints, level, id
+        sps.ue(1).ue(0).ue(0).bits(0, 1).bits(0, 1); // 4:2:0, 8 bit, no scaling matrix
+        sps.ue(0).ue(0).ue(0); // log2_max_frame_num 4, poc type 0, log2_max_poc_lsb 4
+        sps.ue(1).bits(0, 1).ue(119).ue(67).bits(1, 1); // refs, 1920x1088, frame_mbs_only
+
+        let mut pps = BitWriter::default();
+        pps.ue(0).ue(0).bits(1, 1).bits(0, 1).ue(0); // ids, CABAC, no slice groups
+        pps.ue(0).ue(0).bits(0, 1).bits(0, 2).se(4).se(0).se(0); // refs, weights, qp 30
+        pps.bits(1, 1).bits(0, 1).bits(0, 1);
+
+        let mut data = sps.nal(0x67);
+        data.extend(pps.nal(0x68));
+        data
+    }
+
+    #[test]
+    fn test_slice_qps() {
+        let mut parser = SliceQpParser::new();
+
+        // Without parameter sets the slices can't be parsed
+        let mut p_slice = BitWriter::default();
+        p_slice.ue(0).ue(5).ue(0).bits(1, 4).bits(2, 4); // P slice, frame_num, poc lsb
+        p_slice.bits(0, 1).bits(0, 1).bits(0, 1).ue(0).se(-3); // no overrides/mmco, cabac_init, qp
+        let p_frame = p_slice.nal(0x41);
+        assert!(parser.slice_qps(&p_frame).is_empty());
+
+        // IDR frame with two slices after SPS/PPS
+        let mut keyframe = parameter_sets();
+        for (first_mb, qp_delta) in [(0, 2), (4080, 0)] {
+            let mut slice = BitWriter::default();
+            slice.ue(first_mb).ue(7).ue(0).bits(0, 4).ue(0).bits(0, 4); // I slice, idr_pic_id
+            slice.bits(0, 1).bits(0, 1).se(qp_delta); // dec_ref_pic_marking, qp
+            keyframe.extend(slice.nal(0x65));
+        }
+        assert_eq!(parser.slice_qps(&keyframe), [32, 30]);
+
+        // Parameter sets are remembered for later frames
+        assert_eq!(parser.slice_qps(&p_frame), [27]);
+    }
+}
@@ -1,6 +1,10 @@
 //! Video processing module using GStreamer for scaling and cropping
 
 mod gst_pipeline;
+mod h264;
+mod quality;
 
 pub use gst_pipeline::{VideoPipeline, VideoConfig, Viewport};
+pub use h264::SliceQpParser;
+pub use quality::QualityPreset;
 
@@ -0,0 +1,62 @@
+//! Video settings for named quality presets
+//!
+//! `QualityPreset` is part of the wire protocol (clients switch it via
+//! `set_quality`); this maps it onto the server's video configuration.
+
+pub use blink_protocol::QualityPreset;
+
+use super::VideoConfig;
+use crate::config::VideoResolution;
+
+impl From<QualityPreset> for VideoResolution {
+    fn from(preset: QualityPreset) -> Self {
+        match preset {
+            QualityPreset::Low => VideoResolution::Resolution480p,
+            QualityPreset::Medium => VideoResolution::Resolution720p,
+            QualityPreset::High | QualityPreset::LosslessText => VideoResolution::Resolution1080p,
+        }
+    }
+}
+
+impl From<QualityPreset> for VideoConfig {
+    fn from(preset: QualityPreset) -> Self {
+        let (target_width, target_height) = preset.dimensions();
+        VideoConfig {
+            target_width,
+            target_height,
+            enable_scaling: preset.scaling_enabled(),
+            bitrate: preset.bitrate(),
+            fps: preset.fps(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quality_preset_video_config() {
+        let low = VideoConfig::from(QualityPreset::Low);
+        assert_eq!((low.target_width, low.target_height), (854, 480));
+        assert_eq!(low.fps, 15);
+        assert!(low.enable_scaling);
+
+        let text = VideoConfig::from(QualityPreset::LosslessText);
+        assert!(!text.enable_scaling);
+        assert_eq!(text.bitrate, 12_000_000);
+
+        // The default preset matches the default video config
+        let medium = VideoConfig::from(QualityPreset::default());
+        let default = VideoConfig::default();
+        assert_eq!(medium.target_width, default.target_width);
+        assert_eq!(medium.bitrate, default.bitrate);
+    }
+
+    #[test]
+    fn test_quality_preset_resolution() {
+        for preset in [QualityPreset::Low, QualityPreset::Medium, QualityPreset::High] {
+            assert_eq!(VideoResolution::from(preset).dimensions(), preset.dimensions());
+        }
+    }
+}
@@ -14,16 +14,19 @@ use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
 use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::peer_connection::configuration::RTCConfiguration;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
-use webrtc::peer_connection::RTCPeerConnection;
 use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
 use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
 
+use crate::server::client::ClientId;
+
+use peer::PeerState;
 pub use tracks::{create_window_track, H264RtpPacketizer};
+pub(crate) use tracks::parse_annex_b;
 
 /// Manages WebRTC peer connections and video tracks
 pub struct WebRtcManager {
-    /// Current peer connection (single client for now)
-    peer_connection: Option<Arc<RTCPeerConnection>>,
+    /// Current peer connection and the client that owns it (single client for now)
+    peer: Option<PeerState>,
     /// Active video tracks by window ID
     window_tracks: HashMap<u32, Arc<TrackLocalStaticRTP>>,
     /// API for creating peer connections
@@ -44,15 +47,20 @@ impl WebRtcManager {
             .build();
 
         Self {
-            peer_connection: None,
+            peer: None,
             window_tracks: HashMap::new(),
             api,
         }
     }
 
     /// Handle WebRTC offer from client
-    pub async fn handle_offer(&mut self, sdp: &str) -> Result<String> {
+    ///
+    /// The new peer connection replaces the one of any previous client.
+    pub async fn handle_offer(&mut self, sdp: &str, client_id: ClientId) -> Result<String> {
         info!("Processing WebRTC offer");
+        if let Some(previous) = self.client_id().filter(|previous| *previous != client_id) {
+            info!("Peer connection of {} replaced by {}", previous, client_id);
+        }
 
         // Create RTCConfiguration with STUN servers
         let config = RTCConfiguration {
@@ -68,12 +76,12 @@ impl WebRtcManager {
 
         // Set up event handlers
         peer_connection.on_ice_connection_state_change(Box::new(move |state| {
-            info!("ICE connection state changed: {:?}", state);
+            info!("ICE connection state of {} changed: {:?}", client_id, state);
             Box::pin(async {})
         }));
 
         peer_connection.on_peer_connection_state_change(Box::new(move |state| {
-            info!("Peer connection state changed: {:?}", state);
+            info!("Peer connection state of {} changed: {:?}", client_id, state);
             Box::pin(async {})
         }));
 
@@ -88,7 +96,7 @@ impl WebRtcManager {
         peer_connection.set_local_description(answer.clone()).await?;
 
         // Store peer connection
-        self.peer_connection = Some(peer_connection);
+        self.peer = Some(PeerState::new(peer_connection, client_id));
 
         info!("WebRTC answer created");
         Ok(answer.sdp)
@@ -96,10 +104,11 @@ impl WebRtcManager {
 
     /// Add ICE candidate from client
     pub async fn add_ice_candidate(&mut self, candidate: crate::server::websocket::IceCandidate) -> Result<()> {
-        let peer_connection = self
-            .peer_connection
+        let peer_connection = &self
+            .peer
             .as_ref()
-            .ok_or_else(|| anyhow!("No peer connection established"))?;
+            .ok_or_else(|| anyhow!("No peer connection established"))?
+            .connection;
 
         let ice_candidate = RTCIceCandidateInit {
             candidate: candidate.candidate,
@@ -117,10 +126,11 @@ impl WebRtcManager {
     /// Add a video track for a window and return renegotiation offer if needed
     /// Returns Some(sdp) if renegotiation offer was created, None if track already existed
     pub async fn add_window_track(&mut self, window_id: u32) -> Result<Option<String>> {
-        let peer_connection = self
-            .peer_connection
+        let peer_connection = &self
+            .peer
             .as_ref()
-            .ok_or_else(|| anyhow!("No peer connection established"))?;
+            .ok_or_else(|| anyhow!("No peer connection established"))?
+            .connection;
 
         // Check if track already exists
         if self.window_tracks.contains_key(&window_id) {
@@ -160,10 +170,11 @@ impl WebRtcManager {
     
     /// Handle renegotiation answer from client
     pub async fn handle_renegotiation_answer(&mut self, sdp: &str) -> Result<()> {
-        let peer_connection = self
-            .peer_connection
+        let peer_connection = &self
+            .peer
             .as_ref()
-            .ok_or_else(|| anyhow!("No peer connection established"))?;
+            .ok_or_else(|| anyhow!("No peer connection established"))?
+            .connection;
         
         let answer = RTCSessionDescription::answer(sdp.to_string())?;
         peer_connection.set_remote_description(answer).await?;
@@ -189,7 +200,12 @@ impl WebRtcManager {
 
     /// Check if peer connection is established
     pub fn is_connected(&self) -> bool {
-        self.peer_connection.is_some()
+        self.peer.is_some()
+    }
+
+    /// Client whose peer connection receives the tracks
+    pub fn client_id(&self) -> Option<ClientId> {
+        self.peer.as_ref().map(|peer| peer.client_id)
     }
 }
 
@@ -4,14 +4,16 @@ use std::sync::Arc;
 
 use webrtc::peer_connection::RTCPeerConnection;
 
+use crate::server::client::ClientId;
+
 /// Peer connection wrapper with additional state
 pub struct PeerState {
     pub connection: Arc<RTCPeerConnection>,
-    pub client_id: String,
+    pub client_id: ClientId,
 }
 
 impl PeerState {
-    pub fn new(connection: Arc<RTCPeerConnection>, client_id: String) -> Self {
+    pub fn new(connection: Arc<RTCPeerConnection>, client_id: ClientId) -> Self {
         Self {
             connection,
             client_id,
@@ -4,6 +4,7 @@
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
 
 use anyhow::Result;
 use tracing::{debug, trace};
@@ -24,15 +25,36 @@ const NAL_START_CODE_3: [u8; 3] = [0x00, 0x00, 0x01];
 const NAL_TYPE_MASK: u8 = 0x1F;
 const NAL_TYPE_FU_A: u8 = 28;
 
+/// Payload bytes of a frame sent at once before pacing starts
+const PACING_BURST_BYTES: usize = 10 * MAX_RTP_PAYLOAD_SIZE;
+
+/// Longest time the packets of one frame are spread over
+///
+/// Frames too large to be sent at the pacing rate within this time are sent
+/// faster, so pacing never delays the next frame.
+const MAX_FRAME_PACING: Duration = Duration::from_millis(10);
+
 /// RTP packetizer for H.264 video
 pub struct H264RtpPacketizer {
     sequence_number: AtomicU16,
+    /// Target send rate in bits/s for the packets of a frame (0 = no pacing)
+    pacing_rate: u32,
 }
 
 impl H264RtpPacketizer {
+    /// Packetizer that sends the packets of a frame in one burst
     pub fn new() -> Self {
+        Self::with_pacing_rate(0)
+    }
+
+    /// Packetizer that spreads the packets of a frame at `pacing_rate` bits/s
+    ///
+    /// Large keyframes sent in one burst overflow the queues of wifi links
+    /// and get dropped; pacing lets them drain.
+    pub fn with_pacing_rate(pacing_rate: u32) -> Self {
         Self {
             sequence_number: AtomicU16::new(0),
+            pacing_rate,
         }
     }
     
@@ -47,56 +69,82 @@ impl H264RtpPacketizer {
         annex_b_data: &[u8],
         timestamp: u32,
     ) -> Result<()> {
+        let packets = self.packetize(annex_b_data, timestamp);
+        self.send_packets(track, &packets).await?;
+        Ok(())
+    }
+    
+    /// Packetize H.264 Annex-B data into RTP packets for one access unit
+    pub fn packetize(&self, annex_b_data: &[u8], timestamp: u32) -> Vec<Packet> {
         // Parse NAL units from Annex-B format
         let nal_units = parse_annex_b(annex_b_data);
         
-        if nal_units.is_empty() {
-            return Ok(());
-        }
-        
+        let mut packets = Vec::new();
         let total_nals = nal_units.len();
         
         for (idx, nal) in nal_units.iter().enumerate() {
             let is_last_nal = idx == total_nals - 1;
-            self.send_nal_unit(track, nal, timestamp, is_last_nal).await?;
+            self.packetize_nal_unit(&mut packets, nal, timestamp, is_last_nal);
         }
         
-        trace!("Sent {} NAL units, timestamp={}", total_nals, timestamp);
-        Ok(())
+        trace!("Packetized {} NAL units into {} packets, timestamp={}", total_nals, packets.len(), timestamp);
+        packets
     }
     
-    /// Send a single NAL unit, fragmenting if necessary
-    async fn send_nal_unit(
+    /// Write RTP packets to a track, paced at the packetizer's pacing rate
+    ///
+    /// Returns the time spent waiting between packets. The timer resolution
+    /// is one millisecond, so paced packets leave in small bursts each
+    /// millisecond.
+    pub async fn send_packets(&self, track: &TrackLocalStaticRTP, packets: &[Packet]) -> Result<Duration> {
+        let total_bytes: usize = packets.iter().map(|packet| packet.payload.len()).sum();
+        let start = tokio::time::Instant::now();
+        let mut sent_bytes = 0;
+        let mut paced = Duration::ZERO;
+        
+        for packet in packets {
+            let offset = pacing_offset(sent_bytes, total_bytes, self.pacing_rate);
+            if !offset.is_zero() {
+                let wait_start = tokio::time::Instant::now();
+                tokio::time::sleep_until(start + offset).await;
+                paced += wait_start.elapsed();
+            }
+            track.write_rtp(packet).await?;
+            sent_bytes += packet.payload.len();
+        }
+        Ok(paced)
+    }
+    
+    /// Packetize a single NAL unit, fragmenting if necessary
+    fn packetize_nal_unit(
         &self,
-        track: &TrackLocalStaticRTP,
+        packets: &mut Vec<Packet>,
         nal: &[u8],
         timestamp: u32,
         is_last_nal: bool,
-    ) -> Result<()> {
+    ) {
         if nal.is_empty() {
-            return Ok(());
+            return;
         }
         
         if nal.len() <= MAX_RTP_PAYLOAD_SIZE {
             // Single NAL unit packet - fits in one RTP packet
             let marker = is_last_nal; // Marker bit indicates end of access unit
-            self.send_rtp_packet(track, nal, timestamp, marker).await?;
+            packets.push(self.rtp_packet(nal, timestamp, marker));
         } else {
             // FU-A fragmentation required
-            self.send_fragmented_nal(track, nal, timestamp, is_last_nal).await?;
+            self.packetize_fragmented_nal(packets, nal, timestamp, is_last_nal);
         }
-        
-        Ok(())
     }
     
-    /// Send NAL unit using FU-A fragmentation
-    async fn send_fragmented_nal(
+    /// Packetize NAL unit using FU-A fragmentation
+    fn packetize_fragmented_nal(
         &self,
-        track: &TrackLocalStaticRTP,
+        packets: &mut Vec<Packet>,
         nal: &[u8],
         timestamp: u32,
         is_last_nal: bool,
-    ) -> Result<()> {
+    ) {
         let nal_header = nal[0];
         let nal_type = nal_header & NAL_TYPE_MASK;
         let nri = nal_header & 0x60; // NAL ref idc
@@ -134,24 +182,16 @@ impl H264RtpPacketizer {
             // Marker bit only on last fragment of last NAL
             let marker = is_last && is_last_nal;
             
-            self.send_rtp_packet(track, &fu_packet, timestamp, marker).await?;
+            packets.push(self.rtp_packet(&fu_packet, timestamp, marker));
             
             offset += fragment_size;
             is_first = false;
         }
-        
-        Ok(())
     }
     
-    /// Send a single RTP packet
-    async fn send_rtp_packet(
-        &self,
-        track: &TrackLocalStaticRTP,
-        payload: &[u8],
-        timestamp: u32,
-        marker: bool,
-    ) -> Result<()> {
-        let packet = Packet {
+    /// Build a single RTP packet
+    fn rtp_packet(&self, payload: &[u8], timestamp: u32, marker: bool) -> Packet {
+        Packet {
             header: Header {
                 version: 2,
                 padding: false,
@@ -164,10 +204,7 @@ impl H264RtpPacketizer {
                 ..Default::default()
             },
             payload: payload.to_vec().into(),
-        };
-        
-        track.write_rtp(&packet).await?;
-        Ok(())
+        }
     }
 }
 
@@ -177,8 +214,22 @@ impl Default for H264RtpPacketizer {
     }
 }
 
+/// Time after the start of a frame at which the packet after `sent_bytes` is sent
+///
+/// The first `PACING_BURST_BYTES` go out at once; the rest at `pacing_rate`
+/// bits/s, or faster if that would take longer than `MAX_FRAME_PACING`.
+fn pacing_offset(sent_bytes: usize, total_bytes: usize, pacing_rate: u32) -> Duration {
+    if pacing_rate == 0 || sent_bytes <= PACING_BURST_BYTES {
+        return Duration::ZERO;
+    }
+    
+    let paced_bits = (total_bytes - PACING_BURST_BYTES) as f64 * 8.0;
+    let rate = (pacing_rate as f64).max(paced_bits / MAX_FRAME_PACING.as_secs_f64());
+    Duration::from_secs_f64((sent_bytes - PACING_BURST_BYTES) as f64 * 8.0 / rate)
+}
+
 /// Parse Annex-B formatted H.264 data into individual NAL units
-fn parse_annex_b(data: &[u8]) -> Vec<&[u8]> {
+pub(crate) fn parse_annex_b(data: &[u8]) -> Vec<&[u8]> {
     let mut nal_units = Vec::new();
     let mut i = 0;
     
@@ -272,3 +323,23 @@ pub async fn write_rtp_to_track(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacing_offset() {
+        // Small frames and disabled pacing go out in one burst
+        assert_eq!(pacing_offset(8_000, 10_000, 50_000_000), Duration::ZERO);
+        assert_eq!(pacing_offset(100_000, 200_000, 0), Duration::ZERO);
+
+        // Bytes after the burst are sent at the pacing rate
+        let total = PACING_BURST_BYTES + 25_000;
+        assert_eq!(pacing_offset(PACING_BURST_BYTES + 12_500, total, 50_000_000), Duration::from_millis(2));
+
+        // Large keyframes are spread over at most MAX_FRAME_PACING
+        let total = PACING_BURST_BYTES + 500_000;
+        assert_eq!(pacing_offset(total, total, 50_000_000), MAX_FRAME_PACING);
+    }
+}
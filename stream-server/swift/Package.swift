@@ -22,6 +22,11 @@ let package = Package(
                 .unsafeFlags(["-emit-objc-header-path", ".build/SCKBridge-Swift.h"])
             ]
         ),
+        .testTarget(
+            name: "SCKBridgeTests",
+            dependencies: ["SCKBridge"],
+            path: "Tests/SCKBridgeTests"
+        ),
     ]
 )
 
@@ -0,0 +1,124 @@
+// FrameThrottle - Content-aware frame rate limiting
+// Static windows are encoded at a low idle rate and ramp back to full rate on change
+
+import Foundation
+import ScreenCaptureKit
+import CoreMedia
+import CoreVideo
+
+/// Decides which captured frames to encode
+/// Frames are passed through at the capture rate while content changes; once a window
+/// has been static for `idleDelay`, only `idleFps` frames per second are encoded.
+/// A frame skipped by the throttle is encoded on a later idle tick, so the last
+/// state of the window always reaches the client.
+final class FrameThrottle {
+    /// How long content must be unchanged before throttling starts
+    private let idleDelay: TimeInterval
+    private var lastChangeTime: TimeInterval = 0
+    private var lastEncodeTime: TimeInterval = -.infinity
+    private var hasSkippedFrame = false
+
+    init(idleDelay: TimeInterval = 1.0) {
+        self.idleDelay = idleDelay
+    }
+
+    /// Returns true if a captured frame should be encoded
+    /// An idleFps of 0 disables throttling
+    func shouldEncode(contentChanged: Bool, idleFps: Int, now: TimeInterval) -> Bool {
+        if contentChanged {
+            lastChangeTime = now
+        }
+
+        let isIdle = idleFps > 0 && now - lastChangeTime >= idleDelay
+        if isIdle && now - lastEncodeTime < 1.0 / Double(idleFps) {
+            hasSkippedFrame = true
+            return false
+        }
+
+        lastEncodeTime = now
+        hasSkippedFrame = false
+        return true
+    }
+
+    /// Returns true if the last skipped frame should be encoded now
+    /// Called for frames without new content, which ScreenCaptureKit keeps delivering
+    /// while a window is static.
+    func shouldEncodeSkipped(idleFps: Int, now: TimeInterval) -> Bool {
+        guard hasSkippedFrame, idleFps <= 0 || now - lastEncodeTime >= 1.0 / Double(idleFps) else {
+            return false
+        }
+
+        lastEncodeTime = now
+        hasSkippedFrame = false
+        return true
+    }
+}
+
+/// Detects whether a captured frame differs from the previous one
+/// Updates smaller than `minDirtyFraction` of the frame, like a blinking cursor,
+/// do not count as changes. Frames without dirty rects are compared by a sampled hash.
+final class FrameChangeDetector {
+    private let minDirtyFraction: Double
+    private var lastHash: UInt64?
+
+    init(minDirtyFraction: Double = 0.002) {
+        self.minDirtyFraction = minDirtyFraction
+    }
+
+    /// `dirtyFraction` is the dirty share of the frame area, nil if unknown
+    /// `hash` is only evaluated when the dirty rects are unknown
+    func hasChanged(dirtyFraction: Double?, hash: () -> UInt64) -> Bool {
+        guard let dirtyFraction = dirtyFraction else {
+            let frameHash = hash()
+            defer { lastHash = frameHash }
+            return frameHash != lastHash
+        }
+
+        // The next frame without dirty rects is compared against unknown content
+        lastHash = nil
+        return dirtyFraction >= minDirtyFraction
+    }
+}
+
+/// Share of the frame area covered by the frame's dirty rects
+/// Returns nil if ScreenCaptureKit did not report dirty rects
+@available(macOS 12.3, *)
+func frameDirtyFraction(_ sampleBuffer: CMSampleBuffer, pixelBuffer: CVPixelBuffer) -> Double? {
+    guard let attachments = CMSampleBufferGetSampleAttachmentsArray(sampleBuffer, createIfNecessary: false) as? [[SCStreamFrameInfo: Any]],
+          let dirtyRects = attachments.first?[.dirtyRects] as? [NSDictionary] else {
+        return nil
+    }
+
+    let frameArea = Double(CVPixelBufferGetWidth(pixelBuffer) * CVPixelBufferGetHeight(pixelBuffer))
+    guard frameArea > 0 else { return nil }
+
+    let dirtyArea = dirtyRects
+        .compactMap { CGRect(dictionaryRepresentation: $0) }
+        .reduce(0.0) { $0 + Double($1.width * $1.height) }
+    return min(dirtyArea / frameArea, 1.0)
+}
+
+/// Hash of a 64x64 grid of pixels of a BGRA frame
+func sampledFrameHash(_ pixelBuffer: CVPixelBuffer) -> UInt64 {
+    CVPixelBufferLockBaseAddress(pixelBuffer, .readOnly)
+    defer { CVPixelBufferUnlockBaseAddress(pixelBuffer, .readOnly) }
+
+    // FNV-1a offset basis
+    var hash: UInt64 = 0xcbf29ce484222325
+    guard let base = CVPixelBufferGetBaseAddress(pixelBuffer) else { return hash }
+
+    let width = CVPixelBufferGetWidth(pixelBuffer)
+    let height = CVPixelBufferGetHeight(pixelBuffer)
+    let bytesPerRow = CVPixelBufferGetBytesPerRow(pixelBuffer)
+    let samples = 64
+
+    for row in 0..<samples {
+        let y = row * height / samples
+        for column in 0..<samples {
+            let x = column * width / samples
+            let pixel = base.load(fromByteOffset: y * bytesPerRow + x * 4, as: UInt32.self)
+            hash = (hash ^ UInt64(pixel)) &* 0x100000001b3
+        }
+    }
+    return hash
+}
@@ -24,6 +24,8 @@ public class H264Encoder {
     private let windowId: UInt32
     private let width: Int
     private let height: Int
+    private var bitrate: Int
+    private var fps: Int
     private var frameCallback: EncodedFrameCallback?
     private var frameCount: UInt64 = 0
     private let encoderQueue = DispatchQueue(label: "h264encoder", qos: .userInteractive)
@@ -32,10 +34,13 @@ public class H264Encoder {
     private var sps: Data?
     private var pps: Data?
     
-    public init(windowId: UInt32, width: Int, height: Int) {
+    public init(windowId: UInt32, width: Int, height: Int, bitrate: Int = 0, fps: Int = 30) {
         self.windowId = windowId
         self.width = width
         self.height = height
+        // 0 = derive from resolution, capped at 8 Mbps
+        self.bitrate = bitrate > 0 ? bitrate : min(width * height * 4, 8_000_000)
+        self.fps = max(fps, 1)
     }
     
     deinit {
@@ -84,7 +89,7 @@ public class H264Encoder {
         // Prepare to encode
         VTCompressionSessionPrepareToEncodeFrames(session)
         
-        print("H264Encoder started for window \(windowId) at \(width)x\(height)")
+        print("H264Encoder started for window \(windowId) at \(width)x\(height), \(bitrate) bps, \(fps) fps")
         return true
     }
     
@@ -96,8 +101,24 @@ public class H264Encoder {
         VTSessionSetProperty(session, key: kVTCompressionPropertyKey_ProfileLevel, 
                            value: kVTProfileLevel_H264_Baseline_AutoLevel)
         
-        // Bitrate: 4 Mbps (adjust based on resolution)
-        let bitrate = min(width * height * 4, 8_000_000) // Cap at 8 Mbps
+        // Bitrate, frame rate and keyframe interval
+        applyRateSettings(session)
+        
+        // No B-frames for lower latency
+        VTSessionSetProperty(session, key: kVTCompressionPropertyKey_AllowFrameReordering, 
+                           value: kCFBooleanFalse)
+        
+        // Allow temporal compression
+        VTSessionSetProperty(session, key: kVTCompressionPropertyKey_AllowTemporalCompression, 
+                           value: kCFBooleanTrue)
+        
+        // Hardware acceleration
+        VTSessionSetProperty(session, key: kVTCompressionPropertyKey_UsingHardwareAcceleratedVideoEncoder, 
+                           value: kCFBooleanTrue)
+    }
+    
+    /// Apply bitrate/FPS dependent properties (safe to call on a live session)
+    private func applyRateSettings(_ session: VTCompressionSession) {
         VTSessionSetProperty(session, key: kVTCompressionPropertyKey_AverageBitRate, 
                            value: bitrate as CFNumber)
         
@@ -108,25 +129,22 @@ public class H264Encoder {
         VTSessionSetProperty(session, key: kVTCompressionPropertyKey_DataRateLimits, 
                            value: dataRateLimits)
         
-        // Frame rate
         VTSessionSetProperty(session, key: kVTCompressionPropertyKey_ExpectedFrameRate, 
-                           value: 30 as CFNumber)
+                           value: fps as CFNumber)
         
-        // Keyframe interval: every 2 seconds at 30fps = 60 frames
+        // Keyframe interval: every 2 seconds
         VTSessionSetProperty(session, key: kVTCompressionPropertyKey_MaxKeyFrameInterval, 
-                           value: 60 as CFNumber)
-        
-        // No B-frames for lower latency
-        VTSessionSetProperty(session, key: kVTCompressionPropertyKey_AllowFrameReordering, 
-                           value: kCFBooleanFalse)
-        
-        // Allow temporal compression
-        VTSessionSetProperty(session, key: kVTCompressionPropertyKey_AllowTemporalCompression, 
-                           value: kCFBooleanTrue)
+                           value: (fps * 2) as CFNumber)
+    }
+    
+    /// Change bitrate and frame rate without recreating the session
+    public func updateRate(bitrate: Int, fps: Int) {
+        self.bitrate = bitrate > 0 ? bitrate : min(width * height * 4, 8_000_000)
+        self.fps = max(fps, 1)
         
-        // Hardware acceleration
-        VTSessionSetProperty(session, key: kVTCompressionPropertyKey_UsingHardwareAcceleratedVideoEncoder, 
-                           value: kCFBooleanTrue)
+        guard let session = compressionSession else { return }
+        applyRateSettings(session)
+        print("H264Encoder: Updated window \(windowId) to \(self.bitrate) bps, \(self.fps) fps")
     }
     
     /// Stop the encoder
@@ -147,7 +165,7 @@ public class H264Encoder {
             return
         }
         
-        let duration = CMTime(value: 1, timescale: 30) // Assume 30fps
+        let duration = CMTime(value: 1, timescale: CMTimeScale(fps))
         
         // Check if we need to force a keyframe
         var frameProperties: CFDictionary? = nil
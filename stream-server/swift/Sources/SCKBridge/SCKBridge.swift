@@ -7,6 +7,8 @@ import CoreGraphics
 import CoreMedia
 import AppKit
 import VideoToolbox
+import CoreImage
+import ImageIO
 
 // MARK: - FFI Types (must match Rust definitions)
 
@@ -26,6 +28,10 @@ public struct EncodedFrameFFI {
 @_silgen_name("rust_on_encoded_frame")
 func rustOnEncodedFrame(_ frame: UnsafePointer<EncodedFrameFFI>)
 
+/// Import the Rust capture error callback
+@_silgen_name("rust_on_capture_error")
+func rustOnCaptureError(_ windowId: UInt32, _ message: UnsafePointer<CChar>)
+
 // MARK: - App Initialization
 
 /// Initialize the app context for Window Server access
@@ -44,6 +50,31 @@ public func sck_initialize() -> Int32 {
     return 0
 }
 
+// MARK: - Completion Waiting
+
+/// Result of a completion handler that is waited for with a timeout
+/// The handler may still run after the wait timed out, so access is locked
+private final class CompletionResult<Value> {
+    private let lock = NSLock()
+    private var value: Value
+    
+    init(_ value: Value) {
+        self.value = value
+    }
+    
+    func set(_ newValue: Value) {
+        lock.lock()
+        defer { lock.unlock() }
+        value = newValue
+    }
+    
+    func get() -> Value {
+        lock.lock()
+        defer { lock.unlock() }
+        return value
+    }
+}
+
 // MARK: - Capture Session Manager
 
 /// Thread-safe manager for capture sessions
@@ -52,9 +83,24 @@ private final class CaptureManager {
     
     private let lock = NSLock()
     private var sessions: [UInt32: Any] = [:]
+    private var _idleFps: Int = 2
     
     private init() {}
     
+    /// Encode rate for windows whose content is not changing (0 = no throttling)
+    var idleFps: Int {
+        get {
+            lock.lock()
+            defer { lock.unlock() }
+            return _idleFps
+        }
+        set {
+            lock.lock()
+            defer { lock.unlock() }
+            _idleFps = newValue
+        }
+    }
+    
     @available(macOS 12.3, *)
     func getSession(_ windowId: UInt32) -> CaptureSession? {
         lock.lock()
@@ -93,6 +139,16 @@ private class StreamDelegate: NSObject, SCStreamDelegate {
     
     func stream(_ stream: SCStream, didStopWithError error: Error) {
         print("SCStream stopped with error for window \(windowId): \(error)")
+        
+        // Drop the dead session so a restart creates a fresh stream and encoder
+        if let session = CaptureManager.shared.removeSession(windowId) {
+            session.stopEncoder()
+        }
+        
+        // Notify Rust so it can restart the capture
+        error.localizedDescription.withCString { message in
+            rustOnCaptureError(windowId, message)
+        }
     }
 }
 
@@ -104,17 +160,42 @@ private class CaptureSession {
     var outputHandler: FrameOutputHandler?
     var streamDelegate: StreamDelegate?
     var encoder: H264Encoder?
-    let width: Int
-    let height: Int
-    
-    init(windowId: UInt32, width: Int, height: Int) {
+    /// Native window size in points
+    var sourceWidth: Int
+    var sourceHeight: Int
+    /// Bounds the output is scaled to fit within (0 = native size)
+    var maxWidth: Int
+    var maxHeight: Int
+    /// Output (encoded) size
+    var width: Int
+    var height: Int
+    var bitrate: Int
+    var fps: Int
+    
+    /// Most recent captured frame, kept for one-shot screenshots
+    private let frameLock = NSLock()
+    private var latestFrame: CVPixelBuffer?
+    
+    init(windowId: UInt32, sourceWidth: Int, sourceHeight: Int, maxWidth: Int, maxHeight: Int, bitrate: Int, fps: Int) {
         self.windowId = windowId
-        self.width = width
-        self.height = height
+        self.sourceWidth = sourceWidth
+        self.sourceHeight = sourceHeight
+        self.maxWidth = maxWidth
+        self.maxHeight = maxHeight
+        let fitted = fitDimensions(
+            width: sourceWidth,
+            height: sourceHeight,
+            maxWidth: maxWidth,
+            maxHeight: maxHeight
+        )
+        self.width = fitted.0
+        self.height = fitted.1
+        self.bitrate = bitrate
+        self.fps = fps
     }
     
     func startEncoder() {
-        let enc = H264Encoder(windowId: windowId, width: width, height: height)
+        let enc = H264Encoder(windowId: windowId, width: width, height: height, bitrate: bitrate, fps: fps)
         
         let success = enc.start { [weak self] windowId, timestampMs, isKeyframe, nalData, width, height in
             guard let _ = self else { return }
@@ -148,11 +229,23 @@ private class CaptureSession {
         encoder?.stop()
         encoder = nil
     }
+    
+    func storeLatestFrame(_ pixelBuffer: CVPixelBuffer) {
+        frameLock.lock()
+        defer { frameLock.unlock() }
+        latestFrame = pixelBuffer
+    }
+    
+    func copyLatestFrame() -> CVPixelBuffer? {
+        frameLock.lock()
+        defer { frameLock.unlock() }
+        return latestFrame
+    }
 }
 
 // MARK: - Window Enumeration
 
-/// Get count of available windows
+/// Get the available windows, frontmost first
 /// Returns: JSON string with window list, caller must free with sck_free_string
 @_cdecl("sck_get_windows_json")
 public func sck_get_windows_json() -> UnsafeMutablePointer<CChar>? {
@@ -184,13 +277,28 @@ public func sck_get_windows_json() -> UnsafeMutablePointer<CChar>? {
                 return window.frame.width > 100 && window.frame.height > 100
             }
             
+            // SCShareableContent is unordered; the CoreGraphics window list is front to back
+            var stackOrder: [CGWindowID: Int] = [:]
+            if let cgWindows = CGWindowListCopyWindowInfo(.optionOnScreenOnly, kCGNullWindowID) as? [[String: Any]] {
+                for (index, info) in cgWindows.enumerated() {
+                    if let number = info[kCGWindowNumber as String] as? CGWindowID {
+                        stackOrder[number] = index
+                    }
+                }
+            }
+            let ordered = windows.sorted {
+                (stackOrder[$0.windowID] ?? Int.max) < (stackOrder[$1.windowID] ?? Int.max)
+            }
+            
             // Convert to JSON
             var jsonArray: [[String: Any]] = []
-            for window in windows {
+            for window in ordered {
                 let dict: [String: Any] = [
                     "id": window.windowID,
                     "title": window.title ?? "",
                     "app": window.owningApplication?.applicationName ?? "",
+                    "bundle_id": window.owningApplication?.bundleIdentifier ?? "",
+                    "pid": window.owningApplication?.processID ?? 0,
                     "bounds": [
                         "x": window.frame.origin.x,
                         "y": window.frame.origin.y,
@@ -251,19 +359,60 @@ public func sck_get_window_count() -> Int32 {
 
 // MARK: - Screen Capture
 
+/// Fit a source size within max bounds, preserving aspect ratio
+/// A zero bound means native size; output is never upscaled and is rounded to even dimensions for H.264
+private func fitDimensions(width: Int, height: Int, maxWidth: Int, maxHeight: Int) -> (Int, Int) {
+    guard maxWidth > 0, maxHeight > 0, width > 0, height > 0 else {
+        return (width & ~1, height & ~1)
+    }
+    
+    let scale = min(Double(maxWidth) / Double(width), Double(maxHeight) / Double(height), 1.0)
+    let fittedWidth = Int(Double(width) * scale) & ~1
+    let fittedHeight = Int(Double(height) * scale) & ~1
+    return (max(fittedWidth, 2), max(fittedHeight, 2))
+}
+
+/// Build a stream configuration for the given output size and frame rate
+@available(macOS 12.3, *)
+private func makeStreamConfiguration(width: Int, height: Int, fps: Int) -> SCStreamConfiguration {
+    let config = SCStreamConfiguration()
+    config.width = width
+    config.height = height
+    config.minimumFrameInterval = CMTime(value: 1, timescale: CMTimeScale(max(fps, 1)))
+    config.queueDepth = 5
+    config.showsCursor = true
+    config.pixelFormat = kCVPixelFormatType_32BGRA
+    
+    // Capture options for better frame delivery
+    if #available(macOS 13.0, *) {
+        config.capturesAudio = false
+    }
+    
+    // Scale to fit the configured dimensions
+    config.scalesToFit = true
+    return config
+}
+
 /// Start capturing a specific window
+/// Output is scaled to fit within maxWidth x maxHeight (0 = native size)
 /// Returns: 0 on success, -1 on failure
 @_cdecl("sck_start_capture")
-public func sck_start_capture(windowId: UInt32) -> Int32 {
+public func sck_start_capture(windowId: UInt32, maxWidth: UInt32, maxHeight: UInt32, bitrate: UInt32, fps: UInt32) -> Int32 {
     guard #available(macOS 12.3, *) else {
         return -1
     }
     
-    return startCaptureImpl(windowId: windowId)
+    return startCaptureImpl(
+        windowId: windowId,
+        maxWidth: Int(maxWidth),
+        maxHeight: Int(maxHeight),
+        bitrate: Int(bitrate),
+        fps: Int(fps)
+    )
 }
 
 @available(macOS 12.3, *)
-private func startCaptureImpl(windowId: UInt32) -> Int32 {
+private func startCaptureImpl(windowId: UInt32, maxWidth: Int, maxHeight: Int, bitrate: Int, fps: Int) -> Int32 {
     let manager = CaptureManager.shared
     
     // Check if already capturing
@@ -288,31 +437,24 @@ private func startCaptureImpl(windowId: UInt32) -> Int32 {
             return
         }
         
-        let frameWidth = Int(window.frame.width)
-        let frameHeight = Int(window.frame.height)
+        // Create capture session with dimensions
+        let session = CaptureSession(
+            windowId: windowId,
+            sourceWidth: Int(window.frame.width),
+            sourceHeight: Int(window.frame.height),
+            maxWidth: maxWidth,
+            maxHeight: maxHeight,
+            bitrate: bitrate,
+            fps: fps
+        )
+        let frameWidth = session.width
+        let frameHeight = session.height
         
         // Create content filter for single window
         let filter = SCContentFilter(desktopIndependentWindow: window)
         
         // Configure stream
-        let config = SCStreamConfiguration()
-        config.width = frameWidth
-        config.height = frameHeight
-        config.minimumFrameInterval = CMTime(value: 1, timescale: 30) // 30 FPS for encoding
-        config.queueDepth = 5
-        config.showsCursor = true
-        config.pixelFormat = kCVPixelFormatType_32BGRA
-        
-        // Capture options for better frame delivery
-        if #available(macOS 13.0, *) {
-            config.capturesAudio = false
-        }
-        
-        // Scale to fit the configured dimensions
-        config.scalesToFit = true
-        
-        // Create capture session with dimensions
-        let session = CaptureSession(windowId: windowId, width: frameWidth, height: frameHeight)
+        let config = makeStreamConfiguration(width: frameWidth, height: frameHeight, fps: fps)
         
         // Start the H264 encoder
         session.startEncoder()
@@ -413,6 +555,231 @@ public func sck_request_keyframe(windowId: UInt32) -> Int32 {
     return 0
 }
 
+/// Apply a session's current size/rate settings to its running stream and encoder
+/// The encoder is only recreated if the output size changed
+/// Returns: 0 on success, -1 on failure
+@available(macOS 12.3, *)
+private func reconfigureSession(_ session: CaptureSession, stream: SCStream) -> Int32 {
+    let windowId = session.windowId
+    let (newWidth, newHeight) = fitDimensions(
+        width: session.sourceWidth,
+        height: session.sourceHeight,
+        maxWidth: session.maxWidth,
+        maxHeight: session.maxHeight
+    )
+    let sizeChanged = newWidth != session.width || newHeight != session.height
+    
+    if sizeChanged {
+        // Encoder dimensions are fixed at creation; a new encoder starts with a keyframe
+        session.stopEncoder()
+        session.width = newWidth
+        session.height = newHeight
+        session.startEncoder()
+    } else {
+        session.encoder?.updateRate(bitrate: session.bitrate, fps: session.fps)
+    }
+    
+    let config = makeStreamConfiguration(width: newWidth, height: newHeight, fps: session.fps)
+    let semaphore = DispatchSemaphore(value: 0)
+    let failure = CompletionResult<Error?>(nil)
+    
+    stream.updateConfiguration(config) { error in
+        failure.set(error)
+        semaphore.signal()
+    }
+    
+    guard semaphore.wait(timeout: .now() + 5.0) == .success else {
+        print("Timed out updating stream configuration for window \(windowId)")
+        return -1
+    }
+    if let error = failure.get() {
+        print("Failed to update stream configuration for window \(windowId): \(error)")
+        return -1
+    }
+    
+    print("Reconfigured window \(windowId): \(newWidth)x\(newHeight), \(session.bitrate) bps, \(session.fps) fps")
+    return 0
+}
+
+/// Change output size, bitrate and frame rate of a running capture
+/// The SCStream is reconfigured in place; the encoder is only recreated if the output size changes
+/// Returns: 0 on success, -1 on failure
+@_cdecl("sck_set_quality")
+public func sck_set_quality(windowId: UInt32, maxWidth: UInt32, maxHeight: UInt32, bitrate: UInt32, fps: UInt32) -> Int32 {
+    guard #available(macOS 12.3, *) else {
+        return -1
+    }
+    
+    let manager = CaptureManager.shared
+    guard let session = manager.getSession(windowId), let stream = session.stream else {
+        print("sck_set_quality: No session for window \(windowId)")
+        return -1
+    }
+    
+    session.maxWidth = Int(maxWidth)
+    session.maxHeight = Int(maxHeight)
+    session.bitrate = Int(bitrate)
+    session.fps = Int(fps)
+    
+    return reconfigureSession(session, stream: stream)
+}
+
+/// Update the native size of a captured window after it was resized
+/// Output is re-fitted to the session's max bounds, keeping the aspect ratio
+/// Returns: 0 on success, -1 on failure
+@_cdecl("sck_set_source_size")
+public func sck_set_source_size(windowId: UInt32, width: UInt32, height: UInt32) -> Int32 {
+    guard #available(macOS 12.3, *) else {
+        return -1
+    }
+    
+    let manager = CaptureManager.shared
+    guard let session = manager.getSession(windowId), let stream = session.stream else {
+        print("sck_set_source_size: No session for window \(windowId)")
+        return -1
+    }
+    
+    session.sourceWidth = Int(width)
+    session.sourceHeight = Int(height)
+    
+    return reconfigureSession(session, stream: stream)
+}
+
+/// Set the encode rate for windows whose content is not changing (0 = no throttling)
+/// Applies to all captures, including running ones
+@_cdecl("sck_set_idle_fps")
+public func sck_set_idle_fps(fps: UInt32) {
+    CaptureManager.shared.idleFps = Int(fps)
+}
+
+// MARK: - Screenshots
+
+/// Encode a CGImage as PNG
+private func encodePNG(_ image: CGImage) -> Data? {
+    let data = NSMutableData()
+    guard let destination = CGImageDestinationCreateWithData(data, "public.png" as CFString, 1, nil) else {
+        return nil
+    }
+    CGImageDestinationAddImage(destination, image, nil)
+    guard CGImageDestinationFinalize(destination) else {
+        return nil
+    }
+    return data as Data
+}
+
+/// Grab a single window image
+/// Uses the latest frame of an active capture if there is one, otherwise SCScreenshotManager (macOS 14+)
+@available(macOS 12.3, *)
+private func captureWindowImage(windowId: UInt32, maxWidth: Int, maxHeight: Int) -> CGImage? {
+    if let pixelBuffer = CaptureManager.shared.getSession(windowId)?.copyLatestFrame() {
+        let ciImage = CIImage(cvPixelBuffer: pixelBuffer)
+        return CIContext().createCGImage(ciImage, from: ciImage.extent)
+    }
+    
+    guard #available(macOS 14.0, *) else {
+        print("Screenshot of window \(windowId) requires an active capture before macOS 14")
+        return nil
+    }
+    
+    var image: CGImage?
+    let semaphore = DispatchSemaphore(value: 0)
+    
+    SCShareableContent.getExcludingDesktopWindows(true, onScreenWindowsOnly: true) { content, error in
+        guard let content = content, error == nil,
+              let window = content.windows.first(where: { $0.windowID == windowId }) else {
+            print("Window \(windowId) not found for screenshot")
+            semaphore.signal()
+            return
+        }
+        
+        let (width, height) = fitDimensions(
+            width: Int(window.frame.width),
+            height: Int(window.frame.height),
+            maxWidth: maxWidth,
+            maxHeight: maxHeight
+        )
+        let config = makeStreamConfiguration(width: width, height: height, fps: 1)
+        let filter = SCContentFilter(desktopIndependentWindow: window)
+        
+        SCScreenshotManager.captureImage(contentFilter: filter, configuration: config) { cgImage, error in
+            if let error = error {
+                print("Screenshot failed for window \(windowId): \(error)")
+            }
+            image = cgImage
+            semaphore.signal()
+        }
+    }
+    
+    _ = semaphore.wait(timeout: .now() + 5.0)
+    return image
+}
+
+/// Capture a one-shot PNG screenshot of a window
+/// Output is scaled to fit within maxWidth x maxHeight (0 = native size) when captured fresh
+/// Returns: malloc'd PNG bytes (length in outLen), caller must free with sck_free_buffer; nil on failure
+@_cdecl("sck_capture_screenshot_png")
+public func sck_capture_screenshot_png(windowId: UInt32, maxWidth: UInt32, maxHeight: UInt32, outLen: UnsafeMutablePointer<Int>) -> UnsafeMutablePointer<UInt8>? {
+    outLen.pointee = 0
+    
+    guard #available(macOS 12.3, *) else {
+        return nil
+    }
+    
+    guard let image = captureWindowImage(windowId: windowId, maxWidth: Int(maxWidth), maxHeight: Int(maxHeight)),
+          let png = encodePNG(image), !png.isEmpty else {
+        return nil
+    }
+    
+    let buffer = UnsafeMutablePointer<UInt8>.allocate(capacity: png.count)
+    png.copyBytes(to: buffer, count: png.count)
+    outLen.pointee = png.count
+    return buffer
+}
+
+/// Get the icon of a running application as a PNG of `size`x`size` pixels
+/// Returns: malloc'd PNG bytes (length in outLen), caller must free with sck_free_buffer; nil on failure
+@_cdecl("sck_get_app_icon_png")
+public func sck_get_app_icon_png(pid: Int32, size: UInt32, outLen: UnsafeMutablePointer<Int>) -> UnsafeMutablePointer<UInt8>? {
+    outLen.pointee = 0
+    
+    guard let icon = NSRunningApplication(processIdentifier: pid_t(pid))?.icon else {
+        return nil
+    }
+    
+    // Render at the requested size rather than whichever representation is largest
+    let side = Int(size)
+    var rect = CGRect(x: 0, y: 0, width: side, height: side)
+    guard let image = icon.cgImage(forProposedRect: &rect, context: nil, hints: nil),
+          let context = CGContext(
+              data: nil,
+              width: side,
+              height: side,
+              bitsPerComponent: 8,
+              bytesPerRow: 0,
+              space: CGColorSpaceCreateDeviceRGB(),
+              bitmapInfo: CGImageAlphaInfo.premultipliedLast.rawValue
+          ) else {
+        return nil
+    }
+    context.interpolationQuality = .high
+    context.draw(image, in: CGRect(x: 0, y: 0, width: side, height: side))
+    
+    guard let scaled = context.makeImage(), let png = encodePNG(scaled), !png.isEmpty else {
+        return nil
+    }
+    
+    let buffer = UnsafeMutablePointer<UInt8>.allocate(capacity: png.count)
+    png.copyBytes(to: buffer, count: png.count)
+    outLen.pointee = png.count
+    return buffer
+}
+
+/// Free a buffer returned by sck functions
+@_cdecl("sck_free_buffer")
+public func sck_free_buffer(_ ptr: UnsafeMutablePointer<UInt8>?) {
+    ptr?.deallocate()
+}
+
 /// Check if screen recording permission is granted
 @_cdecl("sck_has_permission")
 public func sck_has_permission() -> Int32 {
@@ -441,6 +808,8 @@ private class FrameOutputHandler: NSObject, SCStreamOutput {
     private var frameCount: UInt64 = 0
     private var validFrameCount: UInt64 = 0
     private var lastLogTime: Date = Date()
+    private let throttle = FrameThrottle()
+    private let changeDetector = FrameChangeDetector()
     
     init(windowId: UInt32, session: CaptureSession) {
         self.windowId = windowId
@@ -461,6 +830,13 @@ private class FrameOutputHandler: NSObject, SCStreamOutput {
         
         // Get pixel buffer - may be nil for some frames (e.g., no content change)
         guard let pixelBuffer = CMSampleBufferGetImageBuffer(sampleBuffer) else {
+            // Static content: catch up on a frame the throttle skipped
+            if throttle.shouldEncodeSkipped(
+                idleFps: CaptureManager.shared.idleFps,
+                now: ProcessInfo.processInfo.systemUptime
+            ), let latestFrame = session?.copyLatestFrame() {
+                session?.encoder?.encode(pixelBuffer: latestFrame, timestamp: CMSampleBufferGetPresentationTimeStamp(sampleBuffer))
+            }
             // Only log occasionally to avoid spam
             if frameCount <= 5 || frameCount % 100 == 0 {
                 // Check attachments for clues about why there's no pixel buffer
@@ -477,6 +853,20 @@ private class FrameOutputHandler: NSObject, SCStreamOutput {
         }
         
         validFrameCount += 1
+        session?.storeLatestFrame(pixelBuffer)
+        
+        // Encode static content at the idle rate only
+        let contentChanged = changeDetector.hasChanged(
+            dirtyFraction: frameDirtyFraction(sampleBuffer, pixelBuffer: pixelBuffer),
+            hash: { sampledFrameHash(pixelBuffer) }
+        )
+        guard throttle.shouldEncode(
+            contentChanged: contentChanged,
+            idleFps: CaptureManager.shared.idleFps,
+            now: ProcessInfo.processInfo.systemUptime
+        ) else {
+            return
+        }
         
         // Log every 30 valid frames or every 2 seconds
         let now = Date()
@@ -0,0 +1,65 @@
+import XCTest
+@testable import SCKBridge
+
+final class FrameThrottleTests: XCTestCase {
+    func testChangingContentIsNotThrottled() {
+        let throttle = FrameThrottle(idleDelay: 1.0)
+        for frame in 0..<120 {
+            XCTAssertTrue(throttle.shouldEncode(contentChanged: true, idleFps: 2, now: Double(frame) / 60.0))
+        }
+    }
+
+    func testStaticContentIsEncodedAtIdleRate() {
+        let throttle = FrameThrottle(idleDelay: 1.0)
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: true, idleFps: 2, now: 0.0))
+
+        // Full rate until the idle delay has passed
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: false, idleFps: 2, now: 0.5))
+
+        // Then one frame every half second
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: false, idleFps: 2, now: 1.0))
+        XCTAssertFalse(throttle.shouldEncode(contentChanged: false, idleFps: 2, now: 1.2))
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: false, idleFps: 2, now: 1.5))
+
+        // A change restores full rate
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: true, idleFps: 2, now: 1.6))
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: false, idleFps: 2, now: 1.7))
+    }
+
+    func testZeroIdleFpsDisablesThrottling() {
+        let throttle = FrameThrottle(idleDelay: 1.0)
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: true, idleFps: 0, now: 0.0))
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: false, idleFps: 0, now: 5.0))
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: false, idleFps: 0, now: 5.01))
+    }
+
+    func testSkippedFrameIsEncodedOnIdleTick() {
+        let throttle = FrameThrottle(idleDelay: 1.0)
+        XCTAssertFalse(throttle.shouldEncodeSkipped(idleFps: 2, now: 0.0))
+
+        XCTAssertTrue(throttle.shouldEncode(contentChanged: false, idleFps: 2, now: 2.0))
+        XCTAssertFalse(throttle.shouldEncode(contentChanged: false, idleFps: 2, now: 2.1))
+
+        XCTAssertFalse(throttle.shouldEncodeSkipped(idleFps: 2, now: 2.3))
+        XCTAssertTrue(throttle.shouldEncodeSkipped(idleFps: 2, now: 2.5))
+        XCTAssertFalse(throttle.shouldEncodeSkipped(idleFps: 2, now: 3.5))
+    }
+
+    func testSmallDirtyAreaIsNotAChange() {
+        let detector = FrameChangeDetector(minDirtyFraction: 0.01)
+        XCTAssertTrue(detector.hasChanged(dirtyFraction: 0.5, hash: { XCTFail("hashed"); return 0 }))
+        XCTAssertFalse(detector.hasChanged(dirtyFraction: 0.001, hash: { XCTFail("hashed"); return 0 }))
+        XCTAssertFalse(detector.hasChanged(dirtyFraction: 0.0, hash: { XCTFail("hashed"); return 0 }))
+    }
+
+    func testFramesWithoutDirtyRectsAreCompared() {
+        let detector = FrameChangeDetector()
+        XCTAssertTrue(detector.hasChanged(dirtyFraction: nil, hash: { 1 }))
+        XCTAssertFalse(detector.hasChanged(dirtyFraction: nil, hash: { 1 }))
+        XCTAssertTrue(detector.hasChanged(dirtyFraction: nil, hash: { 2 }))
+
+        // The content behind dirty rects is unknown, so the next hash is a change
+        XCTAssertFalse(detector.hasChanged(dirtyFraction: 0.0, hash: { 2 }))
+        XCTAssertTrue(detector.hasChanged(dirtyFraction: nil, hash: { 2 }))
+    }
+}
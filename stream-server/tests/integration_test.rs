@@ -9,8 +9,8 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
+use blink_protocol::{ClientMessage, IceCandidate, ServerMessage};
 use futures_util::{SinkExt, StreamExt};
-use serde::{Deserialize, Serialize};
 use tokio::net::TcpStream;
 use tokio::time::timeout;
 use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
@@ -31,56 +31,6 @@ const TEST_PORT: u16 = 19876;
 /// Timeout for operations
 const TIMEOUT_SECS: u64 = 10;
 
-/// ICE candidate structure matching server format
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct IceCandidate {
-    candidate: String,
-    #[serde(default)]
-    sdp_mid: Option<String>,
-    #[serde(default, alias = "sdpMLineIndex")]
-    sdp_m_line_index: Option<u16>,
-}
-
-/// Window bounds from server
-#[derive(Debug, Clone, Deserialize)]
-struct WindowBounds {
-    x: f64,
-    y: f64,
-    width: f64,
-    height: f64,
-}
-
-/// Window info from server
-#[derive(Debug, Clone, Deserialize)]
-struct WindowInfo {
-    id: u32,
-    title: String,
-    app: String,
-    bounds: WindowBounds,
-}
-
-/// Outgoing message types (client -> server)
-#[derive(Debug, Serialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-enum OutgoingMessage {
-    Offer { sdp: String },
-    Ice { candidate: IceCandidate },
-    Subscribe { window_ids: Vec<u32> },
-    GetWindows,
-}
-
-/// Incoming message types (server -> client)
-#[derive(Debug, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-enum IncomingMessage {
-    Answer { sdp: String },
-    Ice { candidate: IceCandidate },
-    WindowList { windows: Vec<WindowInfo> },
-    WindowClosed { id: u32 },
-    Error { message: String },
-}
-
 /// Test result tracking
 struct TestState {
     connected_to_ws: AtomicBool,
@@ -235,7 +185,7 @@ async fn connect_websocket(port: u16) -> Result<WebSocketStream<MaybeTlsStream<T
 /// Send a message over WebSocket
 async fn send_message(
     ws: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
-    msg: &OutgoingMessage,
+    msg: &ClientMessage,
 ) -> Result<()> {
     let json = serde_json::to_string(msg)?;
     ws.send(Message::Text(json)).await?;
@@ -245,10 +195,10 @@ async fn send_message(
 /// Receive and parse a message from WebSocket
 async fn receive_message(
     ws: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
-) -> Result<Option<IncomingMessage>> {
+) -> Result<Option<ServerMessage>> {
     match ws.next().await {
         Some(Ok(Message::Text(text))) => {
-            let msg: IncomingMessage = serde_json::from_str(&text)?;
+            let msg: ServerMessage = serde_json::from_str(&text)?;
             Ok(Some(msg))
         }
         Some(Ok(_)) => Ok(None), // Non-text message
@@ -292,7 +242,7 @@ async fn run_integration_test() -> Result<()> {
     // Step 2: Receive initial window list
     println!("[Step 2] Waiting for initial window list...");
     let windows = match timeout(Duration::from_secs(TIMEOUT_SECS), receive_message(&mut ws)).await {
-        Ok(Ok(Some(IncomingMessage::WindowList { windows }))) => {
+        Ok(Ok(Some(ServerMessage::WindowList { windows, .. }))) => {
             println!("  Received {} windows", windows.len());
             for w in &windows {
                 println!("    - [{}] {} ({})", w.id, w.title, w.app);
@@ -363,7 +313,7 @@ async fn run_integration_test() -> Result<()> {
     // Give ICE gathering a moment to start
     tokio::time::sleep(Duration::from_millis(100)).await;
 
-    send_message(&mut ws, &OutgoingMessage::Offer { sdp: offer.sdp.clone() }).await?;
+    send_message(&mut ws, &ClientMessage::Offer { sdp: offer.sdp.clone() }).await?;
     println!("  Offer sent to server");
 
     // Step 5: Wait for answer and exchange ICE candidates
@@ -382,7 +332,7 @@ async fn run_integration_test() -> Result<()> {
             // Send our ICE candidates
             Some(ice_candidate) = ice_rx.recv() => {
                 println!("  Sending ICE candidate to server");
-                if let Err(e) = send_message(&mut ws, &OutgoingMessage::Ice { candidate: ice_candidate }).await {
+                if let Err(e) = send_message(&mut ws, &ClientMessage::Ice { candidate: ice_candidate }).await {
                     println!("  Warning: Failed to send ICE candidate: {}", e);
                 }
             }
@@ -390,7 +340,7 @@ async fn run_integration_test() -> Result<()> {
             // Receive messages from server
             result = receive_message(&mut ws) => {
                 match result {
-                    Ok(Some(IncomingMessage::Answer { sdp })) => {
+                    Ok(Some(ServerMessage::Answer { sdp })) => {
                         println!("  Received SDP answer from server");
                         test_state.received_answer.store(true, Ordering::SeqCst);
                         
@@ -398,7 +348,7 @@ async fn run_integration_test() -> Result<()> {
                         peer_connection.set_remote_description(answer).await?;
                         println!("  Remote description set");
                     }
-                    Ok(Some(IncomingMessage::Ice { candidate })) => {
+                    Ok(Some(ServerMessage::Ice { candidate })) => {
                         println!("  Received ICE candidate from server");
                         let ice_init = RTCIceCandidateInit {
                             candidate: candidate.candidate,
@@ -411,7 +361,7 @@ async fn run_integration_test() -> Result<()> {
                         }
                         test_state.ice_candidates_exchanged.fetch_add(1, Ordering::SeqCst);
                     }
-                    Ok(Some(IncomingMessage::Error { message })) => {
+                    Ok(Some(ServerMessage::Error { message, .. })) => {
                         println!("  Server error: {}", message);
                     }
                     Ok(Some(msg)) => {
@@ -449,7 +399,7 @@ async fn run_integration_test() -> Result<()> {
         let window_id = selected.id;
         println!("  Selected: [{}] {} ({})", window_id, selected.title, selected.app);
         
-        send_message(&mut ws, &OutgoingMessage::Subscribe { window_ids: vec![window_id] }).await?;
+        send_message(&mut ws, &ClientMessage::Subscribe { window_ids: vec![window_id] }).await?;
         println!("  Subscribed to window");
         
         // Wait for subscription to be processed and potentially receive track